@@ -2,11 +2,24 @@ use piston_window::*;
 use piston_window::types::Color;
 
 use crate::game::SnakeNode;
-use crate::DEFAULT_BLOCK_SIZE;
-use crate::DEFAULT_GAME_DIMENSIONS;
 use crate::HUD_WIDTH;
 use crate::game::Game;
 use crate::ClientState;
+use crate::replay::Playback;
+use crate::protocol::LeaderboardEntry;
+use crate::SPECTATOR_ID;
+
+/// État du client affiché par le HUD
+pub struct HudView<'a> {
+    /// État du client de jeu
+    pub client_state: &'a ClientState,
+    /// Option de l'état de lecture, lors de la lecture d'un replay
+    pub playback: Option<&'a Playback>,
+    /// Identifiant du joueur
+    pub id: u8,
+    /// Association codant les joueurs encore en vie
+    pub alive_assoc: &'a [(u8, bool)],
+}
 
 /// Couleur du fond du HUD
 const BLACK_HUD: Color = [0.14, 0.14, 0.14, 0.8];
@@ -17,6 +30,9 @@ const TEXT_COLOR: Color = [0.96, 0.96, 0.96, 1.0];
 /// Couleur du texte pour les joueurs morts
 const DEAD_COLOR: Color = [0.94, 0.08, 0.08, 1.0];
 
+/// Couleur du fond de la frise chronologique d'un replay
+const TIMELINE_COLOR: Color = [0.4, 0.4, 0.4, 1.0];
+
 /// Taille en pixels de la police d'écriture
 const FONT_SIZE: u32 = 16;
 
@@ -65,7 +81,9 @@ pub fn draw_rectangle(c: &Context, g: &mut G2d, col: Color, x: i16, y: i16, h: i
     );
 }
 
-/// Dessine une section entre deux noeuds d'un serpent
+/// Dessine une section entre deux noeuds d'un serpent.
+/// Sur une map torique, les noeuds peuvent être en dehors de la map : la section est ramenée sur la map
+/// et coupée en plusieurs rectangles si elle traverse un bord.
 ///
 /// # Arguments
 ///
@@ -73,28 +91,43 @@ pub fn draw_rectangle(c: &Context, g: &mut G2d, col: Color, x: i16, y: i16, h: i
 /// * `g` - Une référence mutable vers l'objet graphique 2D
 /// * `col` - La couleur du serpent
 /// * `node1`, `node2` - Des références vers les noeuds du serpent
+/// * `(width, height)` - Dimensions de la map en blocs
 /// * `block_size` - Taille d'un bloc en pixels
-pub fn draw_section(c: &Context, g: &mut G2d, col: Color, node1: &SnakeNode, node2: &SnakeNode, block_size: u16) {
+pub fn draw_section(c: &Context, g: &mut G2d, col: Color, node1: &SnakeNode, node2: &SnakeNode, (width, height): (u16, u16), block_size: u16) {
     if node1.x != node2.x && node1.y != node2.y {
-        println!("Node {:?} and node {:?} are not aligned.", node1, node2);
-        panic!("");
-    } else {
-        if node1.x == node2.x {
-            if node1.y < node2.y {
-                draw_rectangle(c, g, col, node1.x, node1.y, 1 + node2.y - node1.y, 1, block_size);
-            } else {
-                draw_rectangle(c, g, col, node2.x, node2.y, 1 + node1.y - node2.y, 1, block_size);
-            }
-        } else {
-            if node1.x < node2.x {
-                draw_rectangle(c, g, col, node1.x, node1.y, 1, 1 + node2.x - node1.x, block_size);
-            } else {
-                draw_rectangle(c, g, col, node2.x, node2.y, 1, 1 + node1.x - node2.x, block_size);
-            }
+        // Noeuds non alignés (frame invalide) : la section est ignorée plutôt que d'interrompre la partie
+        return;
+    }
+
+    let x = std::cmp::min(node1.x, node2.x);
+    let y = std::cmp::min(node1.y, node2.y);
+    let w = 1 + (node1.x - node2.x).abs();
+    let h = 1 + (node1.y - node2.y).abs();
+    for (x, w) in wrap_interval(x, w, width as i16) {
+        for &(y, h) in &wrap_interval(y, h, height as i16) {
+            draw_rectangle(c, g, col, x, y, h, w, block_size);
         }
     }
 }
 
+/// Ramène un intervalle de blocs sur la map, en le coupant en deux s'il traverse un bord.
+/// Renvoie les intervalles (début, longueur) obtenus.
+///
+/// # Arguments
+///
+/// * `start` - Premier bloc de l'intervalle
+/// * `len` - Longueur de l'intervalle en blocs
+/// * `size` - Dimension de la map en blocs
+fn wrap_interval(start: i16, len: i16, size: i16) -> Vec<(i16, i16)> {
+    let start = start.rem_euclid(size);
+    let len = std::cmp::min(len, size);
+    if start + len <= size {
+        vec![(start, len)]
+    } else {
+        vec![(start, size - start), (0, start + len - size)]
+    }
+}
+
 /// Dessine une bordure de map d'épaisseur un bloc en partant de (0, 0)
 ///
 /// # Arguments
@@ -102,13 +135,71 @@ pub fn draw_section(c: &Context, g: &mut G2d, col: Color, node1: &SnakeNode, nod
 /// * `c` - Une référence vers le contexte de la fenêtre
 /// * `g` - Une référence mutable vers l'objet graphique 2D
 /// * `col` - La couleur de la bordure
-/// * `dimensions` - Dimensions de la map (carrée) en blocs
+/// * `width`, `height` - Dimensions de la map en blocs
+/// * `block_size` - Taille d'un bloc en pixels
+pub fn draw_borders(c: &Context, g: &mut G2d, col: Color, width: u16, height: u16, block_size: u16) {
+    draw_rectangle(c, g, col, 0, 0, height as i16, 1, block_size);
+    draw_rectangle(c, g, col, 0, 0, 1, width as i16, block_size);
+    draw_rectangle(c, g, col, width as i16 - 1, 0, height as i16, 1, block_size);
+    draw_rectangle(c, g, col, 0, height as i16 - 1, 1, width as i16, block_size);
+}
+
+/// Dessine les murs intérieurs de la map
+///
+/// # Arguments
+///
+/// * `c` - Une référence vers le contexte de la fenêtre
+/// * `g` - Une référence mutable vers l'objet graphique 2D
+/// * `col` - La couleur des murs
+/// * `walls` - Murs en segments horizontaux (x, y, longueur) en blocs
 /// * `block_size` - Taille d'un bloc en pixels
-pub fn draw_borders(c: &Context, g: &mut G2d, col: Color, dimensions: u16, block_size: u16) {
-    draw_rectangle(c, g, col, 0, 0, dimensions as i16, 1, block_size);
-    draw_rectangle(c, g, col, 0, 0, 1, dimensions as i16, block_size);
-    draw_rectangle(c, g, col, dimensions as i16 - 1, 0, dimensions as i16, 1, block_size);
-    draw_rectangle(c, g, col, 0, dimensions as i16 - 1, 1, dimensions as i16, block_size);
+pub fn draw_walls(c: &Context, g: &mut G2d, col: Color, walls: &[(i16, i16, i16)], block_size: u16) {
+    for &(x, y, len) in walls {
+        draw_rectangle(c, g, col, x, y, 1, len, block_size);
+    }
+}
+
+/// Dessine le classement des joueurs sur la map, pendant l'attente d'une partie
+///
+/// # Arguments
+///
+/// * `c` - Une référence vers le contexte de la fenêtre
+/// * `g` - Une référence mutable vers l'objet graphique 2D
+/// * `glyphs` - Une référence mutable vers le cache Glyph pour la police d'écriture
+/// * `leaderboard` - Une slice vers les premiers joueurs du classement
+pub fn draw_leaderboard(c: &Context, g: &mut G2d, glyphs: &mut Glyphs, leaderboard: &[LeaderboardEntry]) {
+    let mut lines: Vec<String> = vec!["Classement".to_string()];
+    if leaderboard.is_empty() {
+        lines.push("Aucune partie jouée".to_string());
+    }
+    for (rank, entry) in leaderboard.iter().enumerate() {
+        lines.push(format!(
+            "{}. {}  -  {} V / {} D, {} tué(s), longueur {}",
+            rank + 1, entry.identity, entry.wins, entry.losses, entry.kills, entry.max_length,
+        ));
+    }
+
+    draw_text_lines(c, g, glyphs, &lines);
+}
+
+/// Dessine des lignes de texte en haut à gauche de la map
+///
+/// # Arguments
+///
+/// * `c` - Une référence vers le contexte de la fenêtre
+/// * `g` - Une référence mutable vers l'objet graphique 2D
+/// * `glyphs` - Une référence mutable vers le cache Glyph pour la police d'écriture
+/// * `lines` - Une slice vers les lignes à afficher
+pub fn draw_text_lines(c: &Context, g: &mut G2d, glyphs: &mut Glyphs, lines: &[String]) {
+    for (line, text_line) in lines.iter().enumerate() {
+        text::Text::new_color(TEXT_COLOR, FONT_SIZE).draw(
+            text_line,
+            glyphs,
+            &c.draw_state,
+            c.transform.trans(25.0, 40.0 + line as f64 * LINE_HEIGHT),
+            g,
+        ).unwrap();
+    }
 }
 
 /// Dessine le HUD
@@ -119,20 +210,22 @@ pub fn draw_borders(c: &Context, g: &mut G2d, col: Color, dimensions: u16, block
 /// * `g` - Une référence mutable vers l'objet graphique 2D
 /// * `glyphs` - Une référence mutable vers le cache Glyph pour la police d'écriture
 /// * `game` - Une référence mutable vers le jeu
-/// * `client_state` - Une référence vers l'état du client de jeu
 /// * `address` - Une slice vers l'adresse IP du serveur
-/// * `id` - L'identifiant du joueur
-/// * `alive_assoc` - Une référence vers un vecteur d'association codant les joueurs encore en vie
-pub fn draw_hud(c: &Context, g: &mut G2d, glyphs: &mut Glyphs, game: &mut Game, client_state: &ClientState, address: &str, id: u8, alive_assoc: &Vec<(u8, bool)>){
-    let window_size = DEFAULT_GAME_DIMENSIONS;
+/// * `view` - Une référence vers l'état du client affiché par le HUD
+pub fn draw_hud(c: &Context, g: &mut G2d, glyphs: &mut Glyphs, game: &mut Game, address: &str, view: &HudView){
+    let &HudView { client_state, playback, id, alive_assoc } = view;
+
+    // Le HUD occupe la droite de la fenêtre, dont la taille dépend de la map
+    let [view_width, view_height] = c.get_view_size();
+    let hud_x = view_width as u16 - HUD_WIDTH;
 
     draw_rectangle_raw(
         c,
         g,
         BLACK_HUD,
-        (window_size * DEFAULT_BLOCK_SIZE) as i16,
+        hud_x as i16,
         0,
-        (window_size * DEFAULT_BLOCK_SIZE) as i16,
+        view_height as i16,
         HUD_WIDTH as i16,
     );
 
@@ -142,33 +235,44 @@ pub fn draw_hud(c: &Context, g: &mut G2d, glyphs: &mut Glyphs, game: &mut Game,
         address,
         glyphs,
         &c.draw_state,
-        c.transform.trans((window_size * DEFAULT_BLOCK_SIZE + 15) as f64, 25.0 + line as f64 * LINE_HEIGHT),
+        c.transform.trans((hud_x + 15) as f64, 25.0 + line as f64 * LINE_HEIGHT),
         g,
     ).unwrap();
     line += 1;
 
     // Affichage du statut
-    match client_state {
-        ClientState::OnGoing | ClientState::Waiting =>{
+    match (client_state, playback) {
+        (_, Some(playback)) => {
+            text::Text::new_color(TEXT_COLOR, FONT_SIZE).draw(
+                if playback.paused { "Replay (pause)" } else { "Replay" },
+                glyphs,
+                &c.draw_state,
+                c.transform.trans((hud_x + 15) as f64, 25.0 + line as f64 * LINE_HEIGHT),
+                g,
+            ).unwrap();
+            line += 1;
+        }
+        (ClientState::OnGoing, None) | (ClientState::Waiting(_), None) =>{
             text::Text::new_color(TEXT_COLOR, FONT_SIZE).draw(
                 match client_state {
+                    ClientState::OnGoing if id == SPECTATOR_ID => "Spectateur",
                     ClientState::OnGoing => "Connecté",
-                    ClientState::Waiting => "En attente de joueurs",
+                    ClientState::Waiting(_) => "En attente de joueurs",
                     _ => "ERREUR"
                 },
                 glyphs,
                 &c.draw_state,
-                c.transform.trans((window_size * DEFAULT_BLOCK_SIZE + 15) as f64, 25.0 + line as f64 * LINE_HEIGHT),
+                c.transform.trans((hud_x + 15) as f64, 25.0 + line as f64 * LINE_HEIGHT),
                 g,
             ).unwrap();
             line += 1;
         }
-        ClientState::EndOfGame => {
+        (ClientState::EndOfGame, None) => {
             text::Text::new_color(TEXT_COLOR, FONT_SIZE).draw(
                 "Partie terminée",
                 glyphs,
                 &c.draw_state,
-                c.transform.trans((window_size * DEFAULT_BLOCK_SIZE + 15) as f64, 25.0 + line as f64 * LINE_HEIGHT),
+                c.transform.trans((hud_x + 15) as f64, 25.0 + line as f64 * LINE_HEIGHT),
                 g,
             ).unwrap();
             line += 1;
@@ -176,21 +280,68 @@ pub fn draw_hud(c: &Context, g: &mut G2d, glyphs: &mut Glyphs, game: &mut Game,
                 "[R]: Rejouer",
                 glyphs,
                 &c.draw_state,
-                c.transform.trans((window_size * DEFAULT_BLOCK_SIZE + 15) as f64, 25.0 + line as f64 * LINE_HEIGHT),
+                c.transform.trans((hud_x + 15) as f64, 25.0 + line as f64 * LINE_HEIGHT),
                 g,
             ).unwrap();
             line += 1;
         }
     }
     
-    // Affichage des joueurs et leur couleur
+    // Affichage de l'état de la file d'attente
+    if let ClientState::Waiting(Some(queue_status)) = client_state {
+        let lines: Vec<String> = vec![
+            format!("Position : {} sur {}", queue_status.position, queue_status.n_waiting),
+            format!("Joueurs : {} à {}", queue_status.min_players, queue_status.max_players),
+            match queue_status.time_left {
+                Some(time_left) => format!("Début dans {} s", time_left),
+                None => format!("Il manque {} joueur(s)", queue_status.min_players.saturating_sub(queue_status.n_waiting)),
+            },
+        ];
+        for queue_line in lines {
+            text::Text::new_color(TEXT_COLOR, FONT_SIZE).draw(
+                &queue_line,
+                glyphs,
+                &c.draw_state,
+                c.transform.trans((hud_x + 15) as f64, 25.0 + line as f64 * LINE_HEIGHT),
+                g,
+            ).unwrap();
+            line += 1;
+        }
+    }
+
+    // Affichage de l'état de la lecture d'un replay : tick, vitesse et frise chronologique
+    if let Some(playback) = playback {
+        let lines: Vec<String> = vec![
+            format!("Tick {} / {}", playback.tick + 1, playback.n_ticks),
+            format!("Vitesse x{}", playback.speed),
+        ];
+        for replay_line in lines {
+            text::Text::new_color(TEXT_COLOR, FONT_SIZE).draw(
+                &replay_line,
+                glyphs,
+                &c.draw_state,
+                c.transform.trans((hud_x + 15) as f64, 25.0 + line as f64 * LINE_HEIGHT),
+                g,
+            ).unwrap();
+            line += 1;
+        }
+
+        let timeline_width = HUD_WIDTH - 30;
+        let progress = (playback.tick + 1) as f64 / playback.n_ticks as f64;
+        draw_rectangle_raw(c, g, TIMELINE_COLOR, (hud_x + 15) as i16, 15 + line * LINE_HEIGHT as i16, 6, timeline_width as i16);
+        draw_rectangle_raw(c, g, TEXT_COLOR, (hud_x + 15) as i16, 15 + line * LINE_HEIGHT as i16, 6, (timeline_width as f64 * progress) as i16);
+        line += 1;
+    }
+
+    // Affichage des joueurs et leur couleur (plus serrés s'ils sont nombreux)
+    let line_step: i16 = if game.players.len() > 8 { 1 } else { 2 };
     let mut line_number = line;
     for snake in &game.players {
         draw_rectangle_raw(
             &c,
             g,
             TEXT_COLOR,
-            (window_size * DEFAULT_BLOCK_SIZE + 25) as i16,
+            (hud_x + 25) as i16,
             25 + (LINE_HEIGHT as i16) * (2 + line_number),
             LINE_HEIGHT as i16,
             LINE_HEIGHT as i16,
@@ -199,13 +350,13 @@ pub fn draw_hud(c: &Context, g: &mut G2d, glyphs: &mut Glyphs, game: &mut Game,
             &c,
             g,
             snake.color,
-            (window_size * DEFAULT_BLOCK_SIZE + 25 + 1) as i16,
+            (hud_x + 25 + 1) as i16,
             25 + (LINE_HEIGHT as i16) * (2 + line_number) + 1,
             (LINE_HEIGHT - 2.0) as i16,
             (LINE_HEIGHT - 2.0) as i16,
         );
         
-        let player_text: String = if snake.id == id { format!("Joueur {}  (vous)", snake.id) } else { format!("Joueur {}", snake.id) };
+        let player_text: String = if snake.id == id { format!("{}  (vous)", snake.name) } else { snake.name.clone() };
         let mut player_text_color: Color = DEAD_COLOR;
         for (current_id, _) in alive_assoc {
             if *current_id == snake.id {
@@ -219,11 +370,11 @@ pub fn draw_hud(c: &Context, g: &mut G2d, glyphs: &mut Glyphs, game: &mut Game,
             glyphs,
             &c.draw_state,
             c.transform.trans(
-                (window_size * DEFAULT_BLOCK_SIZE + 20) as f64 + LINE_HEIGHT * 1.8,
+                (hud_x + 20) as f64 + LINE_HEIGHT * 1.8,
                 25.0 + LINE_HEIGHT * (2.75 + (line_number as f64)),
             ),
             g,
         ).unwrap();
-        line_number += 2;
+        line_number += line_step;
     }
 }
@@ -1,4 +1,4 @@
-use std::ops::DerefMut;
+use std::collections::VecDeque;
 
 use piston_window::types::Color;
 use piston_window::Context;
@@ -7,14 +7,14 @@ use piston_window::Key;
 
 use crate::draw::*;
 
-/// Différents mouvements
-#[derive(PartialEq)]
-pub enum Move {
-    Up,
-    Down,
-    Left,
-    Right,
-}
+/// Couleur des murs intérieurs de la map
+const WALL_COLOR: Color = [0.55, 0.55, 0.55, 1.0];
+
+/// Opacité d'un serpent invisible, dessiné seulement pour son joueur et les spectateurs
+const INVISIBLE_ALPHA: f32 = 0.35;
+
+pub use snecc_protocol::Move;
+use snecc_protocol::PlayerParams;
 
 /// Différents types de nourriture
 pub enum FoodType {
@@ -22,26 +22,27 @@ pub enum FoodType {
     Apple,
     /// [2] Mangue
     Mango,
-    /// Autres
-    Unknown,
+    /// [3] Invisibilité
+    Invisibility,
 }
 
-/// Noeuds du serpent (liste chaînée)
-#[derive(Debug, Clone, PartialEq)]
+/// Noeud du serpent : extrémité ou coin du corps
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub struct SnakeNode {
     pub x: i16,
     pub y: i16,
-    pub next_node: Option<Box<SnakeNode>>,
 }
 
 /// Structure de serpent
 pub struct Snake {
     /// Identifiant unique du serpent au sein de la partie
     pub id: u8,
+    /// Pseudo du joueur
+    pub name: String,
     /// Couleur du serpent
     pub color: Color,
-    /// Tête du serpent (premier noeud de la liste chaînée)
-    pub head: SnakeNode,
+    /// Noeuds du serpent, de la tête (devant) à la queue (derrière) ; vide si le serpent est caché
+    pub body: VecDeque<SnakeNode>,
     /// Direction (vérifiée) du serpent
     pub direction: Move,
     /// Intention de mouvement du serpent
@@ -53,6 +54,8 @@ pub struct Snake {
     pub has_lost: bool,
     /// Nombre d'unités dans l'estomac du serpent
     pub stomach: u8,
+    /// Booléen codant si le serpent est invisible pour ses adversaires
+    pub invisible: bool,
 }
 
 /// Structure de nourriture
@@ -64,8 +67,14 @@ pub struct Food {
 
 /// Structure de partie
 pub struct Game {
-    /// Taille en blocs de la map
-    pub dimensions: u16,
+    /// Largeur en blocs de la map
+    pub width: u16,
+    /// Hauteur en blocs de la map
+    pub height: u16,
+    /// Map torique : pas de bordure, les bords opposés se rejoignent
+    pub wrap: bool,
+    /// Murs intérieurs de la map, en segments horizontaux (x, y, longueur)
+    pub walls: Vec<(i16, i16, i16)>,
     /// Taille en pixels d'un bloc
     pub block_size: u16,
     /// Couleur de la bordure de la map
@@ -84,17 +93,13 @@ pub struct Game {
 
 /// Implémentation d'un noeud de serpent
 impl SnakeNode {
-    /// Renvoie un nouveau noeud sans successeur
+    /// Renvoie un nouveau noeud
     ///
     /// # Arguments
     ///
     /// * `x`, `y` - Coordonnées du noeud
     pub fn new(x: i16, y: i16) -> Self {
-        SnakeNode {
-            x,
-            y,
-            next_node: None,
-        }
+        SnakeNode { x, y }
     }
 }
 
@@ -110,32 +115,26 @@ impl Snake {
     /// * `initial_direction` - Direction initiale du serpent
     /// * `initial_moving` - Intention initiale de mouvement du serpent (devrait être égale à `initial_direction`)
     pub fn new(id: u8, color: (u8, u8, u8), x: i16, y: i16, initial_direction: Move, initial_moving: Move) -> Self {
-        let mut head: SnakeNode = SnakeNode::new(x, y);
-        let tail: SnakeNode = SnakeNode::new(x, y);
-        head.next_node = Some(Box::new(tail));
+        let mut body: VecDeque<SnakeNode> = VecDeque::new();
+        body.push_back(SnakeNode::new(x, y));
+        body.push_back(SnakeNode::new(x, y));
         let color: Color = [(color.0 as f32)/256.0, (color.1 as f32)/256.0, (color.2 as f32)/256.0, 1.0];
         Snake {
             id,
+            name: format!("Joueur {}", id),
             color,
-            head,
+            body,
             direction: initial_direction,
             moving: initial_moving,
             has_lost: false,
             stomach: 10,
+            invisible: false,
         }
     }
     
-    /// Renvoie une référence mutable vers la queue du serpent
-    fn get_tail(&mut self) -> &mut SnakeNode {
-        let mut node: &mut SnakeNode = &mut self.head;
-        loop {
-            match node.next_node {
-                None => return node,
-                Some(ref mut n) => {
-                    node = n.deref_mut();
-                }
-            }
-        }
+    /// Renvoie la tête du serpent (premier noeud), ou None si le serpent est caché
+    pub fn head(&self) -> Option<&SnakeNode> {
+        self.body.front()
     }
     
     /// Dessine le serpent
@@ -144,58 +143,50 @@ impl Snake {
     ///
     /// * `c` - Référence vers le contexte de la fenêtre
     /// * `g` - Référence mutable vers l'objet graphique 2D
+    /// * `width`, `height` - Dimensions de la map en blocs
     /// * `block_size` - Taille d'un bloc en pixels
-    pub fn draw(&self, c: &Context, g: &mut G2d, block_size: u16) {
-        // Dessine le serpent
-        let mut snake_node: &SnakeNode = &self.head;
-        let mut prev_node: &SnakeNode = &self.head;
-        draw_section(c, g, self.color, snake_node, prev_node, block_size);
-        loop {
-            match snake_node.next_node {
-                None => {
-                    break;
-                }
-                Some(ref snake) => {
-                    prev_node = snake_node;
-                    snake_node = snake;
-                    draw_section(c, g, self.color, snake_node, prev_node, block_size);
-                }
-            }
+    pub fn draw(&self, c: &Context, g: &mut G2d, width: u16, height: u16, block_size: u16) {
+        // Dessine le serpent, semi-transparent s'il est invisible
+        let head = match self.head() {
+            Some(head) => head,
+            None => return,
+        };
+        let mut color = self.color;
+        if self.invisible {
+            color[3] = INVISIBLE_ALPHA;
+        }
+        draw_section(c, g, color, head, head, (width, height), block_size);
+        for (prev_node, snake_node) in self.body.iter().zip(self.body.iter().skip(1)) {
+            draw_section(c, g, color, snake_node, prev_node, (width, height), block_size);
         }
     }
 
-    /// Réinitialise un serpent à partir d'un noeud.
-    /// Utile pour reconstruire le serpent lors de la réception d'une image. 
-    ///
-    /// # Arguments
-    ///
-    /// * `node` - Le nouveau noeud
-    pub fn reset_snake_with_node(&mut self, node: SnakeNode) {
-        self.head = node;
-    }
-
-    /// Ajoute un nouveau noeud en tant que queue
+    /// Remplace les noeuds du serpent.
+    /// Utile pour reconstruire le serpent lors de la réception d'une image ;
+    /// un serpent sans noeud est caché (serpent invisible d'un adversaire).
     ///
     /// # Arguments
     ///
-    /// * `node` - Le nouveau noeud
-    pub fn add_node_as_tail(&mut self, node: SnakeNode) {
-        self.get_tail().next_node = Some(Box::new(node));
+    /// * `nodes` - Slice vers les coordonnées des noeuds, de la tête à la queue
+    pub fn set_body(&mut self, nodes: &[(i16, i16)]) {
+        self.body.clear();
+        self.body.extend(nodes.iter().map(|&(x, y)| SnakeNode::new(x, y)));
     }
 }
 
 /// Implémentation d'une nourriture
 impl Food {
-    /// Renvoie un objet FoodType à partir de l'identifiant numérique
+    /// Renvoie un objet FoodType à partir de l'identifiant numérique, ou None s'il est inconnu
     ///
     /// # Arguments
     ///
     /// * `i` - L'identifiant de la nourriture
-    pub fn int_to_food_type(i: u8) -> FoodType {
+    pub fn int_to_food_type(i: u8) -> Option<FoodType> {
         match i {
-            1 => FoodType::Apple,
-            2 => FoodType::Mango,
-            _ => FoodType::Unknown,
+            1 => Some(FoodType::Apple),
+            2 => Some(FoodType::Mango),
+            3 => Some(FoodType::Invisibility),
+            _ => None,
         }
     }
 
@@ -204,7 +195,7 @@ impl Food {
         match &self.food_type {
             FoodType::Apple => [1.00, 0.00, 0.00, 1.0],
             FoodType::Mango => [0.88, 0.65, 0.04, 1.0],
-            FoodType::Unknown => panic!("Trying to convert unknown food type"),
+            FoodType::Invisibility => [0.75, 0.90, 1.00, 0.6],
         }
     }
 
@@ -226,11 +217,14 @@ impl Game {
     ///
     /// # Arguments
     ///
-    /// * `dimensions` - Taille de la map en blocs
+    /// * `width`, `height` - Dimensions de la map en blocs
     /// * `frequency` - Fréquence de mouvement
-    pub fn new(dimensions: u16, frequency: u8) -> Self {
+    pub fn new(width: u16, height: u16, frequency: u8) -> Self {
         Game {
-            dimensions,
+            width,
+            height,
+            wrap: false,
+            walls: vec![],
             food: vec![],
             block_size: 0,
             n_players: 0,
@@ -260,11 +254,14 @@ impl Game {
     /// * `g` - Référence mutable vers l'objet graphique 2D
     /// * `id` - Identifiant du joueur
     pub fn draw_game(&self, c: &Context, g: &mut G2d, id: u8) {
-        draw_borders(c, g, self.border_color, self.dimensions, self.block_size);
+        if !self.wrap {
+            draw_borders(c, g, self.border_color, self.width, self.height, self.block_size);
+        }
+        draw_walls(c, g, WALL_COLOR, &self.walls, self.block_size);
         for snake in &self.players {
-            snake.draw(c, g, self.block_size);
-            if id == snake.id {
-                draw_rectangle(c, g, [1.0, 1.0, 1.0,1.0], snake.head.x, snake.head.y, 1, 1, self.block_size);
+            snake.draw(c, g, self.width, self.height, self.block_size);
+            if let Some(head) = snake.head().filter(|_| id == snake.id) {
+                draw_rectangle(c, g, [1.0, 1.0, 1.0,1.0], head.x, head.y, 1, 1, self.block_size);
             }
         }
         for food in &self.food {
@@ -307,20 +304,16 @@ impl Game {
         None
     }
 
-    /// Initialise les joueurs de la partie
+    /// Initialise les joueurs de la partie, en remplaçant les joueurs existants
     ///
     /// # Arguments
     ///
-    /// * `player_params` - Vecteur des paramètres des joueurs (identifiant, RGB, coordonnées)
-    pub fn init_players(&mut self, player_params: Vec<(u8, u8, u8, u8, i16, i16)>) {
-        for (id, red, green, blue, x, y) in player_params {
-            let (initial_direction, initial_moving): (Move, Move) = match (x > (self.dimensions as i16) / 2, y > (self.dimensions as i16) / 2) {
-                (false, false) => (Move::Down, Move::Down),
-                (false, true) => (Move::Right, Move::Right),
-                (true, false) => (Move::Left, Move::Left),
-                (true, true) => (Move::Up, Move::Up),
-            };
-            let snake = Snake::new(id, (red, green, blue), x, y, initial_direction, initial_moving);
+    /// * `player_params` - Vecteur des paramètres des joueurs (identifiant, couleur, coordonnées, direction initiale, pseudo)
+    pub fn init_players(&mut self, player_params: Vec<PlayerParams>) {
+        self.players.clear();
+        for player in player_params {
+            let mut snake = Snake::new(player.id, player.color, player.x, player.y, player.direction, player.direction);
+            snake.name = player.name;
             self.players.push(snake);
         }
     }
@@ -3,9 +3,11 @@ extern crate piston_window;
 use piston_window::*;
 use piston_window::types::Color;
 
-use std::net::TcpStream;
+use std::net::{SocketAddr, TcpStream};
 use std::{thread::sleep, time};
 use std::env;
+use std::fs;
+use std::io;
 use std::process::exit;
 
 mod draw;
@@ -17,6 +19,9 @@ use crate::draw::*;
 
 mod protocol;
 
+mod replay;
+use replay::{Playback, Replay};
+
 /// Couleur du fond
 const BLACK: Color = [0.2, 0.2, 0.2, 0.8];
 
@@ -29,26 +34,73 @@ const UPDATE_PERIOD: time::Duration = time::Duration::from_millis(5);
 /// Largeur en pixels du HUD à droite de l'écran
 const HUD_WIDTH: u16 = 250;
 
-/// Dimensions par défaut de la map (en blocs). Cette donnée doit être en phase avec le serveur.
+/// Identifiant attribué par le serveur aux spectateurs
+pub const SPECTATOR_ID: u8 = 0;
+
+/// Dimensions par défaut de la map (en blocs), utilisées pour la fenêtre avant de recevoir celles du serveur
 const DEFAULT_GAME_DIMENSIONS: u16 = 64;
 
-/// Taille en pixels par défaut d'un bloc. Cette donnée doit être en phase avec le serveur.
+/// Taille en pixels par défaut d'un bloc, utilisée pour la fenêtre avant de recevoir celle du serveur
 const DEFAULT_BLOCK_SIZE: u16 = 10;
 
+/// Fichier des réglages du client (pseudo), lu dans le dossier courant s'il existe
+const SETTINGS_FILE: &str = "settings.toml";
+
+/// Nombre de joueurs du classement affichés en attendant une partie
+const LEADERBOARD_SIZE: u8 = 10;
+
+/// Délai maximal d'attente de la réponse du serveur à une demande du classement
+const LEADERBOARD_TIMEOUT: time::Duration = time::Duration::from_secs(2);
+
+/// Délai maximal d'attente de la réponse du serveur à la négociation de version
+const HANDSHAKE_TIMEOUT: time::Duration = time::Duration::from_secs(5);
+
 /// Différents états d'un client
 pub enum ClientState {
-    Waiting,
+    /// En attente de joueurs, avec le dernier état connu de la file d'attente
+    Waiting(Option<protocol::QueueStatus>),
     OnGoing,
     EndOfGame,
 }
 
+/// Différentes issues d'une connexion au serveur
+pub enum ConnectionEnd {
+    /// Le joueur quitte le jeu
+    Quit,
+    /// Le joueur veut relancer une recherche de partie
+    Replay,
+    /// La connexion a été perdue en cours de partie ; contient le jeton de session pour se reconnecter
+    Lost(u64),
+}
+
 fn main() {
-    if env::args().len() != 3 {
-        println!("Usage: ./snake-client ip port");
+    let args: Vec<String> = env::args().collect();
+    if args.len() == 3 && args[1] == "--replay" {
+        view_replay(&args[2]);
         exit(0);
     }
+    if args.len() < 3 {
+        print_usage();
+    }
+
+    // Options : salon à regarder et pseudo (qui remplace celui du fichier de réglages)
+    let options = args[3..].chunks_exact(2);
+    if !options.remainder().is_empty() {
+        print_usage();
+    }
+    let mut spectate: Option<u32> = None;
+    let mut nickname: Option<String> = load_nickname();
+    for option in options {
+        match option[0].as_str() {
+            "--spectate" => match option[1].parse::<u32>() {
+                Ok(room_id) => spectate = Some(room_id),
+                Err(_) => print_usage(),
+            },
+            "--name" => nickname = Some(option[1].clone()),
+            _ => print_usage(),
+        }
+    }
 
-    let args: Vec<String> = env::args().collect();
     let ip_addr: &String = &args[1];
     let port: u16 = args[2].parse::<u16>().unwrap();
 
@@ -60,6 +112,8 @@ fn main() {
         .unwrap();
 
     let mut retry: bool = true;
+    // Jeton de session de la partie en cours, si la connexion a été perdue
+    let mut session: Option<u64> = None;
 
     while retry {
         let mut timeout: u8 = 0;
@@ -68,7 +122,19 @@ fn main() {
         loop {
             if let Ok(mut stream) = TcpStream::connect(format!("{}:{}", ip_addr, port)) {
                 println!("Connected to the server");
-                retry = handle_connection(&mut stream, window);
+                match handle_connection(&mut stream, window, session, spectate, nickname.as_deref()) {
+                    ConnectionEnd::Quit => {
+                        retry = false;
+                    }
+                    ConnectionEnd::Replay => {
+                        session = None;
+                    }
+                    ConnectionEnd::Lost(token) => {
+                        println!("Connection lost, reconnecting...");
+                        session = Some(token);
+                        break;
+                    }
+                }
                 if retry {
                     let mut end_retry = false;
                     while let Some(event) = window.next() {
@@ -87,7 +153,9 @@ fn main() {
                 sleep(time::Duration::from_millis(3000 as u64));
                 timeout += 1;
                 if timeout > 5 {
+                    // Le délai de reconnexion du serveur est dépassé : le jeton de session ne sert plus
                     println!("Couldn't connect.");
+                    retry = false;
                     break;
                 }
             }
@@ -97,22 +165,79 @@ fn main() {
     println!("Goodbye.");
 }
 
-/// Gère une nouvelle connexion. Retourne vrai si le joueur veut relancer la partie.
+/// Affiche l'utilisation du client et quitte
+fn print_usage() -> ! {
+    println!("Usage: ./snake-client ip port [--spectate room] [--name nickname]");
+    println!("       ./snake-client --replay file");
+    exit(0);
+}
+
+/// Lit le pseudo du joueur (clé `nickname`) dans le fichier de réglages, s'il existe.
+/// Renvoie *None* si le fichier est absent, invalide ou ne contient pas de pseudo.
+fn load_nickname() -> Option<String> {
+    let content = fs::read_to_string(SETTINGS_FILE).ok()?;
+    match content.parse::<toml::Value>() {
+        Ok(settings) => settings.get("nickname").and_then(|nickname| nickname.as_str()).map(String::from),
+        Err(e) => {
+            println!("Invalid {}: {}", SETTINGS_FILE, e);
+            None
+        }
+    }
+}
+
+/// Gère une nouvelle connexion et renvoie son issue.
 ///
 /// # Arguments
 ///
 /// * `stream` - Référence mutable vers le flux TCP
 /// * `window` - Référence mutable vers la fenêtre Piston
-fn handle_connection(stream: &mut TcpStream, window: &mut PistonWindow) -> bool {
-    let game: &mut Game = &mut Game::new(0, 0);
+/// * `session` - Option du jeton de session pour se reconnecter à une partie en cours
+/// * `spectate` - Option du salon à regarder en tant que spectateur
+/// * `nickname` - Option du pseudo du joueur, envoyé au serveur avant la demande de connexion
+fn handle_connection(stream: &mut TcpStream, window: &mut PistonWindow, session: Option<u64>, spectate: Option<u32>, nickname: Option<&str>) -> ConnectionEnd {
+    let game: &mut Game = &mut Game::new(DEFAULT_GAME_DIMENSIONS, DEFAULT_GAME_DIMENSIONS, 0);
     let address: &str = &stream.peer_addr().unwrap().to_string();
 
     let assets = find_folder::Search::ParentsThenKids(2, 3).for_folder("assets").unwrap();
     let ref font = assets.join("FiraSans-Regular.ttf");
     let glyphs = &mut window.load_font(font).unwrap();
+
+    // Négociation de la version du protocole ; un refus du serveur est affiché à l'écran
+    let features = match stream.set_read_timeout(Some(HANDSHAKE_TIMEOUT)).and_then(|_| protocol::handshake(stream)) {
+        Ok(features) => features,
+        Err(e) if e.kind() == io::ErrorKind::ConnectionRefused => {
+            println!("Rejected by the server: {}", e);
+            return show_error(window, glyphs, "Connexion refusée par le serveur :", &e.to_string());
+        }
+        Err(e) => {
+            println!("Handshake failed: {}", e);
+            return connection_error(window, glyphs, &e, session);
+        }
+    };
+
+    // Classement affiché pendant l'attente d'une partie
+    let leaderboard: Vec<protocol::LeaderboardEntry> = match stream.peer_addr() {
+        Ok(server) if features & protocol::FEATURE_LEADERBOARD != 0 => fetch_leaderboard(server),
+        _ => vec![],
+    };
     
-    // Récupération de l'identifiant du joueur
-    let id: u8 = protocol::get_player_id(stream);
+    // Présentation, demande d'une place (reconnexion ou spectateur), puis récupération de l'identifiant du joueur
+    let hello = match nickname {
+        Some(nickname) if features & protocol::FEATURE_NICKNAMES != 0 => protocol::send_hello(stream, nickname),
+        _ => Ok(()),
+    };
+    let joined = hello.and_then(|_| match spectate {
+        Some(room_id) => protocol::send_spectate(stream, room_id),
+        None => protocol::send_join(stream, session),
+    });
+    let (id, token): (u8, u64) = match joined.and_then(|_| protocol::get_player_id(stream)) {
+        Ok(player_id) => player_id,
+        Err(e) if e.kind() == io::ErrorKind::ConnectionRefused => {
+            println!("Rejected by the server: {}", e);
+            return show_error(window, glyphs, "Connexion refusée par le serveur :", &e.to_string());
+        }
+        Err(e) => return connection_error(window, glyphs, &e, session),
+    };
     println!("Id received: {}", id);
 
     match stream.set_read_timeout(Some(time::Duration::from_millis(50))) {
@@ -122,7 +247,7 @@ fn handle_connection(stream: &mut TcpStream, window: &mut PistonWindow) -> bool
 
     // En attente d'un ou plusieurs joueurs...
     println!("Waiting for opponent(s)...");
-    let mut client_state: ClientState = ClientState::Waiting;
+    let mut client_state: ClientState = ClientState::Waiting(None);
     
     let mut last_input = time::Instant::now();
     let mut last_update = time::Instant::now();
@@ -135,28 +260,54 @@ fn handle_connection(stream: &mut TcpStream, window: &mut PistonWindow) -> bool
     // ainsi que la documentation Piston
     while let Some(event) = window.next() {
         match client_state  {
-            ClientState::Waiting => {
+            ClientState::Waiting(_) => {
                 window.draw_2d(&event, |c, g, d| {
                     clear(BLACK, g);
-                    draw_hud(&c, g, glyphs, game, &client_state, address, id, &vec![]);
+                    draw_leaderboard(&c, g, glyphs, &leaderboard);
+                    draw_hud(&c, g, glyphs, game, address, &HudView { client_state: &client_state, playback: None, id, alive_assoc: &[] });
                     glyphs.factory.encoder.flush(d);
                 });
-                if protocol::check_if_params(stream, game, id) {
+                let params = loop {
+                    match protocol::check_if_queue(stream) {
+                        Ok(Some(queue_status)) => client_state = ClientState::Waiting(Some(queue_status)),
+                        Ok(None) => break protocol::check_if_params(stream, game, id),
+                        Err(e) => break Err(e),
+                    }
+                };
+                let received = match params {
+                    Ok(received) => received,
+                    Err(e) => return connection_error(window, glyphs, &e, Some(token)),
+                };
+                if received {
                     println!("Received game params. Let's go!");
                     client_state = ClientState::OnGoing;
-                    index = game.get_player_index(id).unwrap();
+                    // La fenêtre s'adapte à la map annoncée par le serveur
+                    window.set_size([
+                        (game.width * game.block_size + HUD_WIDTH) as u32,
+                        (game.height * game.block_size) as u32,
+                    ]);
+                    if id != SPECTATOR_ID {
+                        index = match game.get_player_index(id) {
+                            Some(index) => index,
+                            None => return connection_error(window, glyphs, &snecc_protocol::ProtocolError::UnknownPlayer(id).into(), Some(token)),
+                        };
+                    }
                 }
             }
 
             ClientState::OnGoing => {
                 if let Some(Button::Keyboard(key)) = event.press_args() {
-                    game.key_pressed(id, key);
+                    if id != SPECTATOR_ID {
+                        game.key_pressed(id, key);
+                    }
                 }
                 
-                // Envoi périodique d'un mouvement
-                if last_input.elapsed() > INPUT_PERIOD {
+                // Envoi périodique d'un mouvement (un spectateur n'en envoie pas)
+                if id != SPECTATOR_ID && last_input.elapsed() > INPUT_PERIOD {
                     let snake : &mut Snake = game.players.get_mut(index).unwrap();
-                    protocol::send_move(stream, &snake.moving);
+                    if protocol::send_move(stream, &snake.moving).is_err() {
+                        return ConnectionEnd::Lost(token);
+                    }
                     game.can_send_move = false;
                     last_input = time::Instant::now();
                 }
@@ -164,8 +315,10 @@ fn handle_connection(stream: &mut TcpStream, window: &mut PistonWindow) -> bool
                 // Réception périodique d'une frame du jeu
                 if last_update.elapsed() > UPDATE_PERIOD {
                     match protocol::check_if_frame(stream, game) {
-                        None => (),
-                        Some(alive) => {
+                        // Connexion perdue en cours de partie : on tentera de s'y reconnecter
+                        Err(e) => return connection_error(window, glyphs, &e, Some(token)),
+                        Ok(None) => (),
+                        Ok(Some(alive)) => {
                             alive_assoc = alive.clone();
                             let n_alive: usize = alive.into_iter().filter(|&(_, dead)| !dead).count();
                             if n_alive <= std::cmp::min(1, (game.n_players - 1) as usize) {
@@ -180,7 +333,7 @@ fn handle_connection(stream: &mut TcpStream, window: &mut PistonWindow) -> bool
                     clear(BLACK, g);
                     // Affichage du jeu
                     game.draw_game(&c, g, id);
-                    draw::draw_hud(&c, g, glyphs, game, &client_state, address, id, &alive_assoc);
+                    draw::draw_hud(&c, g, glyphs, game, address, &HudView { client_state: &client_state, playback: None, id, alive_assoc: &alive_assoc });
                     glyphs.factory.encoder.flush(d);
 
                });
@@ -194,7 +347,7 @@ fn handle_connection(stream: &mut TcpStream, window: &mut PistonWindow) -> bool
                         //         clear(BLACK, g);
                         //     });
                         // }
-                        return key == Key::R;
+                        return if key == Key::R { ConnectionEnd::Replay } else { ConnectionEnd::Quit };
                     }
                 }
                 window.draw_2d(&event, |c, g, d| {
@@ -202,7 +355,7 @@ fn handle_connection(stream: &mut TcpStream, window: &mut PistonWindow) -> bool
                     
                     // Affichage du jeu
                     game.draw_game(&c, g, id);
-                    draw::draw_hud(&c, g, glyphs, game, &client_state, address, id, &alive_assoc);
+                    draw::draw_hud(&c, g, glyphs, game, address, &HudView { client_state: &client_state, playback: None, id, alive_assoc: &alive_assoc });
                     glyphs.factory.encoder.flush(d);
 
                });
@@ -210,5 +363,155 @@ fn handle_connection(stream: &mut TcpStream, window: &mut PistonWindow) -> bool
         } 
     }
 
-    false
+    ConnectionEnd::Quit
+}
+
+/// Renvoie l'issue d'une connexion interrompue par une erreur. Un message invalide reçu du serveur
+/// est affiché jusqu'à ce que le joueur quitte ; sinon, la connexion est considérée comme perdue
+/// et le client tentera de se reconnecter à la partie s'il a un jeton de session.
+///
+/// # Arguments
+///
+/// * `window` - Référence mutable vers la fenêtre Piston
+/// * `glyphs` - Référence mutable vers le cache Glyph pour la police d'écriture
+/// * `error` - Référence vers l'erreur
+/// * `session` - Option du jeton de session pour se reconnecter à la partie en cours
+fn connection_error(window: &mut PistonWindow, glyphs: &mut Glyphs, error: &io::Error, session: Option<u64>) -> ConnectionEnd {
+    if error.kind() == io::ErrorKind::InvalidData {
+        println!("Protocol error: {}", error);
+        return show_error(window, glyphs, "Message invalide reçu du serveur :", &error.to_string());
+    }
+    match session {
+        Some(token) => ConnectionEnd::Lost(token),
+        None => ConnectionEnd::Quit,
+    }
+}
+
+/// Affiche une erreur jusqu'à ce que le joueur quitte.
+///
+/// # Arguments
+///
+/// * `window` - Référence mutable vers la fenêtre Piston
+/// * `glyphs` - Référence mutable vers le cache Glyph pour la police d'écriture
+/// * `title` - Titre de l'erreur
+/// * `message` - Message d'erreur
+fn show_error(window: &mut PistonWindow, glyphs: &mut Glyphs, title: &str, message: &str) -> ConnectionEnd {
+    let lines: Vec<String> = vec![
+        title.to_string(),
+        message.to_string(),
+        String::new(),
+        "[Q]: Quitter".to_string(),
+    ];
+    while let Some(event) = window.next() {
+        if let Some(Button::Keyboard(Key::Q)) = event.press_args() {
+            break;
+        }
+        window.draw_2d(&event, |c, g, d| {
+            clear(BLACK, g);
+            draw_text_lines(&c, g, glyphs, &lines);
+            glyphs.factory.encoder.flush(d);
+        });
+    }
+    ConnectionEnd::Quit
+}
+
+/// Récupère les premiers joueurs du classement du serveur, sur une connexion dédiée.
+/// Renvoie un classement vide si le serveur ne répond pas.
+///
+/// # Arguments
+///
+/// * `server` - Adresse du serveur
+fn fetch_leaderboard(server: SocketAddr) -> Vec<protocol::LeaderboardEntry> {
+    let fetched = TcpStream::connect_timeout(&server, LEADERBOARD_TIMEOUT).and_then(|mut stream| {
+        stream.set_read_timeout(Some(LEADERBOARD_TIMEOUT))?;
+        protocol::handshake(&mut stream)?;
+        protocol::get_leaderboard(&mut stream, LEADERBOARD_SIZE)
+    });
+    match fetched {
+        Ok(leaderboard) => leaderboard,
+        Err(e) => {
+            println!("Couldn't fetch the leaderboard: {}", e);
+            vec![]
+        }
+    }
+}
+
+/// Ouvre un replay enregistré par le serveur et le lit, sans connexion au serveur :
+/// les frames du replay passent par le même affichage qu'une partie en direct.
+///
+/// # Arguments
+///
+/// * `path` - Chemin du fichier de replay
+fn view_replay(path: &str) {
+    let replay = match Replay::load(path) {
+        Ok(replay) => replay,
+        Err(e) => {
+            println!("Impossible de lire le replay {} : {}", path, e);
+            return;
+        }
+    };
+    println!("Replay du salon {} ({} ticks, graine {})", replay.room_id, replay.n_ticks(), replay.seed);
+
+    let game: &mut Game = &mut Game::new(DEFAULT_GAME_DIMENSIONS, DEFAULT_GAME_DIMENSIONS, 0);
+    let mut alive_assoc: Vec<(u8, bool)> = match replay.show(game, None, 0) {
+        Ok(alive) => alive,
+        Err(e) => {
+            println!("Replay invalide {} : {}", path, e);
+            return;
+        }
+    };
+    let mut shown: usize = 0;
+
+    let window: &mut PistonWindow = &mut WindowSettings::new("Snake", [
+            (game.width * game.block_size + HUD_WIDTH) as u32,
+            (game.height * game.block_size) as u32,
+        ])
+        .exit_on_esc(true)
+        .resizable(false)
+        .build()
+        .unwrap();
+
+    let assets = find_folder::Search::ParentsThenKids(2, 3).for_folder("assets").unwrap();
+    let font = &assets.join("FiraSans-Regular.ttf");
+    let glyphs = &mut window.load_font(font).unwrap();
+    let name: &str = std::path::Path::new(path).file_name().and_then(|name| name.to_str()).unwrap_or(path);
+
+    let mut playback = Playback::new(replay.n_ticks());
+    let mut last_tick = time::Instant::now();
+
+    while let Some(event) = window.next() {
+        if let Some(Button::Keyboard(key)) = event.press_args() {
+            if key == Key::Q {
+                return;
+            }
+            playback.key_pressed(key, replay.period);
+        }
+
+        // Avancée de la lecture
+        if playback.paused {
+            last_tick = time::Instant::now();
+        } else if last_tick.elapsed() >= playback.interval(replay.period) {
+            playback.advance();
+            last_tick = time::Instant::now();
+        }
+
+        if playback.tick != shown {
+            match replay.show(game, Some(shown), playback.tick) {
+                Ok(alive) => alive_assoc = alive,
+                Err(e) => {
+                    println!("Replay invalide {} : {}", path, e);
+                    show_error(window, glyphs, "Replay invalide :", &e.to_string());
+                    return;
+                }
+            }
+            shown = playback.tick;
+        }
+
+        window.draw_2d(&event, |c, g, d| {
+            clear(BLACK, g);
+            game.draw_game(&c, g, SPECTATOR_ID);
+            draw::draw_hud(&c, g, glyphs, game, name, &HudView { client_state: &ClientState::OnGoing, playback: Some(&playback), id: SPECTATOR_ID, alive_assoc: &alive_assoc });
+            glyphs.factory.encoder.flush(d);
+        });
+    }
 }
@@ -1,301 +1,280 @@
 use std::net::TcpStream;
-use std::io::{Read, Write};
+use std::io::{self, Write};
+
+use snecc_protocol::{self as wire, Message, ProtocolError};
+
 use crate::Game;
 use crate::Snake;
-use crate::SnakeNode;
 use crate::Food;
 use crate::Move;
 
-/// Différents types de messages
-#[derive(PartialEq)]
-pub enum Msg {
-    PlayerId,
-    GameParams,
-    GameStart,
-    Frame,
-    Move,
-    UnknownId,
+pub use snecc_protocol::{Msg, QueueStatus, LeaderboardEntry, PROTOCOL_VERSION, FEATURE_NICKNAMES, FEATURE_LEADERBOARD, FEATURE_QUEUE_STATUS};
+
+/// Fonctionnalités prises en charge par le client
+pub const SUPPORTED_FEATURES: u32 = FEATURE_NICKNAMES | FEATURE_LEADERBOARD | FEATURE_QUEUE_STATUS;
+
+/// Reçoit un message du serveur en attendant qu'il soit complet.
+/// Renvoie un couple (type du message, vecteur d'octets du contenu), ou une erreur
+/// si la connexion est perdue ou si le serveur a envoyé un identifiant de message inconnu.
+/// 
+/// # Arguments
+/// 
+/// `stream` - Référence mutable vers le flux TCP
+fn get_data(stream: &mut TcpStream) -> io::Result<(Msg, Vec<u8>)> {
+    wire::read_packet(stream)
 }
 
-/// Renvoie un objet message à partir de son identifiant
-fn id_to_msg(id: u8) -> Msg {
-    match id {
-        0 => Msg::PlayerId,
-        1 => Msg::GameParams,
-        2 => Msg::GameStart,
-        3 => Msg::Frame,
-        4 => Msg::Move,
-        _ => Msg::UnknownId,
+/// Renvoie le type du prochain message à lire, sans le consommer, ou *None* s'il n'y a rien à lire.
+/// Renvoie une erreur si le serveur a fermé la connexion ou si l'identifiant du message est inconnu.
+/// 
+/// # Arguments
+/// 
+/// `stream` - Référence vers le flux TCP (avec un timeout de lecture)
+fn peek_msg(stream: &TcpStream) -> io::Result<Option<Msg>> {
+    let head: &mut [u8] = &mut [0; 1];
+    match stream.peek(head) {
+        Ok(0) => Err(io::Error::new(io::ErrorKind::UnexpectedEof, "connexion fermée par le serveur")),
+        Ok(_) => Ok(Some(wire::id_to_msg(head[0])?)),
+        Err(ref e) if e.kind() == io::ErrorKind::WouldBlock || e.kind() == io::ErrorKind::TimedOut => Ok(None),
+        Err(e) => Err(e),
     }
 }
 
-/// Renvoie l'identifiant numérique associé à un message
-fn msg_to_id(id: Msg) -> u8 {
-    match id {
-        Msg::PlayerId => 0,
-        Msg::GameParams => 1,
-        Msg::GameStart => 2,
-        Msg::Frame => 3,
-        Msg::Move => 4,
-        Msg::UnknownId => {
-            panic!("Unknown message ID");
-        },
-    }
+/// Envoie un message au serveur.
+/// Renvoie une erreur si la connexion avec le serveur est perdue ou si le message est trop long.
+/// 
+/// # Arguments
+/// 
+/// `stream` - Référence mutable vers le flux TCP
+/// `message` - Référence vers le message à envoyer
+fn send_message<M: Message>(stream: &mut TcpStream, message: &M) -> io::Result<()> {
+    stream.write_all(&message.packet()?)
 }
 
-/// Lit et renvoie un entier 32 bits à partir d'un tampon d'octets
+/// Négocie la version du protocole, premier échange avec le serveur : envoie la version et les fonctionnalités
+/// du client, puis renvoie les fonctionnalités communes retenues par le serveur.
+/// Renvoie une erreur `ConnectionRefused` contenant la raison donnée par le serveur s'il refuse le client,
+/// ou une autre erreur si la connexion est perdue ou si la réponse est invalide.
 /// 
 /// # Arguments
 /// 
-/// `buf` - Slice vers le tampon d'octets
-/// `index` - Indice à partir duquel lire l'entier
-/// `n` - Nombre d'octets à lire
-pub fn read_int_from_n_bytes(buf: &[u8], index: u32, n: u32) -> u32 {
-    if (buf.len() as u32) < index + n {
-        panic!("read_int_from_n_bytes: Asked to read further than actual buffer length");
-    }
-    
-    let mut res: u32 = 0;
-    for i in 0..n {
-        res += (256 as u32).pow(i) * ((buf[(index + i) as usize]) as u32);
+/// `stream` - Référence mutable vers le flux TCP (avec un timeout de lecture)
+pub fn handshake(stream: &mut TcpStream) -> io::Result<u32> {
+    send_message(stream, &wire::Version { version: PROTOCOL_VERSION, features: SUPPORTED_FEATURES })?;
+
+    match get_data(stream)? {
+        (Msg::Version, content) => Ok(wire::Version::decode(&content)?.features & SUPPORTED_FEATURES),
+        (Msg::Reject, content) => Err(io::Error::new(io::ErrorKind::ConnectionRefused, wire::Reject::decode(&content)?.reason)),
+        (msg, _) => Err(ProtocolError::UnexpectedMessage(msg).into()),
     }
+}
 
-    res
+/// Envoie le message de présentation avec le pseudo du joueur, avant la demande de connexion.
+/// 
+/// # Arguments
+/// 
+/// `stream` - Référence mutable vers le flux TCP
+/// `nickname` - Pseudo du joueur (le serveur le nettoie et le rend unique dans la partie)
+pub fn send_hello(stream: &mut TcpStream, nickname: &str) -> io::Result<()> {
+    send_message(stream, &wire::Hello { nickname: nickname.to_string() })
 }
 
-/// Reçoit un message du serveur.
-/// Renvoie un triplet (type du message, taille, vecteur d'octets du contenu)
+/// Envoie la demande de connexion : nouvelle partie, ou reconnexion à une partie en cours
+/// si un jeton de session est fourni.
 /// 
 /// # Arguments
 /// 
 /// `stream` - Référence mutable vers le flux TCP
-fn get_data(stream: &mut TcpStream) ->  (Msg, u32, Vec<u8>) {
-    let header: &mut [u8] = &mut [0; 3];
-    if stream.peek(header).unwrap() > 0 {
-        stream.read(header).unwrap();
+/// `token` - Option du jeton de session reçu lors d'une connexion précédente
+pub fn send_join(stream: &mut TcpStream, token: Option<u64>) -> io::Result<()> {
+    match token {
+        None => send_message(stream, &wire::Join::New),
+        Some(token) => send_message(stream, &wire::Join::Reconnect(token)),
     }
+}
 
-    let msg_id: u8 = read_int_from_n_bytes(header, 0, 1) as u8;
-    let msg_len: u16 = read_int_from_n_bytes(header, 1, 2) as u16;
+/// Envoie une demande de connexion en tant que spectateur.
+/// 
+/// # Arguments
+/// 
+/// `stream` - Référence mutable vers le flux TCP
+/// `room_id` - Identifiant du salon à regarder (0 : la partie en cours la plus récente)
+pub fn send_spectate(stream: &mut TcpStream, room_id: u32) -> io::Result<()> {
+    send_message(stream, &wire::Join::Spectate(room_id))
+}
 
-    let mut buf = vec![0 as u8; msg_len as usize];
-    stream.read_exact(&mut buf).unwrap();
+/// Demande les `n` premiers joueurs du classement et renvoie leurs statistiques, dans l'ordre du classement.
+/// Le serveur ferme la connexion après sa réponse : un flux TCP dédié doit être utilisé.
+/// Renvoie une erreur si la connexion est perdue ou si la réponse est invalide.
+/// 
+/// # Arguments
+/// 
+/// `stream` - Référence mutable vers le flux TCP
+/// `n` - Nombre de joueurs demandés
+pub fn get_leaderboard(stream: &mut TcpStream, n: u8) -> io::Result<Vec<LeaderboardEntry>> {
+    send_message(stream, &wire::LeaderboardRequest { n })?;
 
-    (id_to_msg(msg_id as u8), msg_len as u32, buf)
+    match get_data(stream)? {
+        (Msg::Leaderboard, content) => Ok(wire::Leaderboard::decode(&content)?.entries),
+        (msg, _) => Err(ProtocolError::UnexpectedMessage(msg).into()),
+    }
 }
 
-/// Envoie un message au serveur.
+/// Reçoit l'identifiant attribué au joueur (0 pour un spectateur) et son jeton de session.
+/// Renvoie une erreur `ConnectionRefused` contenant la raison donnée par le serveur s'il refuse la demande
+/// (salon inconnu pour un spectateur), ou une autre erreur si la connexion est perdue ou si le message est invalide.
 /// 
 /// # Arguments
 /// 
 /// `stream` - Référence mutable vers le flux TCP
-/// `msg` - Type de message à envoyer
-/// `msg_data` - Slice vers les octets constituant le contenu du message à envoyer
-fn send_data(stream: &mut TcpStream, msg: Msg, msg_data: &[u8]) -> () {
-    let n = msg_data.len();
-    match stream.write(&[&[msg_to_id(msg), (n % 256) as u8, (n / 256) as u8], msg_data].concat()) {
-        Err (e) => panic!("Erreur send_data: {}", e),
-        Ok (_) => (),
+pub fn get_player_id(stream: &mut TcpStream) -> io::Result<(u8, u64)> {
+    match get_data(stream)? {
+        (Msg::PlayerId, content) => {
+            let player_id = wire::PlayerId::decode(&content)?;
+            Ok((player_id.player_id, player_id.token))
+        }
+        (Msg::Reject, content) => Err(io::Error::new(io::ErrorKind::ConnectionRefused, wire::Reject::decode(&content)?.reason)),
+        (msg, _) => Err(ProtocolError::UnexpectedMessage(msg).into()),
     }
 }
 
-/// Reçoit l'identifiant attribué au joueur.
+/// Vérifie si un état de la file d'attente a été reçu.
+/// Si oui, renvoie une option de cet état, sinon renvoie *None*.
+/// Renvoie une erreur si la connexion est perdue ou si le message est invalide.
 /// 
 /// # Arguments
 /// 
 /// `stream` - Référence mutable vers le flux TCP
-pub fn get_player_id(stream: &mut TcpStream) -> u8 {
-    let (msg_id, msg_len, content): (Msg, u32, Vec<u8>) = get_data(stream);
-
-    if msg_id != Msg::PlayerId || msg_len != 1 {
-        panic!("get_player_id: malformed message; id:{}; len:{}", msg_to_id(msg_id), msg_len);
+pub fn check_if_queue(stream: &mut TcpStream) -> io::Result<Option<QueueStatus>> {
+    match peek_msg(stream)? {
+        Some(Msg::Queue) => {
+            let (_msg, content) = get_data(stream)?;
+            Ok(Some(QueueStatus::decode(&content)?))
+        }
+        _ => Ok(None),
     }
-    
-    content[0]
 }
 
 /// Vérifie et renvoie si les paramètres du jeu ont été reçus.
 /// Si oui, traite les paramètres reçus et modifie la partie en conséquence.
+/// Renvoie une erreur si la connexion est perdue ou si le message est invalide ou inattendu
+/// (seuls l'état de la file d'attente et les paramètres sont attendus avant le début de la partie).
 /// 
 /// # Arguments
 /// 
 /// `stream` - Référence mutable vers le flux TCP
 /// `game` - Référence mutable vers la partie
 /// `client_player_id` - Identifiant du joueur
-pub fn check_if_params(stream: &mut TcpStream, game: &mut Game, client_player_id: u8) -> bool {
-    let head :&mut [u8] = &mut [0; 3];
-    match stream.peek(head) {
-        Err(_e) => false,
-        Ok(n) => {
-            if n > 0 {
-                if head[0] == msg_to_id(Msg::GameParams) {
-                    get_game_params(stream, game, client_player_id);
-                    return true;
-                } else {
-                    return false;
-                }
-            } else {
-                false
-            }       
-        },
+pub fn check_if_params(stream: &mut TcpStream, game: &mut Game, client_player_id: u8) -> io::Result<bool> {
+    match peek_msg(stream)? {
+        Some(Msg::GameParams) => {
+            let (_msg, content) = get_data(stream)?;
+            deconstruct_game_params(&content, game, client_player_id)?;
+            Ok(true)
+        }
+        None | Some(Msg::Queue) => Ok(false),
+        Some(msg) => Err(ProtocolError::UnexpectedMessage(msg).into()),
     }
 }
 
-/// Reçoit et traite les paramètres du jeu.
+/// Déconstruit les paramètres du jeu à partir du contenu du message reçu par le serveur
+/// (ou lu dans un replay) et modifie la partie en conséquence.
+/// Renvoie une erreur si le contenu est invalide ; la partie n'est alors pas modifiée.
 /// 
 /// # Arguments
 /// 
-/// `stream` - Référence mutable vers le flux TCP
+/// `content` - Slice vers le contenu du message
 /// `game` - Référence mutable vers la partie
 /// `client_player_id` - Identifiant du joueur
-pub fn get_game_params(stream: &mut TcpStream, game: &mut Game, client_player_id: u8) {
-    let (msg_id, msg_len, content): (Msg, u32, Vec<u8>) = get_data(stream);
-    
-    if msg_id != Msg::GameParams {
-        panic!("get_game_params: malformed message; id:{}; len:{}", msg_to_id(msg_id), msg_len);
-    }
-
-    let map_size: u16 = read_int_from_n_bytes(&content, 0, 2) as u16;
-    let block_size: u16 = read_int_from_n_bytes(&content, 2, 2) as u16;
-    // let initial_speed: u8 = read_int_from_n_bytes(&content, 4, 1) as u8;
-    let n_players: u8 = read_int_from_n_bytes(&content, 5, 1) as u8;
+pub fn deconstruct_game_params(content: &[u8], game: &mut Game, client_player_id: u8) -> Result<(), ProtocolError> {
+    let params = wire::GameParams::decode(content)?;
 
-    let mut player_params: Vec<(u8, u8, u8, u8, i16, i16)> = vec![];
-    for i in 0..(n_players as u32) {
-        let player_id: u8 = read_int_from_n_bytes(&content, 6 + 8 * i, 1) as u8;
-        let player_red: u8 = read_int_from_n_bytes(&content, 6 + 8 * i + 1, 1) as u8;
-        let player_green: u8 = read_int_from_n_bytes(&content, 6 + 8 * i + 2, 1) as u8;
-        let player_blue: u8 = read_int_from_n_bytes(&content, 6 + 8 * i + 3, 1) as u8;
-        let player_x0: i16 = read_int_from_n_bytes(&content, 6 + 8 * i + 4, 2) as i16;
-        let player_y0: i16 = read_int_from_n_bytes(&content, 6 + 8 * i + 6, 2) as i16;
-        player_params.push(
-            (player_id, player_red, player_green, player_blue, player_x0, player_y0)
-        );
-        if player_id == client_player_id {
-            game.set_border_color([player_red as f32/256.0, player_green as f32/256.0, player_blue as f32/256.0, 1.0]);
+    for player in params.players.iter() {
+        let (red, green, blue) = player.color;
+        if player.id == client_player_id {
+            game.set_border_color([red as f32/256.0, green as f32/256.0, blue as f32/256.0, 1.0]);
         }
     }
 
-    game.dimensions = map_size;
-    game.block_size = block_size;
-    game.n_players = n_players;
-    game.init_players(player_params);    
+    // Murs intérieurs, en segments horizontaux (x, y, longueur)
+    game.walls = params.walls.iter().map(|&(x, y, len)| (x as i16, y as i16, len as i16)).collect();
+
+    game.width = params.width;
+    game.height = params.height;
+    game.wrap = params.wrap;
+    game.block_size = params.block_size;
+    game.n_players = params.players.len() as u8;
+    game.init_players(params.players);
+    Ok(())
 }
 
 /// Envoie un mouvement au serveur.
+/// Renvoie une erreur si la connexion avec le serveur est perdue.
 /// 
 /// # Arguments
 /// 
 /// `stream` - Référence mutable vers le flux TCP
 /// `player_move` - Référence vers le mouvement
-pub fn send_move(stream: &mut TcpStream, player_move: &Move) -> () {
-    let move_byte: u8 = match player_move {
-        Move::Up => 1,
-        Move::Down => 2,
-        Move::Left => 3,
-        Move::Right => 4,
-    };
-    send_data(stream, Msg::Move, &[move_byte]);
+pub fn send_move(stream: &mut TcpStream, player_move: &Move) -> io::Result<()> {
+    send_message(stream, player_move)
 }
 
 /// Vérifie si une frame a été reçue.
 /// Si oui, déconstruit la frame, la traite et renvoie une option de vecteur d'association codant les joueurs encore en vie.
-/// Si non, renvoie *None*.
+/// Si non, renvoie *None*. Renvoie une erreur si le serveur a fermé la connexion,
+/// ou si le message est invalide ou inattendu (seules des frames sont attendues pendant la partie).
 /// 
 /// # Arguments
 /// 
 /// `stream` - Référence mutable vers le flux TCP
 /// `game` - Référence mutable vers la partie
-pub fn check_if_frame(stream: &mut TcpStream, game: &mut Game) -> Option<Vec<(u8, bool)>> {
-    let head :&mut [u8] = &mut [0; 3];
-    match stream.peek(head) {
-        Err(_e) => (),
-        Ok(n) => {
-            if n > 0 {
-                if head[0] == msg_to_id(Msg::Frame) {
-                    let (msg, _len, data) = get_data(stream);
-                    return Some(deconstruct_frame(msg, &data, game));
-                }
-            }        
-        },
+pub fn check_if_frame(stream: &mut TcpStream, game: &mut Game) -> io::Result<Option<Vec<(u8, bool)>>> {
+    match peek_msg(stream)? {
+        Some(Msg::Frame) => {
+            let (_msg, content) = get_data(stream)?;
+            Ok(Some(deconstruct_frame(&content, game)?))
+        }
+        None => Ok(None),
+        Some(msg) => Err(ProtocolError::UnexpectedMessage(msg).into()),
     }
-    None
 }
 
-/// Déconstruit une frame à partir du contenu du message reçu par le serveur,
+/// Déconstruit une frame à partir du contenu du message reçu par le serveur (ou lu dans un replay),
 /// la traite et renvoie un vecteur d'association codant les joueurs encore en vie.
+/// Renvoie une erreur si le contenu est invalide ou contient un joueur inconnu ; la partie n'est alors pas modifiée.
 /// 
 /// # Arguments
 /// 
-/// `msg` - Le type de message
 /// `buf` - Slice vers le contenu du message
 /// `game` - Référence mutable vers la partie
-pub fn deconstruct_frame(msg: Msg, buf: &[u8], game: &mut Game) -> Vec<(u8, bool)> {
-    if msg != Msg::Frame {
-        panic!("deconstruct_frame: expected id {}, got {}", msg_to_id(Msg::Frame), msg_to_id(msg));
+pub fn deconstruct_frame(buf: &[u8], game: &mut Game) -> Result<Vec<(u8, bool)>, ProtocolError> {
+    let frame = wire::Frame::decode(buf)?;
+    if let Some(unknown) = frame.snakes.iter().find(|snake| game.get_player_index(snake.id).is_none()) {
+        return Err(ProtocolError::UnknownPlayer(unknown.id));
     }
 
-    let mut index: usize = 0;
-    let food_number: u8 = buf[index];
-    index += 1;
     game.clear_food();
-    
-    for _i in 0..food_number {
-        let food_type = buf[index];
-        index += 1;
-        let food_x = read_int_from_n_bytes(buf, index as u32, 2);
-        index += 2;
-        let food_y = read_int_from_n_bytes(buf, index as u32, 2);
-        index += 2;
-        game.add_food(food_x as i16, food_y as i16, Food::int_to_food_type(food_type));
+    // Une nourriture d'un type inconnu n'est pas affichée
+    for food in frame.food {
+        if let Some(food_type) = Food::int_to_food_type(food.food_type) {
+            game.add_food(food.x, food.y, food_type);
+        }
     }
-    
-    let snake_number: u8 = buf[index];
-    index += 1;
-    
-    let mut alive = vec![];
-
-    for _i in 0..snake_number {
-        let id = buf[index];
-        index += 1;
-        let has_lost = buf[index] == 1;
-        index += 1;
 
-        alive.push((id, has_lost));
+    let mut alive = vec![];
 
-        let food_in_stomach = buf[index];
-        index += 1;
-        let node_number = buf[index];
-        index += 1;
-        
-        let snake: &mut Snake;
-        match game.get_player_index(id) {
-            Some(snake_index) => {
-                snake = match game.players.get_mut(snake_index) {
-                    None => panic!("Could not get_mut from players: Vec<Snake>"),
-                    Some(snk) => snk,
-                }
-            },
-            None => panic!("deconstruct_frame: Received an unknown ID: {}", id),
-        }
+    for snake_state in frame.snakes {
+        alive.push((snake_state.id, snake_state.has_lost));
 
-        for i in 0..node_number {
-            let node_x = read_int_from_n_bytes(buf, index as u32, 2);
-            index += 2;
-            let node_y = read_int_from_n_bytes(buf, index as u32, 2);
-            index += 2;
-            
-            let node: SnakeNode = SnakeNode::new(node_x as i16, node_y as i16);
-            if i > 0 {
-                snake.add_node_as_tail(node);
-            } else {
-                snake.reset_snake_with_node(node);
-            }
-        }
+        // Les joueurs ont été vérifiés avant de modifier la partie
+        let index = game.get_player_index(snake_state.id).ok_or(ProtocolError::UnknownPlayer(snake_state.id))?;
+        let snake: &mut Snake = &mut game.players[index];
+        snake.set_body(&snake_state.nodes);
 
-        snake.has_lost = has_lost;
-        snake.stomach = food_in_stomach;
+        snake.has_lost = snake_state.has_lost;
+        snake.invisible = snake_state.invisible;
+        snake.stomach = snake_state.stomach;
     }
 
-    alive
+    Ok(alive)
 }
@@ -0,0 +1,295 @@
+use piston_window::Key;
+use std::fs;
+use std::io;
+use std::time;
+
+use snecc_protocol::replay::{self as format, Record};
+use snecc_protocol::{GameParams, Message, ProtocolError};
+
+use crate::game::Game;
+use crate::protocol;
+use crate::SPECTATOR_ID;
+
+/// Vitesses de lecture minimale et maximale
+const MIN_SPEED: f64 = 0.25;
+const MAX_SPEED: f64 = 8.0;
+
+/// Durée du saut en avant ou en arrière lors d'une recherche dans le replay
+const SEEK_STEP: time::Duration = time::Duration::from_secs(10);
+
+/// Replay d'une partie enregistré par le serveur.
+/// Seuls les paramètres et les frames sont utilisés ; les mouvements des joueurs sont ignorés.
+pub struct Replay {
+    /// Identifiant du salon de la partie
+    pub room_id: u32,
+    /// Graine du générateur aléatoire de la partie
+    pub seed: u64,
+    /// Durée d'un tick
+    pub period: time::Duration,
+    /// Contenu du message des paramètres de la partie
+    params: Vec<u8>,
+    /// Contenu des messages de frame, un par tick
+    frames: Vec<Vec<u8>>,
+}
+
+/// État de la lecture d'un replay
+pub struct Playback {
+    /// Tick affiché
+    pub tick: usize,
+    /// Nombre de ticks du replay
+    pub n_ticks: usize,
+    /// Booléen codant si la lecture est en pause
+    pub paused: bool,
+    /// Vitesse de lecture (1 : temps réel)
+    pub speed: f64,
+}
+
+/// Implémentation d'un replay
+impl Replay {
+    /// Lit un fichier de replay.
+    /// Renvoie une erreur si le fichier ne peut pas être lu ou n'est pas un replay valide.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - Chemin du fichier
+    pub fn load(path: &str) -> io::Result<Replay> {
+        Replay::parse(&fs::read(path)?)
+    }
+
+    /// Déconstruit le contenu d'un fichier de replay
+    ///
+    /// # Arguments
+    ///
+    /// * `content` - Slice vers le contenu du fichier
+    fn parse(content: &[u8]) -> io::Result<Replay> {
+        let invalid = |message: &str| io::Error::new(io::ErrorKind::InvalidData, message.to_string());
+        let file = format::parse(content)?;
+
+        let mut replay = Replay {
+            room_id: file.header.room_id,
+            seed: file.header.seed,
+            period: time::Duration::from_millis(file.header.period_ms as u64),
+            params: vec![],
+            frames: vec![],
+        };
+        for (record, payload) in file.records {
+            match record {
+                Record::Params => replay.params = payload.to_vec(),
+                Record::Frame => replay.frames.push(payload.to_vec()),
+                Record::Input | Record::Quit => (),
+            }
+        }
+
+        if replay.params.is_empty() || replay.frames.is_empty() {
+            return Err(invalid("replay vide"));
+        }
+        if let Err(e) = GameParams::decode(&replay.params) {
+            return Err(invalid(&format!("paramètres de la partie invalides : {}", e)));
+        }
+        Ok(replay)
+    }
+
+    /// Renvoie le nombre de ticks du replay
+    pub fn n_ticks(&self) -> usize {
+        self.frames.len()
+    }
+
+    /// Met la partie dans l'état d'un tick et renvoie le vecteur d'association codant les joueurs encore en vie.
+    /// Si le tick suit directement le tick affiché, seule sa frame est appliquée ; sinon, la partie est
+    /// reconstruite depuis les paramètres (les frames ne contiennent que les serpents encore en vie).
+    /// Renvoie une erreur si une frame du replay est invalide.
+    ///
+    /// # Arguments
+    ///
+    /// * `game` - Référence mutable vers la partie
+    /// * `shown` - Option du tick affiché
+    /// * `tick` - Tick à afficher
+    pub fn show(&self, game: &mut Game, shown: Option<usize>, tick: usize) -> Result<Vec<(u8, bool)>, ProtocolError> {
+        let first = match shown {
+            Some(shown) if shown + 1 == tick => tick,
+            _ => {
+                protocol::deconstruct_game_params(&self.params, game, SPECTATOR_ID)?;
+                0
+            }
+        };
+
+        let mut alive = vec![];
+        for frame in &self.frames[first..=tick] {
+            alive = protocol::deconstruct_frame(frame, game)?;
+        }
+        Ok(alive)
+    }
+}
+
+/// Implémentation de l'état de lecture
+impl Playback {
+    /// Renvoie l'état de lecture au début d'un replay
+    ///
+    /// # Arguments
+    ///
+    /// * `n_ticks` - Nombre de ticks du replay
+    pub fn new(n_ticks: usize) -> Self {
+        Playback {
+            tick: 0,
+            n_ticks,
+            paused: false,
+            speed: 1.0,
+        }
+    }
+
+    /// Renvoie le temps entre deux ticks à la vitesse de lecture actuelle
+    ///
+    /// # Arguments
+    ///
+    /// * `period` - Durée d'un tick de la partie
+    pub fn interval(&self, period: time::Duration) -> time::Duration {
+        period.div_f64(self.speed)
+    }
+
+    /// Avance d'un tick ; la lecture se met en pause à la fin du replay
+    pub fn advance(&mut self) {
+        if self.tick + 1 < self.n_ticks {
+            self.tick += 1;
+        } else {
+            self.paused = true;
+        }
+    }
+
+    /// Traite une touche de contrôle de la lecture :
+    /// Espace (lecture/pause), Haut/Bas (vitesse), Gauche/Droite (tick précédent/suivant, en pause),
+    /// Page précédente/suivante (saut de 10 secondes), Début/Fin.
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - La touche pressée
+    /// * `period` - Durée d'un tick de la partie
+    pub fn key_pressed(&mut self, key: Key, period: time::Duration) {
+        let last = self.n_ticks - 1;
+        let seek_ticks = (SEEK_STEP.as_millis() / std::cmp::max(period.as_millis(), 1)) as usize;
+        match key {
+            // À la fin du replay, la lecture reprend depuis le début
+            Key::Space if self.paused && self.tick == last => {
+                self.tick = 0;
+                self.paused = false;
+            }
+            Key::Space => self.paused = !self.paused,
+            Key::Up => self.speed = f64::min(self.speed * 2.0, MAX_SPEED),
+            Key::Down => self.speed = f64::max(self.speed / 2.0, MIN_SPEED),
+            Key::Right => {
+                self.paused = true;
+                self.tick = std::cmp::min(self.tick + 1, last);
+            }
+            Key::Left => {
+                self.paused = true;
+                self.tick = self.tick.saturating_sub(1);
+            }
+            Key::PageUp => self.tick = std::cmp::min(self.tick + seek_ticks, last),
+            Key::PageDown => self.tick = self.tick.saturating_sub(seek_ticks),
+            Key::Home => self.tick = 0,
+            Key::End => self.tick = last,
+            _ => (),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use snecc_protocol::replay::Header;
+    use snecc_protocol::{Frame, HEADER_LEN};
+
+    /// Renvoie le contenu d'un fichier de replay de `n_frames` ticks
+    ///
+    /// # Arguments
+    ///
+    /// * `n_frames` - Nombre de frames du replay
+    fn replay_file(n_frames: usize) -> Vec<u8> {
+        let params = GameParams { width: 16, height: 16, block_size: 10, initial_speed: 1, wrap: false, players: vec![], walls: vec![] };
+        let mut content = vec![];
+        format::write_header(&mut content, &Header { room_id: 3, seed: 42, period_ms: 100 }).unwrap();
+        format::write_record(&mut content, Record::Params, &params.packet().unwrap()[HEADER_LEN..]).unwrap();
+        for _ in 0..n_frames {
+            format::write_record(&mut content, Record::Input, &[1, 2]).unwrap();
+            format::write_record(&mut content, Record::Frame, &Frame { food: vec![], snakes: vec![] }.packet().unwrap()[HEADER_LEN..]).unwrap();
+        }
+        content
+    }
+
+    #[test]
+    fn test_parse() {
+        let replay = Replay::parse(&replay_file(5)).unwrap();
+        assert_eq!((replay.room_id, replay.seed, replay.n_ticks()), (3, 42, 5));
+        assert_eq!(replay.period, time::Duration::from_millis(100));
+    }
+
+    #[test]
+    fn test_parse_invalid() {
+        let content = replay_file(5);
+        let error = |content: &[u8]| Replay::parse(content).err().unwrap().to_string();
+        assert_eq!(error(&content[..content.len() - 1]), "replay tronqué");
+        assert_eq!(error(&[b"SNACKRPL", &content[8..]].concat()), "ce fichier n'est pas un replay");
+        assert_eq!(error(&[&content[..8], &[2], &content[9..]].concat()), format!("version de replay 2 non prise en charge (attendue : {})", format::VERSION));
+        assert_eq!(error(&replay_file(0)), "replay vide");
+    }
+
+    #[test]
+    fn test_advance() {
+        let mut playback = Playback::new(3);
+        playback.advance();
+        playback.advance();
+        assert_eq!((playback.tick, playback.paused), (2, false));
+        // La lecture se met en pause à la fin du replay
+        playback.advance();
+        assert_eq!((playback.tick, playback.paused), (2, true));
+    }
+
+    #[test]
+    fn test_seek() {
+        // 10 secondes à 100 ms par tick : saut de 100 ticks
+        let period = time::Duration::from_millis(100);
+        let mut playback = Playback::new(250);
+        playback.key_pressed(Key::PageUp, period);
+        assert_eq!(playback.tick, 100);
+        playback.key_pressed(Key::PageUp, period);
+        playback.key_pressed(Key::PageUp, period);
+        assert_eq!(playback.tick, 249);
+        playback.key_pressed(Key::Right, period);
+        assert_eq!((playback.tick, playback.paused), (249, true));
+        playback.key_pressed(Key::PageDown, period);
+        assert_eq!(playback.tick, 149);
+        playback.key_pressed(Key::Home, period);
+        playback.key_pressed(Key::Left, period);
+        assert_eq!(playback.tick, 0);
+        playback.key_pressed(Key::End, period);
+        assert_eq!(playback.tick, 249);
+    }
+
+    #[test]
+    fn test_resume_after_end() {
+        let period = time::Duration::from_millis(100);
+        let mut playback = Playback::new(2);
+        playback.advance();
+        playback.advance();
+        assert!(playback.paused);
+        // À la fin du replay, Espace reprend la lecture depuis le début
+        playback.key_pressed(Key::Space, period);
+        assert_eq!((playback.tick, playback.paused), (0, false));
+        // Ailleurs, Espace met simplement en pause
+        playback.key_pressed(Key::Space, period);
+        assert_eq!((playback.tick, playback.paused), (0, true));
+    }
+
+    #[test]
+    fn test_speed() {
+        let period = time::Duration::from_millis(100);
+        let mut playback = Playback::new(2);
+        for _ in 0..10 {
+            playback.key_pressed(Key::Up, period);
+        }
+        assert_eq!(playback.interval(period), period.div_f64(MAX_SPEED));
+        for _ in 0..10 {
+            playback.key_pressed(Key::Down, period);
+        }
+        assert_eq!(playback.interval(period), period.div_f64(MIN_SPEED));
+    }
+}
@@ -0,0 +1,309 @@
+use serde::Deserialize;
+use std::fs;
+use std::time;
+
+use crate::map::MIN_DIMENSION;
+use crate::spawn;
+
+/// Allongement maximal par pomme : deux pommes mangées coup sur coup tiennent dans l'estomac (un octet)
+pub const MAX_FOOD_BY_APPLE: u8 = u8::MAX / 2;
+
+/// Configuration du serveur : constantes du jeu et du matchmaking.
+/// Chargée depuis un fichier TOML (voir `config.toml`), puis surchargée par la ligne de commande.
+/// Les champs absents prennent leur valeur par défaut.
+#[derive(Deserialize, Clone, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    /// Largeur de la map en blocs
+    pub map_width: u16,
+    /// Hauteur de la map en blocs
+    pub map_height: u16,
+    /// Fichier de map (murs, points d'apparition, zones de nourriture) ; remplace `map_width` et `map_height`
+    pub map_file: Option<String>,
+    /// Arène torique : pas de mur sur le pourtour, un serpent qui sort d'un côté revient par le côté opposé
+    pub wrap: bool,
+    /// Taille des blocs en pixels
+    pub block_size: u16,
+    /// Fréquence initiale des joueurs
+    pub initial_speed: u8,
+    /// Période initiale (envoi de frames) en millisecondes
+    pub initial_period_ms: u64,
+    /// Temps entre deux réceptions d'un mouvement du client en millisecondes
+    pub input_period_ms: u64,
+    /// Durée du boost de vitesse (Mangue) en millisecondes
+    pub boost_duration_ms: u64,
+    /// Mode développeur "No Death" qui empêche les joueurs de mourir
+    pub dev_no_death: bool,
+    /// Allongement du serpent par pomme mangée (au plus `MAX_FOOD_BY_APPLE`)
+    pub food_by_apple: u8,
+    /// Nombre de nourritures maximale sur la carte
+    pub max_food: usize,
+    /// Poids de la pomme dans le tirage du type d'une nouvelle nourriture
+    pub apple_weight: u32,
+    /// Poids de la mangue dans le tirage du type d'une nouvelle nourriture
+    pub mango_weight: u32,
+    /// Poids de la nourriture d'invisibilité dans le tirage du type d'une nouvelle nourriture
+    pub invisibility_weight: u32,
+    /// Durée de l'invisibilité (le serpent est caché à ses adversaires) en millisecondes
+    pub invisibility_duration_ms: u64,
+    /// Distance minimale (en blocs) entre une nouvelle nourriture et la tête d'un serpent en vie
+    pub food_head_distance: u16,
+    /// Nombre minimal de joueurs d'une partie
+    pub min_players: u8,
+    /// Nombre maximal de joueurs d'une partie
+    pub max_players: u8,
+    /// Compte à rebours avant le début d'une partie, une fois le nombre minimal de joueurs atteint, en secondes
+    pub countdown_s: u64,
+    /// Couleurs possibles des serpents (RGB), attribuées aléatoirement dans chaque salon
+    pub palette: Vec<(u8, u8, u8)>,
+    /// Graine du générateur aléatoire des parties ; si absente, une graine aléatoire est tirée
+    /// (et affichée) au début de chaque partie
+    pub seed: Option<u64>,
+    /// Dossier où enregistrer le replay de chaque partie ; si absent, aucun replay n'est enregistré
+    pub replay_dir: Option<String>,
+    /// Fichier du classement des joueurs (victoires, défaites, serpents tués, longueur maximale)
+    pub leaderboard_file: String,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            map_width: 64,
+            map_height: 64,
+            map_file: None,
+            wrap: false,
+            block_size: 10,
+            initial_speed: 1,
+            initial_period_ms: 50,
+            input_period_ms: 10,
+            boost_duration_ms: 750,
+            dev_no_death: false,
+            food_by_apple: 4,
+            max_food: 20,
+            apple_weight: 1,
+            mango_weight: 1,
+            invisibility_weight: 1,
+            invisibility_duration_ms: 3000,
+            food_head_distance: 3,
+            min_players: 2,
+            max_players: 2,
+            countdown_s: 10,
+            palette: vec![
+                (0x4C, 0x3B, 0xE3),
+                (0xDA, 0xAD, 0xFF),
+                (0xF6, 0x83, 0x03),
+                (0xF7, 0x49, 0x80),
+                (0x9A, 0xF4, 0x96),
+                (0x91, 0x67, 0x9D),
+                (0xE1, 0x1C, 0x2F),
+                (0x97, 0x99, 0x13),
+                (0x2E, 0xC4, 0xB6),
+                (0xFF, 0xD1, 0x66),
+                (0x06, 0xD6, 0xA0),
+                (0x11, 0x8A, 0xB2),
+                (0xEF, 0x47, 0x6F),
+                (0xB5, 0x83, 0x8D),
+                (0xFF, 0xFF, 0xFF),
+                (0x8A, 0xC9, 0x26),
+            ],
+            seed: None,
+            replay_dir: None,
+            leaderboard_file: "leaderboard.toml".to_string(),
+        }
+    }
+}
+
+/// Implémentation de la configuration
+impl Config {
+    /// Charge la configuration à partir des arguments de la ligne de commande
+    /// (sans le nom du programme ni le port) : `[--config <fichier>] [--<clé> <valeur>]...`.
+    /// Les valeurs données en ligne de commande remplacent celles du fichier.
+    /// Renvoie une erreur lisible si un argument, le fichier ou une valeur est invalide.
+    ///
+    /// # Arguments
+    ///
+    /// * `args` - Arguments de la ligne de commande
+    pub fn from_args(args: &[String]) -> Result<Config, String> {
+        let mut table = toml::value::Table::new();
+        let mut overrides = toml::value::Table::new();
+
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            let key = match arg.strip_prefix("--") {
+                Some(key) => key,
+                None => return Err(format!("argument inattendu : {}", arg)),
+            };
+            let value = match args.next() {
+                Some(value) => value,
+                None => return Err(format!("valeur manquante pour --{}", key)),
+            };
+
+            if key == "config" {
+                let content = fs::read_to_string(value).map_err(|e| format!("{} : {}", value, e))?;
+                table = content.parse::<toml::Value>()
+                    .map_err(|e| format!("{} : {}", value, e))?
+                    .try_into()
+                    .map_err(|e| format!("{} : {}", value, e))?;
+            } else {
+                overrides.insert(key.replace('-', "_"), parse_value(value));
+            }
+        }
+
+        table.extend(overrides);
+        let config: Config = toml::Value::Table(table).try_into().map_err(|e| e.to_string())?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    /// Vérifie la cohérence des valeurs chargées
+    pub fn validate(&self) -> Result<(), String> {
+        if self.map_width < MIN_DIMENSION || self.map_height < MIN_DIMENSION {
+            return Err(format!("map_width et map_height doivent valoir au moins {} (reçu {}x{})", MIN_DIMENSION, self.map_width, self.map_height));
+        }
+        if self.block_size == 0 {
+            return Err("block_size doit être strictement positif".to_string());
+        }
+        if self.initial_speed == 0 {
+            return Err("initial_speed doit être strictement positif".to_string());
+        }
+        if self.initial_period_ms == 0 || self.input_period_ms == 0 {
+            return Err("initial_period_ms et input_period_ms doivent être strictement positifs".to_string());
+        }
+        if self.food_by_apple > MAX_FOOD_BY_APPLE {
+            return Err(format!("food_by_apple doit valoir au plus {}", MAX_FOOD_BY_APPLE));
+        }
+        // Le nombre de nourritures d'une frame tient sur un octet
+        if self.max_food == 0 || self.max_food > u8::MAX as usize {
+            return Err(format!("max_food doit être entre 1 et {}", u8::MAX));
+        }
+        // Un bonus plus court qu'un tick durerait 0 tick et n'aurait aucun effet
+        if self.boost_duration_ms < self.initial_period_ms || self.invisibility_duration_ms < self.initial_period_ms {
+            return Err(format!("boost_duration_ms et invisibility_duration_ms doivent valoir au moins initial_period_ms ({})", self.initial_period_ms));
+        }
+        if self.apple_weight == 0 && self.mango_weight == 0 && self.invisibility_weight == 0 {
+            return Err("au moins un poids de nourriture (apple_weight, mango_weight, invisibility_weight) doit être strictement positif".to_string());
+        }
+        // La distance est comparée à des distances en blocs sur la map (i16)
+        let max_distance = match self.map_file {
+            None => self.map_width.max(self.map_height),
+            Some(_) => i16::MAX as u16,
+        };
+        if self.food_head_distance > max_distance {
+            return Err(format!("food_head_distance doit valoir au plus {} sur cette map", max_distance));
+        }
+        // La capacité d'une map chargée depuis un fichier est vérifiée à son chargement
+        let capacity = match self.map_file {
+            None => spawn::capacity(self.map_width, self.map_height),
+            Some(_) => spawn::MAX_PLAYERS,
+        };
+        if self.min_players < 1 || self.max_players > capacity || self.min_players > self.max_players {
+            return Err(format!("le nombre de joueurs doit être entre 1 et {} sur cette map, avec min_players <= max_players", capacity));
+        }
+        if self.palette.len() < self.max_players as usize {
+            return Err(format!("palette doit contenir au moins {} couleurs (max_players)", self.max_players));
+        }
+        Ok(())
+    }
+
+    /// Renvoie la période d'un tick de jeu
+    pub fn initial_period(&self) -> time::Duration {
+        time::Duration::from_millis(self.initial_period_ms)
+    }
+
+    /// Renvoie le temps minimal entre deux réceptions d'un mouvement du client
+    pub fn input_period(&self) -> time::Duration {
+        time::Duration::from_millis(self.input_period_ms)
+    }
+
+    /// Renvoie la durée du compte à rebours avant le début d'une partie
+    pub fn countdown(&self) -> time::Duration {
+        time::Duration::from_secs(self.countdown_s)
+    }
+
+    /// Renvoie la durée du boost de vitesse en nombre de ticks
+    pub fn boost_ticks(&self) -> u32 {
+        (self.boost_duration_ms / self.initial_period_ms) as u32
+    }
+
+    /// Renvoie la durée de l'invisibilité en nombre de ticks
+    pub fn invisibility_ticks(&self) -> u32 {
+        (self.invisibility_duration_ms / self.initial_period_ms) as u32
+    }
+}
+
+/// Interprète une valeur de la ligne de commande comme une valeur TOML (nombre, booléen, tableau...),
+/// ou à défaut comme une chaîne de caractères
+///
+/// # Arguments
+///
+/// * `value` - Valeur telle qu'écrite en ligne de commande
+fn parse_value(value: &str) -> toml::Value {
+    match format!("value = {}", value).parse::<toml::Value>() {
+        Ok(toml::Value::Table(mut table)) => table.remove("value").unwrap(),
+        _ => toml::Value::String(value.to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(args: &[&str]) -> Vec<String> {
+        args.iter().map(|arg| arg.to_string()).collect()
+    }
+
+    #[test]
+    fn test_default_is_valid() {
+        assert!(Config::default().validate().is_ok());
+        assert_eq!(Config::from_args(&[]).unwrap().map_width, 64);
+    }
+
+    #[test]
+    fn test_overrides() {
+        let config = Config::from_args(&args(&["--map_width", "80", "--map-height", "32", "--dev-no-death", "true", "--max_players", "4"])).unwrap();
+        assert_eq!((config.map_width, config.map_height), (80, 32));
+        assert!(config.dev_no_death);
+        assert_eq!(config.max_players, 4);
+        assert_eq!(config.min_players, 2);
+        assert!(!config.wrap);
+        assert!(Config::from_args(&args(&["--wrap", "true"])).unwrap().wrap);
+        assert_eq!(Config::from_args(&args(&["--seed", "1234"])).unwrap().seed, Some(1234));
+    }
+
+    #[test]
+    fn test_file_then_overrides() {
+        let path = std::env::temp_dir().join(format!("snecc-config-{}.toml", std::process::id()));
+        fs::write(&path, "map_width = 40\nmax_food = 5\npalette = [[1, 2, 3], [4, 5, 6]]\n").unwrap();
+        let config = Config::from_args(&args(&["--config", path.to_str().unwrap(), "--max_food", "8"])).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!((config.map_width, config.map_height), (40, 64));
+        assert_eq!(config.max_food, 8);
+        assert_eq!(config.palette, vec![(1, 2, 3), (4, 5, 6)]);
+    }
+
+    #[test]
+    fn test_food_by_apple_bound() {
+        assert!(Config::from_args(&args(&["--food_by_apple", "127"])).is_ok());
+        assert!(Config::from_args(&args(&["--food_by_apple", "128"])).is_err());
+    }
+
+    #[test]
+    fn test_invalid() {
+        assert!(Config::from_args(&args(&["--map_height", "8"])).is_err());
+        assert!(Config::from_args(&args(&["--min_players", "3", "--max_players", "2"])).is_err());
+        assert!(Config::from_args(&args(&["--max_players", "17"])).is_err());
+        assert!(Config::from_args(&args(&["--unknown", "1"])).is_err());
+        assert!(Config::from_args(&args(&["--map_width", "abc"])).is_err());
+        assert!(Config::from_args(&args(&["--map_width"])).is_err());
+        assert!(Config::from_args(&args(&["--max_players", "3", "--palette", "[[1, 2, 3]]"])).is_err());
+        assert!(Config::from_args(&args(&["--max_food", "256"])).is_err());
+        assert!(Config::from_args(&args(&["--apple_weight", "0", "--mango_weight", "0", "--invisibility_weight", "0"])).is_err());
+        assert!(Config::from_args(&args(&["--apple_weight", "0"])).is_ok());
+        assert!(Config::from_args(&args(&["--boost_duration_ms", "49"])).is_err());
+        assert!(Config::from_args(&args(&["--invisibility_duration_ms", "10"])).is_err());
+        assert!(Config::from_args(&args(&["--boost_duration_ms", "50"])).is_ok());
+        assert!(Config::from_args(&args(&["--food_head_distance", "65"])).is_err());
+        assert!(Config::from_args(&args(&["--food_head_distance", "40000", "--map_file", "map.txt"])).is_err());
+        assert!(Config::from_args(&args(&["--food_head_distance", "64"])).is_ok());
+    }
+}
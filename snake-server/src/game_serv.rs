@@ -1,79 +1,62 @@
-use std::ops::Deref;
-use std::ops::DerefMut;
 use std::cmp;
-use std::time;
-use rand::distributions::{Distribution, Uniform};
+use std::collections::VecDeque;
+use std::sync::Arc;
+use rand::distributions::{Distribution, Uniform, WeightedIndex};
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+use rand::SeedableRng;
 
-use crate::DEV_NO_DEATH;
+use crate::config::Config;
+use crate::grid::Grid;
+use crate::map::Map;
+use crate::spawn::Spawn;
 
 
-/// Allongement du serpent par pomme mangée
-const FOOD_BY_APPLE: u8 = 4;
+/// Nombre de cases tirées au hasard pour faire apparaître une nourriture,
+/// avant de chercher parmi toutes les cases candidates
+const FOOD_TILE_ATTEMPTS: usize = 32;
 
-/// Nombre de types de nourritures
-const N_FOOD_TYPES: u64 = 2;
-
-/// Nombre de nourritures maximale sur la carte
-const MAX_FOOD: usize = 20;
-
-/// Différents mouvements
-#[derive(PartialEq, Debug)]
-pub enum Move {
-    Up,
-    Down,
-    Left,
-    Right,
-}
+pub use snecc_protocol::Move;
 
 /// Différents types de nourriture
-#[derive(Clone, PartialEq)]
+#[derive(Clone, PartialEq, Debug)]
 pub enum FoodType {
     /// [1] Pomme
     Apple,
     /// [2] Mangue
     Mango,
+    /// [3] Invisibilité
+    Invisibility,
 }
 
 
-/// Renvoie un objet FoodType à partir de l'identifiant numérique
-///
-/// # Arguments
-///
-/// * `food_type` - L'identifiant de la nourriture
-pub fn food_id_to_type(food_type: u64) -> FoodType {
-    match food_type {
-        1 => FoodType::Apple,
-        2 => FoodType::Mango,
-        _ => {
-            panic!("Unknown food type {}", food_type);
-        }
-    }
-}
-
 /// Différents types de cases
-enum TileType {
+#[derive(Debug, PartialEq)]
+pub enum TileType {
     FoodTile(FoodType),
     SnakeTile(u8),
     Wall,
     Nothing,
 }
 
-/// Noeuds du serpent (liste chaînée)
-#[derive(Debug, Clone, PartialEq)]
+/// Noeud du serpent : extrémité ou coin du corps
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub struct SnakeNode {
     pub x: i16,
     pub y: i16,
-    pub next_node: Option<Box<SnakeNode>>,
 }
 
 /// Structure de serpent
 pub struct Snake {
     /// Identifiant unique du serpent au sein de la partie
     pub id: u8,
+    /// Pseudo du joueur, unique au sein de la partie
+    pub name: String,
     /// Couleur du serpent
     pub color: (u8, u8, u8),
-    /// Tête du serpent (premier noeud de la liste chaînée)
-    pub head: SnakeNode,
+    /// Noeuds du serpent, de la tête (devant) à la queue (derrière) :
+    /// deux noeuds consécutifs sont toujours alignés
+    pub body: VecDeque<SnakeNode>,
     /// Direction (vérifiée) du serpent
     pub direction: Move,
     /// Intention de mouvement du serpent
@@ -85,8 +68,14 @@ pub struct Snake {
     pub has_lost: bool,
     /// Nombre d'unités dans l'estomac du serpent
     pub stomach: u8,
-    /// Actif durant le power-up de boost (Mangue)
-    pub boost: bool,
+    /// Nombre de ticks restants du power-up de boost (Mangue)
+    pub boost: u32,
+    /// Nombre de ticks restants du power-up d'invisibilité : le serpent est caché à ses adversaires
+    pub invisible: u32,
+    /// Nombre de serpents tués par ce serpent
+    pub kills: u32,
+    /// Longueur maximale atteinte en blocs
+    pub max_length: u32,
 }
 
 /// Structure de nourriture
@@ -98,68 +87,39 @@ pub struct Food {
 
 /// Structure de partie
 pub struct Game {
-    /// Taille en blocs de la map
-    dimensions: u16,
+    /// Map de la partie (dimensions, murs, zones de nourriture)
+    map: Arc<Map>,
+    /// Grille d'occupation de la map, tenue à jour avec les serpents et la nourriture
+    grid: Grid,
     /// Vecteur des nourritures présentes sur la map
-    pub food: Vec<Food>,
+    food: Vec<Food>,
     pub waiting: bool,
     pub period: f64,
     pub progress: f64,
     /// Vecteur des serpents joueurs
     pub players: Vec<Snake>,
+    /// Configuration du serveur (allongement par pomme, nourriture maximale, boost...)
+    config: Arc<Config>,
+    /// Générateur aléatoire de la partie : la même graine et les mêmes mouvements
+    /// donnent les mêmes apparitions de nourriture
+    rng: StdRng,
 }
 
 /// Implémentation d'un noeud de serpent
 impl SnakeNode {
-    /// Renvoie un nouveau noeud sans successeur
+    /// Renvoie un nouveau noeud
     ///
     /// # Arguments
     ///
     /// * `x`, `y` - Coordonnées du noeud
     pub fn new(x: i16, y: i16) -> Self {
-        SnakeNode {
-            x,
-            y,
-            next_node: None,
-        }
-    }
-
-    /// Renvoie si le noeud a un successeur
-    fn has_next(&self) -> bool {
-        match self.next_node {
-            None => return false,
-            Some(_) => return true,
-        }
-    }
-
-    /// Renvoie la longueur de la chaîne de noeuds
-    pub fn len(&self) -> i16 {
-        let mut node: &Self = self;
-        let mut i: i16 = 1;
-        loop {
-            match node.next_node {
-                None => return i,
-                Some (ref n) => {
-                    i += 1;
-                    node = n.deref();
-                },
-            }
-        }
-    }
-
-    /// Renvoie une option du prochain noeud
-    pub fn get_next(&self) -> Option<&Self> {
-        match self.next_node {
-            None => None,
-            Some (ref n) => Some(n.deref())
-        }
+        SnakeNode { x, y }
     }
-   
 }
 
 /// Implémentation d'un serpent
 impl Snake {    
-    /// Renvoie un nouveau serpent
+    /// Renvoie un nouveau serpent, nommé d'après l'identifiant du joueur
     ///
     /// # Arguments
     ///
@@ -169,84 +129,71 @@ impl Snake {
     /// * `initial_direction` - Direction initiale du serpent
     /// * `initial_moving` - Intention initiale de mouvement du serpent (devrait être égale à `initial_direction`)
     pub fn new(id: u8, color: (u8, u8, u8), x: i16, y: i16, initial_direction: Move, initial_moving: Move) -> Self {
-        let mut head: SnakeNode = SnakeNode::new(x, y);
-        let tail: SnakeNode = SnakeNode::new(x, y);
-        head.next_node = Some(Box::new(tail));
+        let mut body: VecDeque<SnakeNode> = VecDeque::new();
+        body.push_back(SnakeNode::new(x, y));
+        body.push_back(SnakeNode::new(x, y));
         Snake {
             id,
+            name: format!("Joueur {}", id),
             color,
-            head,
+            body,
             direction: initial_direction,
             moving: initial_moving,
             has_lost: false,
             stomach: 10,
-            boost: false,
+            boost: 0,
+            invisible: 0,
+            kills: 0,
+            max_length: 1,
         }
     }
 
-    /// Crée un nouveau noeud lors d'un virage
-    fn add_snake_node(&mut self) {
-        if self.moving != self.direction {
-            let mut new_node: SnakeNode = SnakeNode::new(self.head.x, self.head.y);
-            self.insert_node(&mut new_node);
-        }
+    /// Renvoie la tête du serpent (premier noeud)
+    pub fn head(&self) -> &SnakeNode {
+        &self.body[0]
     }
 
-    /// Insère un nouveau noeud au serpent
-    ///
-    /// # Arguments
-    ///
-    /// * `node` - Le nouveau noeud
-    fn insert_node(&mut self, node: &mut SnakeNode) {
-        match &self.head.next_node {
-            None => (),
-            Some(n) => node.next_node = Some(n.clone()),
-        }
-        self.head.next_node = Some(Box::new(node.clone()));
-    }
-
-    /// Renvoie une référence mutable vers la queue du serpent
-    fn get_tail(&mut self) -> &mut SnakeNode {
-        let mut node: &mut SnakeNode = &mut self.head;
-        loop {
-            match node.next_node {
-                None => return node,
-                Some(ref mut n) => {
-                    node = n.deref_mut();
-                }
-            }
-        }
+    /// Renvoie une référence mutable vers la tête du serpent
+    fn head_mut(&mut self) -> &mut SnakeNode {
+        &mut self.body[0]
     }
 
-    /// Enlève la queue du serpent
-    fn back_tail(&mut self) {
-        let mut len: i16 = self.head.len() - 1;
-        let mut node: &mut SnakeNode = &mut self.head;
-        while len > 1 {
-            match node.next_node {
-                None => panic!("SnakeNode::len misfunctioned"),
-                Some(ref mut n) => {
-                    node = n.deref_mut();
-                    len -= 1;
-                }
-            }            
+    /// Crée un nouveau noeud lors d'un virage : le coin est laissé à la position actuelle de la tête
+    fn add_snake_node(&mut self) {
+        if self.moving != self.direction {
+            self.body.push_front(*self.head());
         }
-        node.next_node = None;
     }
 
     /// Renvoie les coordonnées de l'avant-dernier noeud (avant la queue)
     fn get_position_of_node_before_tail(&self) -> (i16, i16) {
-        let mut node: &SnakeNode = &self.head;
-        let mut next_node: &SnakeNode = &node.next_node.as_ref().unwrap().deref();
-        loop {
-            match next_node.next_node {
-                None => return (node.x, node.y),
-                Some(ref n) => {
-                    node = next_node;
-                    next_node = n.deref();
-                }
+        let node = &self.body[self.body.len() - 2];
+        (node.x, node.y)
+    }
+
+    /// Renvoie les cases occupées par le serpent, de la tête à la queue (une case par bloc)
+    fn cells(&self) -> Vec<(i16, i16)> {
+        let head = self.head();
+        let mut cells = vec![(head.x, head.y)];
+        for (node, next_node) in self.body.iter().zip(self.body.iter().skip(1)) {
+            let (dx, dy) = ((next_node.x - node.x).signum(), (next_node.y - node.y).signum());
+            let (mut x, mut y) = (node.x, node.y);
+            while (x, y) != (next_node.x, next_node.y) {
+                x += dx;
+                y += dy;
+                cells.push((x, y));
             }
         }
+        cells
+    }
+
+    /// Renvoie la longueur du serpent en blocs
+    pub fn length(&self) -> u32 {
+        let mut length: u32 = 1;
+        for (node, next_node) in self.body.iter().zip(self.body.iter().skip(1)) {
+            length += ((node.x - next_node.x).abs() + (node.y - next_node.y).abs()) as u32;
+        }
+        length
     }
 
     /// Change l'intention de mouvement du serpent
@@ -279,8 +226,15 @@ impl Snake {
         }
     }
     
-    /// Met à jour la position du serpent (tête et queue)
-    pub fn update(&mut self) {
+    /// Met à jour la position du serpent (tête et queue).
+    /// Sur une map torique, les noeuds restent alignés de part et d'autre d'un bord : quand la tête
+    /// sort de la map, tout le serpent est décalé d'une largeur (ou d'une hauteur) pour la ramener sur la map,
+    /// et les noeuds suivants peuvent se trouver en dehors.
+    ///
+    /// # Arguments
+    ///
+    /// `torus` - Dimensions de la map si elle est torique
+    pub fn update(&mut self, torus: Option<(i16, i16)>) {
         if self.has_lost{
             return ();
         }
@@ -290,23 +244,23 @@ impl Snake {
             Move::Up => {
                 // L'ordre est très important.
                 self.add_snake_node();
-                self.head.y -= 1;
+                self.head_mut().y -= 1;
                 self.direction = Move::Up;
             }
             Move::Down => {
                 self.add_snake_node();
-                self.head.y += 1;
+                self.head_mut().y += 1;
                 self.direction = Move::Down;
             }
             Move::Right => {
                 self.add_snake_node();
-                self.head.x += 1;
+                self.head_mut().x += 1;
                 self.direction = Move::Right;
             }
 
             Move::Left => {
                 self.add_snake_node();
-                self.head.x -= 1;
+                self.head_mut().x -= 1;
                 self.direction = Move::Left;
             }
             
@@ -319,67 +273,42 @@ impl Snake {
         } else {
             // La queue doit suivre le serpent
             let (x, y) = self.get_position_of_node_before_tail();
-            let tail: &mut SnakeNode = self.get_tail();
+            let tail: &SnakeNode = self.body.back().unwrap();
             if tail.x == x && tail.y == y {
-                self.back_tail();
-            } 
+                // La queue a atteint le dernier coin
+                self.body.pop_back();
+            }
 
             let (x, y) = self.get_position_of_node_before_tail();
-            let tail: &mut SnakeNode = self.get_tail();
+            let tail: &mut SnakeNode = self.body.back_mut().unwrap();
             if tail.x == x {
                 tail.y += (y - tail.y) / (y - tail.y).abs();
             } else if tail.y == y {
                 tail.x += (x - tail.x) / (x - tail.x).abs();
             }
         }
-    }
 
-    /// Indique si une coordonnée appartient au serpent
-    /// 
-    /// # Arguments
-    /// 
-    /// `x`, `y` - Les coordonnées dont on veut vérifier l'appartenance
-    /// `id` - L'identifiant du serpent qui rentre potentiellement en collision 
-    fn contains(&self, x: i16, y: i16, id: u8) -> bool {
-        let mut node = &self.head;
-        let mut next_node = node.next_node.as_ref().unwrap().deref();
-        if id == self.id {
-            if next_node.has_next() {
-                node = next_node;
-                next_node = node.next_node.as_ref().unwrap().deref();
-            } else {
-                return false;
+        // Passage d'un bord à l'autre
+        if let Some((width, height)) = torus {
+            let head = *self.head();
+            let dx = if head.x < 0 { width } else if head.x >= width { -width } else { 0 };
+            let dy = if head.y < 0 { height } else if head.y >= height { -height } else { 0 };
+            if dx != 0 || dy != 0 {
+                self.shift(dx, dy);
             }
         }
+    }
 
-        loop {
-            // Segment vertical
-            if node.x == next_node.x {
-                if x == node.x {
-                    if cmp::min(node.y, next_node.y) <= y && y <= cmp::max(node.y, next_node.y) {
-                        return true;
-                    }
-                }
-            }
-            
-            // Segment horizontal
-            if node.y == next_node.y {
-                if y == node.y {
-                    if cmp::min(node.x, next_node.x) <= x && x <= cmp::max(node.x, next_node.x) {
-                        return true
-                    }
-                }
-            }
-
-            if next_node.has_next() {
-                node = next_node;
-                next_node = node.next_node.as_ref().unwrap().deref();
-            } else {
-                break;
-            }
+    /// Décale tous les noeuds du serpent
+    ///
+    /// # Arguments
+    ///
+    /// `dx`, `dy` - Décalage en blocs
+    fn shift(&mut self, dx: i16, dy: i16) {
+        for node in self.body.iter_mut() {
+            node.x += dx;
+            node.y += dy;
         }
-        
-        false
     }
 }
 
@@ -399,6 +328,7 @@ impl Food {
         match &self.food_type {
             FoodType::Apple => 1,
             FoodType::Mango => 2,
+            FoodType::Invisibility => 3,
         }
     }
 
@@ -406,20 +336,6 @@ impl Food {
     pub fn get_info_for_data_trs(&self) -> (u8, i16, i16) {
         (self.food_type_to_food_id(), self.x, self.y)
     }
-
-    /// Réinitialise la nourriture (change ses coordonnées aléatoirement)
-    ///
-    /// # Arguments
-    /// 
-    /// `dimensions` - Taille de la map en blocs
-    fn reset(&mut self, dimensions: u16) {
-        let mut rng = rand::thread_rng();
-        let rd = Uniform::from(2..dimensions - 2);
-        let x = rd.sample(&mut rng);
-        let y = rd.sample(&mut rng);
-        self.x = x as i16;
-        self.y = y as i16;
-    }
 }
 
 /// Implémentation d'une partie
@@ -428,82 +344,185 @@ impl Game {
     ///
     /// # Arguments
     ///
-    /// * `dimensions` - Taille de la map en blocs
-    /// * `frequency` - Fréquence de mouvement
-    pub fn new(dimensions: u16, frequency: u8) -> Self {
-        let mut rng = rand::thread_rng();
-        let rd = Uniform::from(2..dimensions - 2);
-        let food_x0 = rd.sample(&mut rng) as i16;
-        let food_y0 = rd.sample(&mut rng) as i16;
-        Game {
-            dimensions,
-            food: vec![Food::new(food_x0, food_y0, FoodType::Apple)],
+    /// * `config` - Configuration du serveur (fréquence de mouvement, nourriture...)
+    /// * `map` - Map de la partie
+    /// * `seed` - Graine du générateur aléatoire de la partie
+    pub fn new(config: Arc<Config>, map: Arc<Map>, seed: u64) -> Self {
+        let rng = StdRng::seed_from_u64(seed);
+        let mut game = Game {
+            grid: Grid::new(&map),
+            map,
+            food: vec![],
             waiting: false,
-            period: 1.0 / (frequency as f64), // Période entre deux mouvements (~framerate)
+            period: 1.0 / (config.initial_speed as f64), // Période entre deux mouvements (~framerate)
             progress: 0.0,
             players: vec![],
+            config,
+            rng,
+        };
+        if let Some((food_x0, food_y0)) = game.free_food_tile() {
+            game.add_food(Food::new(food_x0, food_y0, FoodType::Apple));
         }
+        game
     }
 
-    /// Met à jour un serpent.
-    /// Renvoie une option avec l'instant de mise à jour si le serpent a reçu un boost.
+    /// Renvoie les nourritures présentes sur la map
+    pub fn food(&self) -> &[Food] {
+        &self.food
+    }
+
+    /// Fait avancer la partie d'un tick : tous les serpents sont déplacés dans la même étape,
+    /// puis les serpents sous l'effet d'un boost sont déplacés une seconde fois.
+    pub fn step(&mut self) {
+        for snake in self.players.iter_mut() {
+            snake.invisible = snake.invisible.saturating_sub(1);
+        }
+
+        let movers: Vec<usize> = (0..self.players.len())
+            .filter(|&index| !self.players[index].has_lost)
+            .collect();
+        self.move_snakes(&movers);
+
+        let boosted: Vec<usize> = movers.into_iter()
+            .filter(|&index| !self.players[index].has_lost && self.players[index].boost > 0)
+            .collect();
+        for &index in &boosted {
+            self.players[index].boost -= 1;
+        }
+        self.move_snakes(&boosted);
+
+        for snake in self.players.iter_mut() {
+            snake.max_length = cmp::max(snake.max_length, snake.length());
+        }
+    }
+
+    /// Déplace simultanément plusieurs serpents : toutes les nouvelles têtes sont calculées,
+    /// puis toutes les issues sont résolues en même temps (voir `collision::resolve`),
+    /// de sorte que le résultat ne dépend pas de l'ordre des joueurs.
     ///
     /// # Arguments
     /// 
-    /// `index` - L'indice du serpent dans le vecteur des joueurs
-    pub fn update_snake(&mut self, index: usize) -> Option<time::Instant> {
-        let snake: &mut Snake = self.players.get_mut(index).unwrap();
-        snake.update();
-        let id = snake.id;
-        let x = snake.head.x;
-        let y = snake.head.y;
-
-        match self.check_tile(x, y, id) {
-            TileType::Nothing => (),
-            TileType::SnakeTile(snake_id) => self.killed(id, snake_id),
-            TileType::Wall => self.killed(id, id),
-            TileType::FoodTile(food_type) => {
-                match food_type {
-                    FoodType::Apple => {
-                        // Allonge le serpent
-                        self.feed(id, FOOD_BY_APPLE);
-                        self.reset_food(x, y);
-                    },
-                    FoodType::Mango => {
-                        // Donne un coup de boost temporaire au serpent
-                        let snake: &mut Snake = self.players.get_mut(index).unwrap();
-                        snake.boost = true;
-                        self.delete_food(x, y);
-                    },
-                };
-
-                if self.food.len() < MAX_FOOD {
-                    // Quand de la nourriture est mangée, peu importe son type, il est possible qu'un nouveau apparaisse
-                    let mut rng = rand::thread_rng();
-                    let rd = Uniform::from(0..2);
-                    // Une chance sur deux que ce soit le cas
-                    if rd.sample(&mut rng) as u8 == 1 {
-                        let rd = Uniform::from(2..self.dimensions - 2);
-                        let food_x = rd.sample(&mut rng) as i16;
-                        let food_y = rd.sample(&mut rng) as i16;
-                        let rd = Uniform::from(1..N_FOOD_TYPES + 1);
-                        /* 
-                        // Le type de la nouvelle nourriture générée est choisie aléatoirement, mais à partir de 2 (pas de pommes)
-                        let rd = Uniform::from(2..N_FOOD_TYPES + 1); 
-                        */
-                        let food_id = rd.sample(&mut rng) as u64;
-                        self.food.push(Food::new(food_x, food_y, food_id_to_type(food_id)));
-                    }
-                }
-                
+    /// `movers` - Les indices des serpents à déplacer dans le vecteur des joueurs
+    fn move_snakes(&mut self, movers: &[usize]) {
+        let mut moves: Vec<collision::HeadMove> = vec![];
+        for &index in movers {
+            let snake: &mut Snake = self.players.get_mut(index).unwrap();
+            let from = (snake.head().x, snake.head().y);
+            let tail = *snake.body.back().unwrap();
+            let grows = snake.stomach > 0;
+            snake.update(self.map.torus());
+            let to = (snake.head().x, snake.head().y);
+
+            // La tête occupe une nouvelle case et, si le serpent ne grandit pas, la queue en libère une
+            self.grid.add_snake(snake.id, to.0, to.1);
+            if !grows {
+                self.grid.remove_snake(snake.id, tail.x, tail.y);
+            }
+            moves.push(collision::HeadMove { id: snake.id, from, to });
+        }
+
+        let outcomes = collision::resolve(&moves, |x, y, id| self.check_tile(x, y, id));
+
+        for (head_move, outcome) in moves.iter().zip(outcomes) {
+            match outcome {
+                collision::Outcome::Safe => (),
+                collision::Outcome::Killed(murderer) => self.killed(head_move.id, murderer),
+                collision::Outcome::Eats(food_type) => self.eat(head_move.id, head_move.to, food_type),
+            }
+        }
+    }
 
-                if food_type == FoodType::Mango {
-                    return Some(time::Instant::now());
+    /// Traite la nourriture mangée par un serpent
+    ///
+    /// # Arguments
+    /// 
+    /// `player_id` - Identifiant du joueur qui mange
+    /// `(x, y)` - Coordonnées de la nourriture
+    /// `food_type` - Type de la nourriture
+    fn eat(&mut self, player_id: u8, (x, y): (i16, i16), food_type: FoodType) {
+        match food_type {
+            FoodType::Apple => {
+                // Allonge le serpent
+                self.feed(player_id, self.config.food_by_apple);
+                self.reset_food(x, y);
+            },
+            FoodType::Mango => {
+                // Donne un coup de boost temporaire au serpent
+                let index = self.get_player(player_id).unwrap();
+                self.players[index].boost = self.config.boost_ticks();
+                self.delete_food(x, y);
+            },
+            FoodType::Invisibility => {
+                // Cache temporairement le serpent à ses adversaires ; il reste un obstacle
+                let index = self.get_player(player_id).unwrap();
+                self.players[index].invisible = self.config.invisibility_ticks();
+                self.delete_food(x, y);
+            },
+        };
+
+        if self.food.len() < self.config.max_food {
+            // Quand de la nourriture est mangée, peu importe son type, il est possible qu'un nouveau apparaisse
+            let rd = Uniform::from(0..2);
+            // Une chance sur deux que ce soit le cas
+            if rd.sample(&mut self.rng) as u8 == 1 {
+                let food_type = self.random_food_type();
+                if let Some((food_x, food_y)) = self.free_food_tile() {
+                    self.add_food(Food::new(food_x, food_y, food_type));
                 }
             }
         }
+    }
 
-        None
+    /// Renvoie le type d'une nouvelle nourriture, tiré selon les poids de la configuration
+    fn random_food_type(&mut self) -> FoodType {
+        let types = [
+            (FoodType::Apple, self.config.apple_weight),
+            (FoodType::Mango, self.config.mango_weight),
+            (FoodType::Invisibility, self.config.invisibility_weight),
+        ];
+        // La configuration garantit qu'au moins un poids est strictement positif
+        let weights = WeightedIndex::new(types.iter().map(|(_, weight)| *weight)).unwrap();
+        types[weights.sample(&mut self.rng)].0.clone()
+    }
+
+    /// Renvoie une case où faire apparaître une nourriture : libre (ni mur, ni nourriture, ni serpent)
+    /// et à distance des têtes des serpents en vie, ou None si aucune case ne convient.
+    /// Quelques cases candidates de la map sont tirées au hasard, puis la case est choisie
+    /// parmi toutes les cases candidates qui conviennent.
+    fn free_food_tile(&mut self) -> Option<(i16, i16)> {
+        for _ in 0..FOOD_TILE_ATTEMPTS {
+            let (x, y) = self.map.food_tile(&mut self.rng);
+            if self.can_spawn_food(x, y) {
+                return Some((x, y));
+            }
+        }
+
+        let tiles: Vec<(i16, i16)> = self.map.food_tiles().into_iter()
+            .filter(|&(x, y)| self.can_spawn_food(x, y))
+            .collect();
+        tiles.choose(&mut self.rng).copied()
+    }
+
+    /// Renvoie si une nourriture peut apparaître sur une case
+    ///
+    /// # Arguments
+    ///
+    /// `x`, `y` - Les coordonnées de la case
+    fn can_spawn_food(&self, x: i16, y: i16) -> bool {
+        let min_distance = self.config.food_head_distance as i16;
+        self.grid.is_free(x, y) && self.players.iter()
+            .filter(|snake| !snake.has_lost)
+            .all(|snake| self.grid.distance((snake.head().x, snake.head().y), (x, y)) >= min_distance)
+    }
+
+    /// Ajoute une nourriture sur la map
+    ///
+    /// # Arguments
+    ///
+    /// `food` - La nouvelle nourriture
+    fn add_food(&mut self, food: Food) {
+        self.grid.set_food(food.x, food.y, Some(food.food_type.clone()));
+        self.food.push(food);
     }
 
     /// Supprime une nourriture
@@ -521,67 +540,86 @@ impl Game {
                 i += 1;
             }
         }
+        self.grid.set_food(x, y, None);
     }
 
 
-    /// Réinitialise une nourriture (changement aléatoire des coordonnées)
+    /// Réinitialise une nourriture : elle est déplacée sur une case libre choisie au hasard,
+    /// ou supprimée s'il n'y en a aucune
     /// 
     /// # Arguments
     /// 
-    /// `food_type` - Le type de la nourriture
+    /// `x`, `y` - Les coordonnées de la nourriture
     fn reset_food(&mut self, x: i16, y: i16) {
-        for i in 0..self.food.len() {
-            let food = self.food.get_mut(i).unwrap();
-            if food.x == x && food.y == y {
-                food.reset(self.dimensions);
-                return ();
+        let index = match self.food.iter().position(|food| food.x == x && food.y == y) {
+            Some(index) => index,
+            None => return,
+        };
+        match self.free_food_tile() {
+            Some((new_x, new_y)) => {
+                let food = &mut self.food[index];
+                food.x = new_x;
+                food.y = new_y;
+                let food_type = food.food_type.clone();
+                self.refresh_food_tile(x, y);
+                self.grid.set_food(new_x, new_y, Some(food_type));
+            }
+            None => {
+                self.food.remove(index);
+                self.refresh_food_tile(x, y);
             }
         }
     }
 
+    /// Remet à jour la nourriture d'une case de la grille, qui peut en contenir plusieurs
+    ///
+    /// # Arguments
+    ///
+    /// `x`, `y` - Les coordonnées de la case
+    fn refresh_food_tile(&mut self, x: i16, y: i16) {
+        let food_type = self.food.iter()
+            .find(|food| food.x == x && food.y == y)
+            .map(|food| food.food_type.clone());
+        self.grid.set_food(x, y, food_type);
+    }
+
     /// Renvoie le type d'une case
     /// 
     /// # Arguments
     /// 
     /// `x`, `y` - Les coordonnées de la case à vérifier
     /// `id` - Identifiant du joueur souhaitant vérifier
-    fn check_tile(&mut self, x: i16, y: i16, id: u8) -> TileType {
-        for snake in &self.players {
-            if snake.contains(x, y, id) {
-                return TileType::SnakeTile(snake.id);
-            }
-        }
-
-        for food in &self.food {
-            if food.x == x && food.y == y {
-                return TileType::FoodTile(food.food_type.clone());        
-            }
-        }
-
-        if x < 1 || y < 1 || x >= self.dimensions as i16 -1 || y >= self.dimensions as i16 - 1 {
-            return TileType::Wall;
-        }
-
-        TileType::Nothing
+    fn check_tile(&self, x: i16, y: i16, id: u8) -> TileType {
+        // La tête du joueur qui vérifie la case ne compte pas comme un obstacle
+        let own_head = self.get_player(id)
+            .map(|index| self.players[index].head())
+            .filter(|head| self.grid.same_cell((head.x, head.y), (x, y)))
+            .map(|_| id);
+        self.grid.tile(x, y, own_head)
     }
 
-    /// Traite le meurtre d'un serpent.
-    /// Si `DEV_NO_DEATH`, aucun serpent ne meurt effectivement.
+    /// Traite le meurtre d'un serpent ; le meurtrier est crédité d'un serpent tué, sauf s'il s'agit du serpent lui-même.
+    /// Si `dev_no_death` est activé dans la configuration, aucun serpent ne meurt effectivement.
     /// 
     /// # Arguments
     /// 
     /// `murdered` - identifiant du joueur tué
     /// `murderer` - identifiant du joueur qui a tué
     fn killed(&mut self, murdered: u8, murderer: u8) {
-        println!("Le serpent {} a tué le serpent {} !", murderer, murdered);
-        if DEV_NO_DEATH {
+        println!("{} a tué {} !", self.player_name(murderer), self.player_name(murdered));
+        if self.config.dev_no_death {
             ()
         } else {
             self.set_lost(murdered);
+            if murderer != murdered {
+                if let Some(index) = self.get_player(murderer) {
+                    self.players[index].kills += 1;
+                }
+            }
         }
     }
     
-    /// Nourrit un serpent (rajoute des unités à son estomac)
+    /// Nourrit un serpent (rajoute des unités à son estomac, qui sature à `u8::MAX`)
     /// 
     /// # Arguments
     /// 
@@ -589,7 +627,8 @@ impl Game {
     /// `food` - Nombre d'unités à rajouter à l'estomac
     fn feed(&mut self, player_id: u8, food: u8) {
         let index = self.get_player(player_id).unwrap();
-        self.players.get_mut(index).unwrap().stomach += food;
+        let snake = self.players.get_mut(index).unwrap();
+        snake.stomach = snake.stomach.saturating_add(food);
     }
 
     /// Ajoute un nouveau joueur à la partie
@@ -598,18 +637,44 @@ impl Game {
     /// 
     /// `player_id` - Identifiant du joueur
     /// `color` - Couleur du joueur (triplet RGB)
-    /// `x0`, `y0` - Coordonnées initiales du joueur 
-    pub fn add_player(&mut self, player_id: u8, color: (u8, u8, u8), x0: u16, y0: u16) -> () {
-        let (initial_direction, initial_moving): (Move, Move) = match (x0 > self.dimensions / 2, y0 > self.dimensions / 2) {
-            (false, false) => (Move::Down, Move::Down),
-            (false, true) => (Move::Right, Move::Right),
-            (true, false) => (Move::Left, Move::Left),
-            (true, true) => (Move::Up, Move::Up),
-        };
+    /// `name` - Pseudo du joueur
+    /// `spawn` - Point d'apparition du joueur (coordonnées et direction initiales)
+    pub fn add_player(&mut self, player_id: u8, color: (u8, u8, u8), name: String, spawn: &Spawn) {
+        let mut snake = Snake::new(player_id, color, spawn.x as i16, spawn.y as i16, spawn.direction, spawn.direction);
+        snake.name = name;
+        self.add_snake(snake);
+    }
+
+    /// Ajoute un serpent à la partie et l'inscrit dans la grille d'occupation
+    ///
+    /// # Arguments
+    ///
+    /// `snake` - Le nouveau serpent
+    fn add_snake(&mut self, snake: Snake) {
+        // Une nourriture ne doit pas se trouver sous le serpent : elle est déplacée
+        let mut covered_food = vec![];
+        for (x, y) in snake.cells() {
+            if let TileType::FoodTile(_) = self.grid.tile(x, y, None) {
+                covered_food.push((x, y));
+            }
+            self.grid.add_snake(snake.id, x, y);
+        }
+        self.players.push(snake);
+        for (x, y) in covered_food {
+            self.reset_food(x, y);
+        }
+    }
 
-        self.players.push(
-            Snake::new(player_id, color, x0 as i16, y0 as i16, initial_direction, initial_moving)
-        );
+    /// Renvoie le pseudo d'un joueur, ou son identifiant s'il n'est pas dans la partie
+    ///
+    /// # Arguments
+    ///
+    /// * `player_id` - L'identifiant du joueur
+    pub fn player_name(&self, player_id: u8) -> String {
+        match self.get_player(player_id) {
+            Some(index) => self.players[index].name.clone(),
+            None => player_id.to_string(),
+        }
     }
 
     /// Renvoie une option de l'index du joueur dans la liste des joueurs.
@@ -638,60 +703,474 @@ impl Game {
         self.players[index].has_lost = true;
     }
 
+    /// Renvoie la map de la partie
+    pub fn map(&self) -> &Map {
+        &self.map
+    }
+
     /// Renvoie un vecteur des identifiants des joueurs encore en vie
     pub fn get_alive(&self) -> Vec<u8> {
-        let mut alive: Vec<u8> = vec![];
-        for player_id in 1..((self.players.len() + 1) as u8) {
-            let index: usize = self.get_player(player_id).unwrap();
-            if !self.players[index].has_lost {
-                alive.push(player_id);
-            }
-        }
-        alive
+        self.players_alive().iter().map(|snake| snake.id).collect()
     }
 
-    /// Renvoie un vecteur de références vers les serpents encore en vie
+    /// Renvoie un vecteur de références vers les serpents encore en vie, par identifiant croissant
+    /// (les identifiants ne se suivent pas forcément : un joueur a pu quitter la file avant la partie)
     pub fn players_alive(&self) -> Vec<&Snake> {
-        let alive: Vec<u8> = self.get_alive();
-        let mut snakes_alive : Vec<&Snake> = vec![];
-        for id in alive {
-            snakes_alive.push(self.players.get(self.get_player(id).unwrap()).unwrap());
+        let mut alive: Vec<&Snake> = self.players.iter().filter(|snake| !snake.has_lost).collect();
+        alive.sort_by_key(|snake| snake.id);
+        alive
+    }
+}
+
+/// Résolution simultanée des collisions d'un tick.
+///
+/// Toutes les têtes ayant déjà été déplacées, chaque issue est calculée à partir du même état :
+/// * deux têtes qui arrivent sur la même case meurent toutes les deux ;
+/// * deux têtes qui échangent leurs positions meurent toutes les deux ;
+/// * une collision frontale ne rapporte de kill à personne : chaque serpent est son propre responsable ;
+/// * une tête qui entre dans un mur ou dans le corps d'un serpent meurt ;
+/// * une nourriture n'est mangée que par une tête seule sur sa case : si plusieurs têtes
+///   arrivent sur la même nourriture, elles se percutent et la nourriture reste en place.
+mod collision {
+    use super::{FoodType, TileType};
+
+    /// Déplacement de la tête d'un serpent au cours d'un tick
+    pub struct HeadMove {
+        /// Identifiant du serpent
+        pub id: u8,
+        /// Position de la tête avant le déplacement
+        pub from: (i16, i16),
+        /// Position de la tête après le déplacement
+        pub to: (i16, i16),
+    }
+
+    /// Issue du déplacement d'un serpent
+    #[derive(Debug, PartialEq)]
+    pub enum Outcome {
+        /// Le serpent avance sans rien rencontrer
+        Safe,
+        /// Le serpent mange une nourriture
+        Eats(FoodType),
+        /// Le serpent meurt ; contient l'identifiant du responsable (lui-même s'il s'agit d'un mur)
+        Killed(u8),
+    }
+
+    /// Renvoie l'issue de chaque déplacement, dans le même ordre que `moves`.
+    ///
+    /// # Arguments
+    ///
+    /// `moves` - Slice vers les déplacements des têtes de ce tick
+    /// `tile` - Renvoie le type d'une case (coordonnées, identifiant du serpent qui y entre),
+    /// évalué après le déplacement de tous les serpents
+    pub fn resolve<F: Fn(i16, i16, u8) -> TileType>(moves: &[HeadMove], tile: F) -> Vec<Outcome> {
+        moves.iter().map(|head_move| {
+            // Collision frontale : plusieurs têtes sur la même case, ou deux têtes qui se croisent
+            let other = moves.iter().find(|other| {
+                other.id != head_move.id
+                    && (other.to == head_move.to || (other.to == head_move.from && other.from == head_move.to))
+            });
+            if other.is_some() {
+                return Outcome::Killed(head_move.id);
+            }
+
+            let (x, y) = head_move.to;
+            match tile(x, y, head_move.id) {
+                TileType::Nothing => Outcome::Safe,
+                TileType::Wall => Outcome::Killed(head_move.id),
+                TileType::SnakeTile(snake_id) => Outcome::Killed(snake_id),
+                TileType::FoodTile(food_type) => Outcome::Eats(food_type),
+            }
+        }).collect()
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        fn empty(_x: i16, _y: i16, _id: u8) -> TileType {
+            TileType::Nothing
+        }
+
+        #[test]
+        fn test_resolve_head_on() {
+            let moves = vec![
+                HeadMove { id: 1, from: (9, 10), to: (10, 10) },
+                HeadMove { id: 2, from: (11, 10), to: (10, 10) },
+            ];
+            assert_eq!(resolve(&moves, empty), vec![Outcome::Killed(1), Outcome::Killed(2)]);
+        }
+
+        #[test]
+        fn test_resolve_swap() {
+            let moves = vec![
+                HeadMove { id: 1, from: (10, 10), to: (11, 10) },
+                HeadMove { id: 2, from: (11, 10), to: (10, 10) },
+            ];
+            assert_eq!(resolve(&moves, empty), vec![Outcome::Killed(1), Outcome::Killed(2)]);
+        }
+
+        #[test]
+        fn test_resolve_contested_food() {
+            let moves = vec![
+                HeadMove { id: 1, from: (9, 10), to: (10, 10) },
+                HeadMove { id: 2, from: (10, 11), to: (10, 10) },
+                HeadMove { id: 3, from: (20, 20), to: (20, 21) },
+            ];
+            let food = |x, y, _id| if (x, y) == (10, 10) { TileType::FoodTile(FoodType::Apple) } else { TileType::Nothing };
+            assert_eq!(resolve(&moves, food), vec![Outcome::Killed(1), Outcome::Killed(2), Outcome::Safe]);
+        }
+
+        #[test]
+        fn test_resolve_single_eater() {
+            let moves = vec![
+                HeadMove { id: 1, from: (9, 10), to: (10, 10) },
+                HeadMove { id: 2, from: (10, 12), to: (10, 11) },
+            ];
+            let food = |x, y, _id| if (x, y) == (10, 10) { TileType::FoodTile(FoodType::Mango) } else { TileType::Nothing };
+            assert_eq!(resolve(&moves, food), vec![Outcome::Eats(FoodType::Mango), Outcome::Safe]);
+        }
+
+        #[test]
+        fn test_resolve_wall_and_body() {
+            let moves = vec![
+                HeadMove { id: 1, from: (1, 5), to: (0, 5) },
+                HeadMove { id: 2, from: (5, 5), to: (5, 6) },
+            ];
+            let tile = |x, _y, _id| if x == 0 { TileType::Wall } else { TileType::SnakeTile(3) };
+            assert_eq!(resolve(&moves, tile), vec![Outcome::Killed(1), Outcome::Killed(3)]);
         }
-        snakes_alive
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::config::MAX_FOOD_BY_APPLE;
+
+    /// Renvoie les coordonnées des noeuds d'un serpent, de la tête à la queue
+    fn nodes(snake: &Snake) -> Vec<(i16, i16)> {
+        snake.body.iter().map(|node| (node.x, node.y)).collect()
+    }
 
     #[test]
-    fn test_has_next1() {
-        let node1 = SnakeNode::new(0,0);
-        assert!(!node1.has_next());
+    fn test_new_snake() {
+        let snake = Snake::new(1, (0, 0, 0), 5, 7, Move::Up, Move::Up);
+        assert_eq!(nodes(&snake), vec![(5, 7), (5, 7)]);
+        assert_eq!(snake.head(), &SnakeNode::new(5, 7));
+        assert_eq!(snake.length(), 1);
     }
 
     #[test]
-    fn test_has_next2() {
-        let mut node1 = SnakeNode::new(0,0);
-        let node2 = SnakeNode::new(0,0);
-        node1.next_node = Some (Box::new(node2));
-        assert!(node1.has_next());
+    fn test_update_body() {
+        let mut snake = Snake::new(1, (0, 0, 0), 10, 10, Move::Right, Move::Right);
+        snake.stomach = 3;
+        for _ in 0..4 {
+            snake.update(None);
+        }
+        assert_eq!(nodes(&snake), vec![(14, 10), (11, 10)]);
+
+        // Un virage ajoute un coin derrière la tête
+        snake.change_intent(Move::Down);
+        snake.update(None);
+        snake.update(None);
+        assert_eq!(nodes(&snake), vec![(14, 12), (14, 10), (13, 10)]);
+        assert_eq!(snake.length(), 4);
+
+        // La queue atteint le coin, qui est retiré
+        snake.update(None);
+        snake.update(None);
+        assert_eq!(nodes(&snake), vec![(14, 14), (14, 11)]);
+        assert_eq!(snake.length(), 4);
     }
-    
+
+    /// Renvoie une partie sans nourriture sur une map donnée
+    fn game_without_food(map: Map) -> Game {
+        game_with_config(Config::default(), map)
+    }
+
+    /// Renvoie une partie sans nourriture sur une map donnée, avec une configuration donnée
+    fn game_with_config(config: Config, map: Map) -> Game {
+        let mut game = Game::new(Arc::new(config), Arc::new(map), 0);
+        while let Some(food) = game.food.first() {
+            let (x, y) = (food.x, food.y);
+            game.delete_food(x, y);
+        }
+        game
+    }
+
+    /// Renvoie une partie sans nourriture avec des serpents placés aux coordonnées données
+    fn game_with(snakes: Vec<(u8, i16, i16, Move)>) -> Game {
+        let mut game = game_without_food(Map::empty(64, 64));
+        for (id, x, y, direction) in snakes {
+            game.add_snake(Snake::new(id, (0, 0, 0), x, y, direction, direction));
+        }
+        game
+    }
+
+    #[test]
+    fn test_step_simultaneous_wall() {
+        let mut game = game_with(vec![(1, 61, 10, Move::Right), (2, 61, 20, Move::Right)]);
+        game.step();
+        assert_eq!(game.get_alive(), vec![1, 2]);
+        game.step();
+        assert!(game.get_alive().is_empty());
+    }
+
+    #[test]
+    fn test_step_head_on() {
+        let mut game = game_with(vec![(1, 10, 10, Move::Right), (2, 12, 10, Move::Left), (3, 30, 30, Move::Down)]);
+        game.step();
+        assert_eq!(game.get_alive(), vec![3]);
+        assert!(game.players.iter().all(|snake| snake.kills == 0));
+    }
+
+    #[test]
+    fn test_alive_with_missing_ids() {
+        let mut game = game_with(vec![(3, 10, 10, Move::Right), (1, 10, 20, Move::Right)]);
+        assert_eq!(game.get_alive(), vec![1, 3]);
+        game.set_lost(3);
+        assert_eq!(game.get_alive(), vec![1]);
+    }
+
+    #[test]
+    fn test_step_swap() {
+        let mut game = game_with(vec![(1, 10, 10, Move::Right), (2, 11, 10, Move::Left)]);
+        game.step();
+        assert!(game.get_alive().is_empty());
+    }
+
+    #[test]
+    fn test_step_order_independent() {
+        // Le serpent 2 entre dans la case que le serpent 1 quitte au même tick : personne ne meurt,
+        // quel que soit l'ordre des joueurs
+        let snakes = vec![(1, 10, 10, Move::Down), (2, 10, 9, Move::Down)];
+        let reversed = vec![(2, 10, 9, Move::Down), (1, 10, 10, Move::Down)];
+        for snakes in [snakes, reversed] {
+            let mut game = game_with(snakes);
+            for player in game.players.iter_mut() {
+                player.stomach = 0;
+            }
+            game.step();
+            assert_eq!(game.get_alive(), vec![1, 2]);
+        }
+    }
+
+    #[test]
+    fn test_check_tile_rectangular() {
+        let game = game_without_food(Map::empty(80, 30));
+        assert!(matches!(game.check_tile(70, 20, 1), TileType::Nothing));
+        assert!(matches!(game.check_tile(79, 20, 1), TileType::Wall));
+        assert!(matches!(game.check_tile(70, 29, 1), TileType::Wall));
+        assert!(matches!(game.check_tile(78, 28, 1), TileType::Nothing));
+    }
+
+    #[test]
+    fn test_step_wrap() {
+        let mut map = Map::empty(64, 64);
+        map.wrap = true;
+        let mut game = game_without_food(map);
+        game.add_snake(Snake::new(1, (0, 0, 0), 62, 10, Move::Right, Move::Right));
+        game.players[0].stomach = 4;
+
+        // La tête traverse le bord droit et revient à gauche
+        for _ in 0..3 {
+            game.step();
+        }
+        assert_eq!(game.get_alive(), vec![1]);
+        assert_eq!(game.players[0].head(), &SnakeNode::new(1, 10));
+        assert!(matches!(game.check_tile(63, 10, 2), TileType::SnakeTile(1)));
+        assert!(matches!(game.check_tile(0, 10, 2), TileType::SnakeTile(1)));
+        assert!(matches!(game.check_tile(2, 10, 2), TileType::Nothing));
+
+        // La queue suit de l'autre côté du bord, puis le serpent traverse le bord du haut
+        game.players[0].change_intent(Move::Up);
+        for _ in 0..13 {
+            game.step();
+        }
+        assert_eq!(game.get_alive(), vec![1]);
+        assert_eq!(game.players[0].head(), &SnakeNode::new(1, 61));
+        assert!(matches!(game.check_tile(63, 10, 2), TileType::Nothing));
+        assert!(matches!(game.check_tile(1, 2, 2), TileType::Nothing));
+        assert!(matches!(game.check_tile(1, 0, 2), TileType::SnakeTile(1)));
+        assert!(matches!(game.check_tile(1, 63, 2), TileType::SnakeTile(1)));
+    }
+
+    #[test]
+    fn test_seed_reproducible() {
+        // Même graine et mêmes mouvements : mêmes apparitions de nourriture
+        let play = |seed: u64| -> Vec<(u8, i16, i16)> {
+            let mut game = Game::new(Arc::new(Config::default()), Arc::new(Map::empty(64, 64)), seed);
+            game.add_snake(Snake::new(1, (0, 0, 0), 10, 10, Move::Right, Move::Right));
+            for i in 0..20 {
+                game.add_food(Food::new(11 + i, 10, FoodType::Apple));
+            }
+            for _ in 0..20 {
+                game.step();
+            }
+            game.food.iter().map(|food| food.get_info_for_data_trs()).collect()
+        };
+        assert_eq!(play(42), play(42));
+        assert_ne!(play(42), play(43));
+    }
+
+    #[test]
+    fn test_kills_and_length() {
+        // Le serpent 1 entre dans le corps du serpent 2
+        let mut game = game_with(vec![(1, 10, 10, Move::Right), (2, 14, 8, Move::Down)]);
+        for _ in 0..4 {
+            game.step();
+        }
+        assert_eq!(game.get_alive(), vec![2]);
+        assert_eq!((game.players[0].kills, game.players[1].kills), (0, 1));
+        assert_eq!(game.players[1].length(), 5);
+        assert_eq!(game.players[1].max_length, 5);
+
+        // Un mur ne crédite personne
+        let mut game = game_with(vec![(1, 62, 10, Move::Right), (2, 30, 30, Move::Down)]);
+        game.step();
+        game.step();
+        assert_eq!(game.get_alive(), vec![2]);
+        assert_eq!((game.players[0].kills, game.players[1].kills), (0, 0));
+    }
+
+    #[test]
+    fn test_grid_follows_snakes() {
+        // La grille tenue à jour coïncide avec une grille reconstruite à partir des serpents et de la nourriture
+        let mut game = game_with(vec![(1, 10, 10, Move::Right), (2, 30, 40, Move::Up), (3, 50, 50, Move::Left)]);
+        game.add_food(Food::new(14, 10, FoodType::Apple));
+        let turns = [Move::Down, Move::Left, Move::Up, Move::Right];
+        for tick in 0..60 {
+            if tick % 7 == 6 {
+                for player in game.players.iter_mut() {
+                    player.change_intent(turns[(tick / 7) % 4]);
+                }
+            }
+            game.step();
+
+            let mut grid = Grid::new(&game.map);
+            for snake in &game.players {
+                for (x, y) in snake.cells() {
+                    grid.add_snake(snake.id, x, y);
+                }
+            }
+            for food in &game.food {
+                grid.set_food(food.x, food.y, Some(food.food_type.clone()));
+            }
+            for x in 0..64 {
+                for y in 0..64 {
+                    assert_eq!(game.grid.tile(x, y, None), grid.tile(x, y, None), "case ({}, {}), tick {}", x, y, tick);
+                }
+            }
+        }
+        assert_eq!(game.get_alive(), vec![1, 2, 3]);
+    }
+
+    #[test]
+    fn test_food_on_free_tiles() {
+        // Seule la case (13, 13) est libre parmi les cases candidates
+        let mut game = game_without_food(Map::empty(16, 16));
+        for (x, y) in game.map.food_tiles() {
+            if (x, y) != (13, 13) {
+                game.add_food(Food::new(x, y, FoodType::Apple));
+            }
+        }
+        assert_eq!(game.free_food_tile(), Some((13, 13)));
+        game.add_food(Food::new(13, 13, FoodType::Mango));
+        assert_eq!(game.free_food_tile(), None);
+
+        // Une pomme mangée sans case libre disparaît
+        let n_food = game.food.len();
+        game.reset_food(13, 13);
+        assert_eq!(game.food.len(), n_food - 1);
+        assert!(game.grid.is_free(13, 13));
+    }
+
+    #[test]
+    fn test_food_far_from_heads() {
+        let config = Config { food_head_distance: 6, ..Config::default() };
+        let mut game = game_with_config(config, Map::empty(16, 16));
+        game.add_snake(Snake::new(1, (0, 0, 0), 7, 7, Move::Right, Move::Right));
+        for _ in 0..50 {
+            let (x, y) = game.free_food_tile().unwrap();
+            assert!((x - 7).abs() + (y - 7).abs() >= 6);
+        }
+
+        // Aucune case candidate n'est assez loin de la tête ((2, 2) est à 12 blocs)
+        let config = Config { food_head_distance: 13, ..Config::default() };
+        let mut game = game_with_config(config, Map::empty(16, 16));
+        game.add_snake(Snake::new(1, (0, 0, 0), 8, 8, Move::Right, Move::Right));
+        assert_eq!(game.free_food_tile(), None);
+    }
+
+    #[test]
+    fn test_food_weights() {
+        let config = Config { mango_weight: 0, invisibility_weight: 0, ..Config::default() };
+        let mut game = game_with_config(config, Map::empty(16, 16));
+        for _ in 0..50 {
+            assert_eq!(game.random_food_type(), FoodType::Apple);
+        }
+    }
+
+    #[test]
+    fn test_food_weights_ratio() {
+        // Graine fixe : pas de pomme, et trois fois plus d'invisibilité que de mangues
+        let config = Config { apple_weight: 0, mango_weight: 1, invisibility_weight: 3, ..Config::default() };
+        let mut game = game_with_config(config, Map::empty(16, 16));
+        let draws: Vec<FoodType> = (0..4000).map(|_| game.random_food_type()).collect();
+        let count = |food_type: FoodType| draws.iter().filter(|&drawn| *drawn == food_type).count();
+        assert_eq!(count(FoodType::Apple), 0);
+        let mangoes = count(FoodType::Mango);
+        assert!((900..1100).contains(&mangoes), "{} mangues sur 4000", mangoes);
+        assert_eq!(count(FoodType::Invisibility), 4000 - mangoes);
+    }
+
+    #[test]
+    fn test_snake_spawns_over_food() {
+        let mut game = game_without_food(Map::empty(32, 32));
+        game.add_food(Food::new(10, 10, FoodType::Apple));
+        game.add_snake(Snake::new(1, (0, 0, 0), 10, 10, Move::Right, Move::Right));
+        assert_eq!(game.food.len(), 1);
+        assert_ne!((game.food[0].x, game.food[0].y), (10, 10));
+        assert!(matches!(game.grid.tile(game.food[0].x, game.food[0].y, None), TileType::FoodTile(FoodType::Apple)));
+    }
+
+    #[test]
+    fn test_step_invisibility() {
+        let config = Config { invisibility_duration_ms: 500, ..Config::default() };
+        let ticks = config.invisibility_ticks();
+        let mut game = game_with_config(config, Map::empty(64, 64));
+        game.add_snake(Snake::new(1, (0, 0, 0), 10, 10, Move::Right, Move::Right));
+        game.add_snake(Snake::new(2, (0, 0, 0), 10, 20, Move::Right, Move::Right));
+        game.add_food(Food::new(11, 10, FoodType::Invisibility));
+        game.step();
+        assert_eq!(game.players[0].invisible, ticks);
+        assert!(game.food.iter().all(|food| food.food_type != FoodType::Invisibility || (food.x, food.y) != (11, 10)));
+        for _ in 0..ticks {
+            game.step();
+        }
+        assert_eq!(game.players[0].invisible, 0);
+        assert_eq!(game.get_alive(), vec![1, 2]);
+
+        // Un serpent invisible reste un obstacle
+        game.players[0].invisible = 10;
+        let head = *game.players[0].head();
+        assert!(matches!(game.check_tile(head.x - 1, head.y, 2), TileType::SnakeTile(1)));
+    }
+
     #[test]
-    fn test_len1() {
-        let mut node1 = SnakeNode::new(0, 0);
-        let mut node2 = SnakeNode::new(0, 0);
-        let node3 = SnakeNode::new(0, 0);
-        node2.next_node = Some(Box::new(node3));
-        node1.next_node = Some(Box::new(node2));
-        assert_eq!(node1.len(), 3);
+    fn test_step_eat() {
+        let mut game = game_with(vec![(1, 10, 10, Move::Right)]);
+        game.add_food(Food::new(11, 10, FoodType::Apple));
+        game.step();
+        assert_eq!(game.players[0].stomach, 9 + Config::default().food_by_apple);
+        assert!(!game.food.is_empty());
     }
 
     #[test]
-    fn test_len2() {
-        let node1 = SnakeNode::new(0, 0);
-        assert_eq!(node1.len(), 1);
+    fn test_feed_saturates() {
+        let mut game = game_with(vec![(1, 10, 10, Move::Right)]);
+        game.feed(1, MAX_FOOD_BY_APPLE);
+        game.feed(1, MAX_FOOD_BY_APPLE);
+        assert_eq!(game.players[0].stomach, u8::MAX);
     }
 }
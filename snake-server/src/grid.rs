@@ -0,0 +1,227 @@
+use std::cmp;
+
+use crate::game_serv::{FoodType, TileType};
+use crate::map::Map;
+use crate::spawn::MAX_PLAYERS;
+
+/// Contenu d'une case de la grille
+#[derive(Clone, Default)]
+struct Cell {
+    /// Booléen codant si la case est un mur
+    wall: bool,
+    /// Nourriture présente sur la case
+    food: Option<FoodType>,
+    /// Nombre de blocs de chaque serpent sur la case, indexé par identifiant - 1
+    /// (un serpent qui entre dans son propre corps occupe deux fois la même case)
+    snakes: [u16; MAX_PLAYERS as usize],
+}
+
+/// Grille d'occupation d'une partie : contenu de chaque case de la map (murs, nourriture, corps des serpents).
+/// Elle est mise à jour à chaque déplacement de serpent et à chaque apparition ou disparition de nourriture,
+/// de sorte que le type d'une case s'obtient en temps constant.
+///
+/// Sur une map torique, les coordonnées sont ramenées sur la map ; sinon, les cases en dehors de la map
+/// sont des murs et ne sont pas enregistrées.
+pub struct Grid {
+    /// Largeur de la map en blocs
+    width: i16,
+    /// Hauteur de la map en blocs
+    height: i16,
+    /// Map torique
+    wrap: bool,
+    /// Cases de la map, indexées par `y * width + x`
+    cells: Vec<Cell>,
+}
+
+/// Implémentation de la grille d'occupation
+impl Grid {
+    /// Renvoie une grille sans serpent ni nourriture, avec les murs de la map
+    ///
+    /// # Arguments
+    ///
+    /// * `map` - Map de la partie
+    pub fn new(map: &Map) -> Self {
+        let (width, height) = (map.width as i16, map.height as i16);
+        let mut cells = vec![Cell::default(); width as usize * height as usize];
+        for y in 0..height {
+            for x in 0..width {
+                cells[y as usize * width as usize + x as usize].wall = map.is_wall(x, y);
+            }
+        }
+        Grid { width, height, wrap: map.wrap, cells }
+    }
+
+    /// Renvoie l'indice d'une case, ou None si elle est en dehors de la map
+    ///
+    /// # Arguments
+    ///
+    /// * `x`, `y` - Coordonnées de la case
+    fn index(&self, x: i16, y: i16) -> Option<usize> {
+        let (x, y) = if self.wrap {
+            (x.rem_euclid(self.width), y.rem_euclid(self.height))
+        } else {
+            (x, y)
+        };
+        if x < 0 || y < 0 || x >= self.width || y >= self.height {
+            None
+        } else {
+            Some(y as usize * self.width as usize + x as usize)
+        }
+    }
+
+    /// Renvoie si deux coordonnées désignent la même case
+    ///
+    /// # Arguments
+    ///
+    /// * `a`, `b` - Coordonnées des cases
+    pub fn same_cell(&self, a: (i16, i16), b: (i16, i16)) -> bool {
+        a == b || (self.index(a.0, a.1).is_some() && self.index(a.0, a.1) == self.index(b.0, b.1))
+    }
+
+    /// Renvoie la distance de Manhattan entre deux cases, en passant par les bords sur une map torique
+    ///
+    /// # Arguments
+    ///
+    /// * `a`, `b` - Coordonnées des cases
+    pub fn distance(&self, a: (i16, i16), b: (i16, i16)) -> i16 {
+        let (dx, dy) = ((a.0 - b.0).abs(), (a.1 - b.1).abs());
+        if self.wrap {
+            let (dx, dy) = (dx.rem_euclid(self.width), dy.rem_euclid(self.height));
+            cmp::min(dx, self.width - dx) + cmp::min(dy, self.height - dy)
+        } else {
+            dx + dy
+        }
+    }
+
+    /// Renvoie si une case de la map est libre : ni mur, ni nourriture, ni serpent
+    ///
+    /// # Arguments
+    ///
+    /// * `x`, `y` - Coordonnées de la case
+    pub fn is_free(&self, x: i16, y: i16) -> bool {
+        self.tile(x, y, None) == TileType::Nothing
+    }
+
+    /// Ajoute un bloc de serpent sur une case
+    ///
+    /// # Arguments
+    ///
+    /// * `id` - Identifiant du serpent
+    /// * `x`, `y` - Coordonnées de la case
+    pub fn add_snake(&mut self, id: u8, x: i16, y: i16) {
+        if let Some(index) = self.index(x, y) {
+            self.cells[index].snakes[id as usize - 1] += 1;
+        }
+    }
+
+    /// Enlève un bloc de serpent d'une case
+    ///
+    /// # Arguments
+    ///
+    /// * `id` - Identifiant du serpent
+    /// * `x`, `y` - Coordonnées de la case
+    pub fn remove_snake(&mut self, id: u8, x: i16, y: i16) {
+        if let Some(index) = self.index(x, y) {
+            let count = &mut self.cells[index].snakes[id as usize - 1];
+            *count = count.saturating_sub(1);
+        }
+    }
+
+    /// Place ou enlève la nourriture d'une case
+    ///
+    /// # Arguments
+    ///
+    /// * `x`, `y` - Coordonnées de la case
+    /// * `food` - Nourriture de la case, ou None pour la vider
+    pub fn set_food(&mut self, x: i16, y: i16, food: Option<FoodType>) {
+        if let Some(index) = self.index(x, y) {
+            self.cells[index].food = food;
+        }
+    }
+
+    /// Renvoie le type d'une case : un serpent (le plus petit identifiant si plusieurs se recouvrent),
+    /// sinon une nourriture, sinon un mur ou rien.
+    ///
+    /// # Arguments
+    ///
+    /// * `x`, `y` - Coordonnées de la case
+    /// * `own_head` - Identifiant du serpent dont la tête est sur cette case et ne compte pas
+    pub fn tile(&self, x: i16, y: i16, own_head: Option<u8>) -> TileType {
+        let cell = match self.index(x, y) {
+            None => return TileType::Wall,
+            Some(index) => &self.cells[index],
+        };
+
+        for (i, &count) in cell.snakes.iter().enumerate() {
+            let id = i as u8 + 1;
+            let ignored = if own_head == Some(id) { 1 } else { 0 };
+            if count > ignored {
+                return TileType::SnakeTile(id);
+            }
+        }
+
+        match &cell.food {
+            Some(food_type) => TileType::FoodTile(food_type.clone()),
+            None if cell.wall => TileType::Wall,
+            None => TileType::Nothing,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tile() {
+        let mut grid = Grid::new(&Map::empty(32, 32));
+        assert!(matches!(grid.tile(0, 5, None), TileType::Wall));
+        assert!(matches!(grid.tile(-1, 5, None), TileType::Wall));
+        assert!(matches!(grid.tile(5, 5, None), TileType::Nothing));
+
+        grid.set_food(5, 5, Some(FoodType::Mango));
+        assert!(matches!(grid.tile(5, 5, None), TileType::FoodTile(FoodType::Mango)));
+        grid.add_snake(3, 5, 5);
+        assert!(matches!(grid.tile(5, 5, None), TileType::SnakeTile(3)));
+        grid.remove_snake(3, 5, 5);
+        grid.set_food(5, 5, None);
+        assert!(matches!(grid.tile(5, 5, None), TileType::Nothing));
+    }
+
+    #[test]
+    fn test_tile_own_head() {
+        let mut grid = Grid::new(&Map::empty(32, 32));
+        grid.add_snake(1, 5, 5);
+        assert!(matches!(grid.tile(5, 5, Some(1)), TileType::Nothing));
+        assert!(matches!(grid.tile(5, 5, Some(2)), TileType::SnakeTile(1)));
+
+        // La tête est entrée dans le corps du serpent
+        grid.add_snake(1, 5, 5);
+        assert!(matches!(grid.tile(5, 5, Some(1)), TileType::SnakeTile(1)));
+    }
+
+    #[test]
+    fn test_wrap() {
+        let mut map = Map::empty(32, 32);
+        map.wrap = true;
+        let mut grid = Grid::new(&map);
+        assert!(matches!(grid.tile(0, 5, None), TileType::Nothing));
+        grid.add_snake(2, -1, 5);
+        assert!(matches!(grid.tile(31, 5, None), TileType::SnakeTile(2)));
+        assert!(grid.same_cell((31, 5), (-1, 37)));
+        assert!(!grid.same_cell((31, 5), (30, 5)));
+        assert_eq!(grid.distance((1, 5), (30, 6)), 4);
+        assert!(!grid.is_free(31, 5) && grid.is_free(30, 5));
+    }
+
+    #[test]
+    fn test_is_free() {
+        let mut grid = Grid::new(&Map::empty(32, 32));
+        assert!(grid.is_free(5, 5));
+        assert!(!grid.is_free(0, 5));
+        assert!(!grid.is_free(40, 5));
+        grid.set_food(5, 5, Some(FoodType::Apple));
+        assert!(!grid.is_free(5, 5));
+        assert_eq!(grid.distance((1, 5), (30, 6)), 30);
+    }
+}
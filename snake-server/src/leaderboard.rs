@@ -0,0 +1,162 @@
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+
+/// Statistiques cumulées d'un joueur
+#[derive(Serialize, Deserialize, Clone, Default, PartialEq, Debug)]
+#[serde(default)]
+pub struct Stats {
+    /// Nombre de parties gagnées
+    pub wins: u32,
+    /// Nombre de parties perdues
+    pub losses: u32,
+    /// Nombre de serpents tués
+    pub kills: u32,
+    /// Longueur maximale atteinte en blocs
+    pub max_length: u32,
+    /// Dernier pseudo du joueur, affiché dans le classement à la place de son adresse IP
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+}
+
+/// Résultat d'un joueur à la fin d'une partie, envoyé par la boucle de jeu au gestionnaire de salons
+pub struct PlayerResult {
+    /// Identifiant du joueur dans le salon
+    pub player_id: u8,
+    /// Booléen codant si le joueur a gagné la partie
+    pub won: bool,
+    /// Nombre de serpents tués pendant la partie
+    pub kills: u32,
+    /// Longueur maximale atteinte pendant la partie en blocs
+    pub max_length: u32,
+}
+
+/// Contenu du fichier du classement
+#[derive(Serialize, Deserialize, Default)]
+struct LeaderboardFile {
+    /// Statistiques des joueurs, indexées par identité (adresse IP)
+    #[serde(default)]
+    players: BTreeMap<String, Stats>,
+}
+
+/// Classement persistant des joueurs, enregistré dans un fichier TOML après chaque partie
+pub struct Leaderboard {
+    /// Chemin du fichier du classement
+    path: String,
+    /// Statistiques des joueurs, indexées par identité
+    players: BTreeMap<String, Stats>,
+}
+
+/// Implémentation du classement
+impl Leaderboard {
+    /// Charge le classement depuis son fichier ; un fichier absent donne un classement vide.
+    /// Renvoie une erreur lisible si le fichier existe mais est invalide.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - Chemin du fichier du classement
+    pub fn load(path: &str) -> Result<Leaderboard, String> {
+        let players = if Path::new(path).exists() {
+            let content = fs::read_to_string(path).map_err(|e| format!("{} : {}", path, e))?;
+            let file: LeaderboardFile = toml::from_str(&content).map_err(|e| format!("{} : {}", path, e))?;
+            file.players
+        } else {
+            BTreeMap::new()
+        };
+        Ok(Leaderboard { path: path.to_string(), players })
+    }
+
+    /// Ajoute le résultat d'une partie aux statistiques d'un joueur
+    ///
+    /// # Arguments
+    ///
+    /// * `identity` - Identité du joueur (adresse IP)
+    /// * `name` - Option du pseudo du joueur dans la partie, qui remplace le précédent
+    /// * `result` - Résultat du joueur
+    pub fn record(&mut self, identity: &str, name: Option<&str>, result: &PlayerResult) {
+        let stats = self.players.entry(identity.to_string()).or_default();
+        if let Some(name) = name {
+            stats.name = Some(name.to_string());
+        }
+        if result.won {
+            stats.wins += 1;
+        } else {
+            stats.losses += 1;
+        }
+        stats.kills += result.kills;
+        stats.max_length = std::cmp::max(stats.max_length, result.max_length);
+    }
+
+    /// Enregistre le classement dans son fichier
+    pub fn save(&self) -> Result<(), String> {
+        let file = LeaderboardFile { players: self.players.clone() };
+        let content = toml::to_string(&file).map_err(|e| e.to_string())?;
+        fs::write(&self.path, content).map_err(|e| format!("{} : {}", self.path, e))
+    }
+
+    /// Renvoie les `n` meilleurs joueurs, sous leur pseudo ou à défaut leur identité :
+    /// par victoires, puis par serpents tués, puis par longueur maximale, puis par défaites (le moins possible)
+    ///
+    /// # Arguments
+    ///
+    /// * `n` - Nombre de joueurs
+    pub fn top(&self, n: usize) -> Vec<(&str, &Stats)> {
+        let mut ranking: Vec<(&str, &Stats)> = self.players.iter()
+            .map(|(identity, stats)| (stats.name.as_deref().unwrap_or(identity), stats))
+            .collect();
+        ranking.sort_by(|(_, a), (_, b)| {
+            b.wins.cmp(&a.wins)
+                .then(b.kills.cmp(&a.kills))
+                .then(b.max_length.cmp(&a.max_length))
+                .then(a.losses.cmp(&b.losses))
+        });
+        ranking.truncate(n);
+        ranking
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn result(won: bool, kills: u32, max_length: u32) -> PlayerResult {
+        PlayerResult { player_id: 1, won, kills, max_length }
+    }
+
+    #[test]
+    fn test_record_and_top() {
+        let mut leaderboard = Leaderboard { path: String::new(), players: BTreeMap::new() };
+        leaderboard.record("10.0.0.1", None, &result(false, 2, 30));
+        leaderboard.record("10.0.0.2", Some("Bob"), &result(true, 1, 20));
+        leaderboard.record("10.0.0.3", None, &result(false, 3, 10));
+        leaderboard.record("10.0.0.1", Some("Léa"), &result(true, 0, 25));
+        leaderboard.record("10.0.0.1", None, &result(false, 0, 5));
+
+        let top = leaderboard.top(2);
+        assert_eq!(top.len(), 2);
+        assert_eq!(top[0], ("Léa", &Stats { wins: 1, losses: 2, kills: 2, max_length: 30, name: Some("Léa".to_string()) }));
+        assert_eq!(top[1].0, "Bob");
+        assert_eq!(leaderboard.top(10).len(), 3);
+    }
+
+    #[test]
+    fn test_save_and_load() {
+        let path = std::env::temp_dir().join(format!("snecc-leaderboard-{}.toml", std::process::id()));
+        let path = path.to_str().unwrap();
+        let mut leaderboard = Leaderboard::load(path).unwrap();
+        assert!(leaderboard.top(10).is_empty());
+        leaderboard.record("10.0.0.1", None, &result(true, 4, 42));
+        leaderboard.record("10.0.0.2", Some("Bob"), &result(false, 0, 3));
+        leaderboard.save().unwrap();
+
+        let loaded = Leaderboard::load(path).unwrap();
+        fs::write(path, "players = 3").unwrap();
+        assert!(Leaderboard::load(path).is_err());
+        fs::remove_file(path).unwrap();
+        assert_eq!(loaded.top(10), vec![
+            ("10.0.0.1", &Stats { wins: 1, losses: 0, kills: 4, max_length: 42, name: None }),
+            ("Bob", &Stats { wins: 0, losses: 1, kills: 0, max_length: 3, name: Some("Bob".to_string()) }),
+        ]);
+    }
+}
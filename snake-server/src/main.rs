@@ -3,9 +3,10 @@
 
 extern crate rand;
 
-use rand::seq::SliceRandom;
+use std::io;
+use std::sync::Arc;
 use std::net::{TcpListener, TcpStream};
-use std::sync::{Arc, Mutex};
+use std::sync::mpsc::{channel, RecvTimeoutError, Sender};
 use std::{thread, time};
 use std::env;
 use std::process::exit;
@@ -15,17 +16,23 @@ use game_serv::*;
 
 mod protocol;
 
-/// Taille de la map (carrée) en blocs
-const MAP_SIZE: u16 = 64;
+mod config;
+use config::Config;
 
-/// Taille des blocs en pixels
-const BLOCK_SIZE: u16 = 10;
+mod spawn;
 
-/// Fréquence initiale des joueurs
-const INITIAL_SPEED: u8 = 1;
+mod leaderboard;
+use leaderboard::Leaderboard;
 
-/// Période initiale (envoi de frames)
-const INITIAL_PERIOD: time::Duration = time::Duration::from_millis(50);
+mod map;
+use map::Map;
+
+mod grid;
+
+mod replay;
+
+mod room;
+use room::{ClientEvent, Identity, LobbyRequest, RoomManager, Seat};
 
 /// Temps d'attente entre deux cycles de jeu en millisecondes
 const GAME_SLEEP: u64 = 1;
@@ -33,245 +40,227 @@ const GAME_SLEEP: u64 = 1;
 /// Temps de timeout pendant un read en millisecondes
 const READ_TO: u64 = 1;
 
-/// Temps entre deux réceptions d'un mouvement du client
-const INPUT_PERIOD: time::Duration = time::Duration::from_millis(10);
+/// Période d'envoi de l'état de la file d'attente aux joueurs en attente
+const QUEUE_PERIOD: time::Duration = time::Duration::from_millis(500);
 
-/// Durée du boost de vitesse (Mangue) en secondes
-const BOOST_DURATION: time::Duration = time::Duration::from_millis(750);
+/// Temps laissé à un client pour envoyer sa demande de connexion
+const JOIN_TIMEOUT: time::Duration = time::Duration::from_secs(5);
 
-/// Mode développeur "No Death" qui empêche les joueurs de mourir
-const DEV_NO_DEATH : bool = false;
+/// Délai pendant lequel un joueur déconnecté peut se reconnecter à sa partie avant d'être éliminé
+const RECONNECT_GRACE: time::Duration = time::Duration::from_secs(10);
 
-/// Gère un client.
+/// Gère un client. Ce thread ne fait que des entrées/sorties : il négocie la version du protocole,
+/// demande une place au gestionnaire de salons, puis transmet les mouvements reçus à la boucle de jeu
+/// et envoie au client les paquets produits par celle-ci.
 ///
 /// # Arguments
 ///
 /// * `stream` - Référence mutable vers le flux TCP
-/// * `player_id` - Identifiant du joueur
-/// * `n_players` - Nombre de joueurs dans la partie
-/// * `game_mutex` - Arc de mutex vers la partie
-/// * `snake_color` - Couleur du serpent (triplet RGB)
-fn handle_client(stream: &mut TcpStream, player_id: u8, n_players: u8, game_mutex: Arc<Mutex<Game>>, snake_color: (u8, u8, u8)) -> () {
-    // On envoie le player_id au client
-    protocol::send_player_id(stream, player_id);
-    println!("[{}] Sent player id", player_id);
+/// * `lobby` - Canal vers le gestionnaire de salons
+/// * `input_period` - Temps minimal entre deux réceptions d'un mouvement du client
+fn handle_client(stream: &mut TcpStream, lobby: Sender<LobbyRequest>, input_period: time::Duration) {
+    let features = match handshake(stream) {
+        Ok(features) => features,
+        Err(e) => {
+            println!("Connexion refusée : {}", e);
+            return;
+        }
+    };
+    let seat = match join(stream, &lobby, features) {
+        Ok(Some(seat)) => seat,
+        // Demande du classement : la connexion est fermée une fois la réponse envoyée
+        Ok(None) => return,
+        Err(e) => {
+            println!("Connexion refusée : {}", e);
+            return;
+        }
+    };
 
-    // On génère les données du nouveau joueur
-    // Détermination de la position initiale du joueur
-    let offset_x : u16 = 4;
-    let offset_y : u16 = 4;
-    let x : u16;
-    let y : u16;
-    match player_id {
-        1 => {
-            x = offset_x;
-            y = offset_y;
-        },
-        2 => {
-            x = offset_x;
-            y = MAP_SIZE - offset_y;
-        },
-        3 => {
-            x = MAP_SIZE - offset_x;
-            y = offset_y;
-        },
-        4 => {
-            x = MAP_SIZE - offset_x;
-            y = MAP_SIZE - offset_y;
+    if let Err(e) = client_io(stream, &seat, features, input_period) {
+        if e.kind() == io::ErrorKind::InvalidData {
+            // Message invalide : seule cette connexion est fermée, le joueur peut se reconnecter
+            println!("[{}] Erreur de protocole : {}", seat.player_id, e);
+        } else {
+            println!("[{}] Déconnecté : {}", seat.player_id, e);
         }
-        _ => panic!("Max number of players supported is 4 for now."),
+        // La boucle de jeu peut déjà être terminée
+        let _ = seat.events.send(ClientEvent::Disconnected(seat.player_id, seat.connection));
     }
 
-    // Ajout du nouveau joueur
-    {
-        let mut game = game_mutex.lock().unwrap();
-        game.add_player(player_id, snake_color, x, y);
-    }
+    println!("Fermeture de la connection avec {}.", seat.player_id);
+}
 
-    println!("[{}] Added player", player_id);
+/// Négocie la version du protocole, premier échange avec le client : le client est refusé avec une raison
+/// lisible si sa version diffère de celle du serveur, sinon il reçoit les fonctionnalités communes, qui sont renvoyées.
+///
+/// # Arguments
+///
+/// * `stream` - Référence mutable vers le flux TCP
+fn handshake(stream: &mut TcpStream) -> io::Result<u32> {
+    stream.set_read_timeout(Some(JOIN_TIMEOUT))?;
+    let reason = match protocol::get_version(stream) {
+        Ok((protocol::PROTOCOL_VERSION, features)) => {
+            let features = features & protocol::SUPPORTED_FEATURES;
+            protocol::send_version(stream, features)?;
+            return Ok(features);
+        }
+        Ok((version, _)) => format!(
+            "version du protocole {} non prise en charge (serveur : version {}), mettez à jour le client",
+            version, protocol::PROTOCOL_VERSION,
+        ),
+        Err(ref e) if e.kind() == io::ErrorKind::InvalidData => format!(
+            "version du protocole attendue en premier message (serveur : version {}), mettez à jour le client",
+            protocol::PROTOCOL_VERSION,
+        ),
+        Err(e) => return Err(e),
+    };
+    protocol::send_reject(stream, &reason)?;
+    Err(io::Error::new(io::ErrorKind::InvalidData, reason))
+}
 
-    // On attend que la partie soit prête à démarrer (i.e. que tous les threads aient reçu un joueur)
-    loop {
-        {
-            let game = game_mutex.lock().unwrap();
-            if game.players.len() == n_players as usize {
-                break;
-            }
+/// Reçoit la présentation éventuelle du client (son pseudo), puis sa demande (nouvelle partie, reconnexion,
+/// spectateur ou classement) et renvoie la place attribuée par le gestionnaire de salons.
+/// Une demande du classement reçoit sa réponse directement et ne donne pas de place (*None*).
+///
+/// # Arguments
+///
+/// * `stream` - Référence mutable vers le flux TCP
+/// * `lobby` - Canal vers le gestionnaire de salons
+/// * `features` - Fonctionnalités négociées avec le client
+fn join(stream: &mut TcpStream, lobby: &Sender<LobbyRequest>, features: u32) -> io::Result<Option<Seat>> {
+    let nickname = if features & protocol::FEATURE_NICKNAMES != 0 { protocol::get_hello(stream)? } else { None };
+    let identity = Identity {
+        address: stream.peer_addr()?.ip().to_string(),
+        nickname: nickname.and_then(|raw| room::sanitize_nickname(&raw)),
+    };
+    let (reply_tx, reply_rx) = channel::<Seat>();
+    let closed = || io::Error::other("gestionnaire de salons arrêté");
+    let request = match protocol::get_join(stream)? {
+        protocol::Join::New => LobbyRequest::Join(identity, reply_tx),
+        protocol::Join::Reconnect(token) => LobbyRequest::Reconnect(token, identity, reply_tx),
+        protocol::Join::Spectate(room_id) => {
+            let (seat_tx, seat_rx) = channel::<Result<Seat, String>>();
+            lobby.send(LobbyRequest::Spectate(room_id, seat_tx)).map_err(|_| closed())?;
+            return match seat_rx.recv().map_err(|_| closed())? {
+                Ok(seat) => Ok(Some(seat)),
+                Err(reason) => {
+                    protocol::send_reject(stream, &reason)?;
+                    Err(io::Error::new(io::ErrorKind::NotFound, reason))
+                }
+            };
         }
-        // On dort un peu pour pas monopoliser le verrou
-        thread::sleep(time::Duration::from_millis(200 as u64));
-    }
-    println!("[{}] Ready", player_id);
-
-    // Les n clients sont prêts ; on peut envoyer les paramètres du jeu
-    let mut players: Vec<(u8, (u8, u8, u8), i16, i16)> = vec![];
-    {
-        let mut game = game_mutex.lock().unwrap();
-        for i in 0..(n_players as usize) {
-            let player = game.players.get_mut(i).unwrap();
-            players.push((player.id, player.color, player.head.x, player.head.y));
+        protocol::Join::Leaderboard(_) if features & protocol::FEATURE_LEADERBOARD == 0 => {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "classement demandé sans l'avoir négocié"));
         }
-    }
-    protocol::send_game_params(stream, MAP_SIZE, BLOCK_SIZE, INITIAL_SPEED, n_players, players);
-    
-    println!("[{}] Sent game params", player_id);
+        protocol::Join::Leaderboard(n) => {
+            let (packet_tx, packet_rx) = channel::<Result<Vec<u8>, protocol::ProtocolError>>();
+            lobby.send(LobbyRequest::Leaderboard(n, packet_tx)).map_err(|_| closed())?;
+            protocol::send_packet(stream, &packet_rx.recv().map_err(|_| closed())??)?;
+            return Ok(None);
+        }
+    };
+    lobby.send(request).map_err(|_| closed())?;
+    reply_rx.recv().map(Some).map_err(|_| closed())
+}
 
-    match stream.set_read_timeout(Some(time::Duration::from_millis(READ_TO))) {
-        Err(e) => panic!("{}", e), // erreur ?
-        Ok(_) => (),
-    }
+/// Échanges avec un client jusqu'à la fin de la partie.
+/// Renvoie une erreur si le client s'est déconnecté en cours de route.
+///
+/// # Arguments
+///
+/// * `stream` - Référence mutable vers le flux TCP
+/// * `seat` - Place du joueur
+/// * `features` - Fonctionnalités négociées avec le client ; les paquets des autres fonctionnalités ne lui sont pas envoyés
+/// * `input_period` - Temps minimal entre deux réceptions d'un mouvement du client
+fn client_io(stream: &mut TcpStream, seat: &Seat, features: u32, input_period: time::Duration) -> io::Result<()> {
+    let player_id = seat.player_id;
+
+    // On envoie le player_id et le jeton de session au client (0 et 0 pour un spectateur)
+    protocol::send_player_id(stream, player_id, seat.token)?;
+    println!("[{}] Sent player id", player_id);
+
+    stream.set_read_timeout(Some(time::Duration::from_millis(READ_TO)))?;
 
-    let mut last_frame = time::Instant::now();
     let mut last_input = time::Instant::now();
-    let mut last_boost = time::Instant::now();
-    let mut alive: Vec<u8>;
-    let snake_index: usize = {
-        let game = game_mutex.lock().unwrap();
-        game.get_player(player_id).unwrap()
-    };
 
     // Boucle principale
     loop {
-
         // Réception d'un mouvement du client
-        if last_input.elapsed() > INPUT_PERIOD {
-            let player_move: Option<Move> = protocol::get_move_empty_buff(stream);
-            match player_move {
-                None => (),
-                Some(mv) => {
-                    last_input = time::Instant::now();
-                    let mut game = game_mutex.lock().unwrap();
-                    // On change l'intention du serpent du client
-                    game.players[snake_index].change_intent(mv);
-                },
+        if last_input.elapsed() > input_period {
+            if let Some(mv) = protocol::get_move_empty_buff(stream)? {
+                last_input = time::Instant::now();
+                let _ = seat.events.send(ClientEvent::Move(player_id, mv));
             }
         }
-        
-        thread::sleep(time::Duration::from_millis(1));
-        
-        // Assez de temps s'est écoulé depuis la dernière frame
-        if last_frame.elapsed() > INITIAL_PERIOD {
-            {
-                let mut game = game_mutex.lock().unwrap();
-                
-                match game.update_snake(snake_index) {
-                    None => (),
-                    Some(instant) => {
-                        last_boost = instant;
-                    }
-                }
-
-                if game.players[snake_index].boost {
-                    let additional_updates: usize = 1;
-                    for _i in 0..additional_updates {
-                        game.update_snake(snake_index);
-                    }
-                }
 
-                if last_boost.elapsed() > BOOST_DURATION {
-                    game.players[snake_index].boost = false;
+        // Envoi des paquets produits par la boucle de jeu
+        match seat.outbox.recv_timeout(time::Duration::from_millis(GAME_SLEEP)) {
+            Ok(packet) => {
+                if protocol::required_feature(&packet) & !features == 0 {
+                    protocol::send_packet(stream, &packet)?;
                 }
-
-                protocol::send_frame(stream, &game.food, &game.players_alive());
-            }
-            last_frame = time::Instant::now();
-        }
-
-        thread::sleep(time::Duration::from_millis(1));
-
-        // Vérification du nombre de joueurs encore vivants
-        {
-            let mut game = game_mutex.lock().unwrap();
-            alive = game.get_alive();
-            if alive.len() < std::cmp::min(2, n_players as usize) {
-                game.update_snake(snake_index);
-                break;
             }
+            Err(RecvTimeoutError::Timeout) => (),
+            // La partie est terminée et toutes les frames ont été envoyées,
+            // ou le joueur s'est reconnecté sur une autre connexion
+            Err(RecvTimeoutError::Disconnected) => return Ok(()),
         }
-        
-        thread::sleep(time::Duration::from_millis(GAME_SLEEP));
-    }
-
-    thread::sleep(time::Duration::from_millis(GAME_SLEEP));
-
-    // On envoie au moins une nouvelle frame pour signaler la ou les dernières morts
-    {
-        let game = game_mutex.lock().unwrap();
-        protocol::send_frame(stream, &game.food, &game.players_alive());
-    }
-
-    if alive.len() == 0 {
-        // Tout le monde est mort
-        println!("Tout le monde est mort !");
-    } else if alive.len() == 1 {
-        // On a un gagnant
-        println!("{} a gagné", alive[0]);
     }
-
-    drop(stream);
-    println!("Fermeture de la connection avec {}.", player_id);
 }
 
 fn main() {
-    if env::args().len() != 3 {
-        println!("Usage: ./snake-server port n_players");
+    if env::args().len() < 2 {
+        println!("Usage: ./snake-server port [--config file] [--key value]...");
         exit(0);
     }
 
     let args: Vec<String> = env::args().collect();
     let port: u16 = args[1].parse::<u16>().unwrap();
-    let n_players: u8 = args[2].parse::<u8>().unwrap();
-
-    if n_players < 1 || n_players > 4 {
-        println!("Number of players should be between 1 and 4.");
-        exit(0);
-    }
+    let config = match Config::from_args(&args[2..]) {
+        Ok(config) => Arc::new(config),
+        Err(e) => {
+            println!("Invalid configuration: {}", e);
+            exit(1);
+        }
+    };
+    println!("Configuration: {:?}", config);
+    let map = match Map::from_config(&config) {
+        Ok(map) => Arc::new(map),
+        Err(e) => {
+            println!("Invalid map: {}", e);
+            exit(1);
+        }
+    };
 
     let listener = TcpListener::bind(format!("0.0.0.0:{}", port)).unwrap();
     println!("Listening on port {}", port);
 
-    let mut player_id: u8;
-    loop {
-        // Permutation aléatoire des couleurs
-        let mut snake_colors: Vec<(u8, u8, u8)> = vec![
-            (0x4C, 0x3B, 0xE3),
-            (0xDA, 0xAD, 0xFF),
-            (0xF6, 0x83, 0x03),
-            (0xF7, 0x49, 0x80),
-            (0x9A, 0xF4, 0x96),
-            (0x91, 0x67, 0x9D),
-            (0xE1, 0x1C, 0x2F),
-            (0x97, 0x99, 0x13),
-        ];
-        snake_colors.shuffle(&mut rand::thread_rng());
-
-        let game_mutex = Arc::new(Mutex::new(Game::new(MAP_SIZE, INITIAL_SPEED)));
-        let mut handles: Vec<std::thread::JoinHandle<()>> = vec![];
-        player_id = 0;
-
-        for stream in listener.incoming() {
-            match stream {
-                Ok(mut stream) => {
-                    player_id += 1;
-                    println!("New connection: {}", stream.peer_addr().unwrap());
-
-                    let snake_color = snake_colors[player_id as usize];
-
-                    let game_mutex = Arc::clone(&game_mutex);
-                    let handle = thread::spawn(move || handle_client(&mut stream, player_id, n_players, game_mutex, snake_color));
-                    handles.push(handle);
-                }
-                Err(e) => {
-                    println!("Error: {}", e);
+    // Le serveur accepte des clients en continu : le matchmaking se fait dans son propre thread,
+    // et les parties dans les leurs
+    let (lobby_tx, lobby_rx) = channel::<LobbyRequest>();
+    let leaderboard = match Leaderboard::load(&config.leaderboard_file) {
+        Ok(leaderboard) => leaderboard,
+        Err(e) => {
+            println!("Invalid leaderboard: {}", e);
+            exit(1);
+        }
+    };
+    let rooms = RoomManager::new(config.clone(), map, leaderboard);
+    thread::spawn(move || rooms.run(lobby_rx));
+
+    for stream in listener.incoming() {
+        match stream {
+            Ok(mut stream) => {
+                match stream.peer_addr() {
+                    Ok(addr) => println!("New connection: {}", addr),
+                    Err(e) => println!("New connection: {}", e),
                 }
+                let lobby = lobby_tx.clone();
+                let input_period = config.input_period();
+                thread::spawn(move || handle_client(&mut stream, lobby, input_period));
             }
-            if player_id >= n_players {
-                // Les threads des N joueurs sont en cours ; on attend qu'ils finissent leur partie.
-                for handle in handles {
-                    handle.join().unwrap();
-                }
-                drop(game_mutex);
-                break;
+            Err(e) => {
+                println!("Error: {}", e);
             }
         }
     }
@@ -0,0 +1,352 @@
+use rand::distributions::{Distribution, Uniform};
+use rand::seq::SliceRandom;
+use rand::Rng;
+use std::fs;
+
+use crate::config::Config;
+use crate::game_serv::Move;
+use crate::spawn::{self, Spawn};
+
+/// Dimension minimale (largeur et hauteur) d'une map en blocs
+pub const MIN_DIMENSION: u16 = 16;
+
+/// Map d'une partie : dimensions, murs intérieurs, points d'apparition et zones d'apparition de la nourriture.
+///
+/// Format d'un fichier de map (grille ASCII, une ligne par rangée de blocs) :
+/// * `#` : mur
+/// * `.` ou espace : case vide
+/// * `*` : case d'une zone d'apparition de la nourriture
+/// * `^`, `v`, `<`, `>` : point d'apparition d'un serpent, tourné dans cette direction
+///
+/// La largeur est celle de la plus longue ligne, la hauteur le nombre de lignes.
+/// Le pourtour de la grille est toujours un mur, comme sur une map sans fichier, sauf sur une map torique.
+#[derive(Debug)]
+pub struct Map {
+    /// Largeur de la map en blocs
+    pub width: u16,
+    /// Hauteur de la map en blocs
+    pub height: u16,
+    /// Map torique : le pourtour n'est pas un mur, les bords opposés se rejoignent
+    pub wrap: bool,
+    /// Murs intérieurs (hors pourtour), indexés par `y * width + x`
+    walls: Vec<bool>,
+    /// Points d'apparition définis par la map (vide : placement automatique)
+    spawns: Vec<Spawn>,
+    /// Cases où la nourriture peut apparaître (vide : n'importe où sur la map)
+    food_zones: Vec<(u16, u16)>,
+}
+
+/// Implémentation d'une map
+impl Map {
+    /// Renvoie une map vide (sans mur intérieur) de dimensions données
+    ///
+    /// # Arguments
+    ///
+    /// * `width`, `height` - Dimensions de la map en blocs
+    pub fn empty(width: u16, height: u16) -> Self {
+        Map {
+            width,
+            height,
+            wrap: false,
+            walls: vec![false; width as usize * height as usize],
+            spawns: vec![],
+            food_zones: vec![],
+        }
+    }
+
+    /// Renvoie la map décrite par la configuration : le fichier `map_file` s'il est donné,
+    /// sinon une map vide de dimensions `map_width` x `map_height`, torique si `wrap` est activé.
+    /// Renvoie une erreur lisible si le fichier est invalide ou si la map ne peut pas accueillir `max_players` joueurs.
+    ///
+    /// # Arguments
+    ///
+    /// * `config` - Configuration du serveur
+    pub fn from_config(config: &Config) -> Result<Map, String> {
+        let mut map = match &config.map_file {
+            None => Map::empty(config.map_width, config.map_height),
+            Some(path) => {
+                let content = fs::read_to_string(path).map_err(|e| format!("{} : {}", path, e))?;
+                Map::parse(&content).map_err(|e| format!("{} : {}", path, e))?
+            }
+        };
+        map.wrap = config.wrap;
+
+        let capacity = map.capacity();
+        if config.max_players > capacity {
+            return Err(format!("la map ne peut accueillir que {} joueurs (max_players = {})", capacity, config.max_players));
+        }
+        Ok(map)
+    }
+
+    /// Lit une map au format ASCII
+    ///
+    /// # Arguments
+    ///
+    /// * `content` - Contenu du fichier de map
+    pub fn parse(content: &str) -> Result<Map, String> {
+        let lines: Vec<&str> = content.trim_end().lines().collect();
+        let width = lines.iter().map(|line| line.chars().count()).max().unwrap_or(0);
+        let height = lines.len();
+        if width < MIN_DIMENSION as usize || height < MIN_DIMENSION as usize || width > u16::MAX as usize || height > u16::MAX as usize {
+            return Err(format!("dimensions invalides ({}x{}), minimum {}x{}", width, height, MIN_DIMENSION, MIN_DIMENSION));
+        }
+
+        let mut map = Map::empty(width as u16, height as u16);
+        for (y, line) in lines.iter().enumerate() {
+            for (x, tile) in line.chars().enumerate() {
+                let (x, y) = (x as u16, y as u16);
+                let direction = match tile {
+                    // Le pourtour est toujours un mur : seuls les murs intérieurs sont enregistrés
+                    '#' => {
+                        map.walls[y as usize * width + x as usize] = !map.is_border(x as i16, y as i16);
+                        continue;
+                    }
+                    '.' | ' ' => continue,
+                    '*' => {
+                        map.food_zones.push((x, y));
+                        continue;
+                    }
+                    '^' => Move::Up,
+                    'v' => Move::Down,
+                    '<' => Move::Left,
+                    '>' => Move::Right,
+                    _ => return Err(format!("caractère inconnu '{}' (ligne {}, colonne {})", tile, y + 1, x + 1)),
+                };
+                if map.is_border(x as i16, y as i16) {
+                    return Err(format!("point d'apparition sur le pourtour (ligne {}, colonne {})", y + 1, x + 1));
+                }
+                map.spawns.push(Spawn { x, y, direction });
+            }
+        }
+        Ok(map)
+    }
+
+    /// Renvoie si une case est sur le pourtour de la map (ou en dehors)
+    ///
+    /// # Arguments
+    ///
+    /// * `x`, `y` - Coordonnées de la case
+    fn is_border(&self, x: i16, y: i16) -> bool {
+        x < 1 || y < 1 || x >= self.width as i16 - 1 || y >= self.height as i16 - 1
+    }
+
+    /// Renvoie si une case est un mur : le pourtour de la map ou un mur intérieur.
+    /// Sur une map torique, le pourtour n'est pas un mur et les coordonnées sont ramenées sur la map.
+    ///
+    /// # Arguments
+    ///
+    /// * `x`, `y` - Coordonnées de la case
+    pub fn is_wall(&self, x: i16, y: i16) -> bool {
+        if self.wrap {
+            let (x, y) = (x.rem_euclid(self.width as i16), y.rem_euclid(self.height as i16));
+            return self.walls[y as usize * self.width as usize + x as usize];
+        }
+        self.is_border(x, y) || self.walls[y as usize * self.width as usize + x as usize]
+    }
+
+    /// Renvoie les dimensions de la map si elle est torique
+    pub fn torus(&self) -> Option<(i16, i16)> {
+        if self.wrap {
+            Some((self.width as i16, self.height as i16))
+        } else {
+            None
+        }
+    }
+
+    /// Renvoie les murs intérieurs (hors pourtour) sous forme de segments horizontaux (x, y, longueur)
+    pub fn wall_runs(&self) -> Vec<(u16, u16, u16)> {
+        let mut runs = vec![];
+        for y in 0..self.height {
+            let mut x = 0;
+            while x < self.width {
+                if self.walls[y as usize * self.width as usize + x as usize] {
+                    let start = x;
+                    while x < self.width && self.walls[y as usize * self.width as usize + x as usize] {
+                        x += 1;
+                    }
+                    runs.push((start, y, x - start));
+                } else {
+                    x += 1;
+                }
+            }
+        }
+        runs
+    }
+
+    /// Renvoie le nombre maximal de joueurs que la map peut accueillir
+    pub fn capacity(&self) -> u8 {
+        if self.spawns.is_empty() {
+            // Le placement automatique ne doit faire apparaître aucun serpent dans un mur, ni face à un mur
+            (1..=spawn::capacity(self.width, self.height))
+                .take_while(|&n| !spawn::plan(n as usize, self.width, self.height).iter().any(|spawn| self.faces_wall(spawn)))
+                .last()
+                .unwrap_or(0)
+        } else {
+            std::cmp::min(self.spawns.len(), spawn::MAX_PLAYERS as usize) as u8
+        }
+    }
+
+    /// Renvoie les points d'apparition de `n` serpents : répartis parmi ceux définis par la map,
+    /// ou placés automatiquement si la map n'en définit pas
+    ///
+    /// # Arguments
+    ///
+    /// * `n` - Nombre de serpents
+    pub fn spawns(&self, n: usize) -> Vec<Spawn> {
+        if self.spawns.is_empty() {
+            spawn::plan(n, self.width, self.height)
+        } else {
+            (0..n).map(|i| self.spawns[i * self.spawns.len() / n]).collect()
+        }
+    }
+
+    /// Renvoie si un point d'apparition est dans un mur ou juste devant un mur
+    ///
+    /// # Arguments
+    ///
+    /// * `spawn` - Point d'apparition
+    fn faces_wall(&self, spawn: &Spawn) -> bool {
+        let (x, y) = (spawn.x as i16, spawn.y as i16);
+        let (dx, dy) = match spawn.direction {
+            Move::Up => (0, -1),
+            Move::Down => (0, 1),
+            Move::Left => (-1, 0),
+            Move::Right => (1, 0),
+        };
+        self.is_wall(x, y) || self.is_wall(x + dx, y + dy)
+    }
+
+    /// Renvoie la distance minimale entre une nourriture et le bord de la map, hors zones de nourriture :
+    /// 2 cases à partir du pourtour, aucune sur une map torique qui n'a pas de pourtour
+    fn food_margin(&self) -> u16 {
+        if self.wrap { 0 } else { 2 }
+    }
+
+    /// Renvoie une case candidate au hasard pour l'apparition de nourriture : dans une zone de nourriture
+    /// si la map en définit, sinon n'importe où à distance du pourtour (voir `food_margin`). La case peut être un mur :
+    /// la partie vérifie qu'elle est libre (voir `food_tiles`).
+    ///
+    /// # Arguments
+    ///
+    /// * `rng` - Générateur aléatoire
+    pub fn food_tile<R: Rng>(&self, rng: &mut R) -> (i16, i16) {
+        if let Some(&(x, y)) = self.food_zones.choose(rng) {
+            return (x as i16, y as i16);
+        }
+
+        let margin = self.food_margin();
+        let rd_x = Uniform::from(margin..self.width - margin);
+        let rd_y = Uniform::from(margin..self.height - margin);
+        (rd_x.sample(rng) as i16, rd_y.sample(rng) as i16)
+    }
+
+    /// Renvoie toutes les cases candidates pour l'apparition de nourriture (celles que peut renvoyer `food_tile`),
+    /// sans les murs
+    pub fn food_tiles(&self) -> Vec<(i16, i16)> {
+        if !self.food_zones.is_empty() {
+            return self.food_zones.iter().map(|&(x, y)| (x as i16, y as i16)).collect();
+        }
+
+        let margin = self.food_margin() as i16;
+        let mut tiles = vec![];
+        for y in margin..self.height as i16 - margin {
+            for x in margin..self.width as i16 - margin {
+                if !self.is_wall(x, y) {
+                    tiles.push((x, y));
+                }
+            }
+        }
+        tiles
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Map de 16x16 avec un mur intérieur, deux points d'apparition et une zone de nourriture
+    fn small_map() -> String {
+        let mut lines = vec!["################".to_string()];
+        lines.push("#>.............#".to_string());
+        lines.push("#..####........#".to_string());
+        lines.push("#.......**.....#".to_string());
+        for _ in 0..10 {
+            lines.push("#..............#".to_string());
+        }
+        lines.push("#.............<#".to_string());
+        lines.push("################".to_string());
+        lines.join("\n")
+    }
+
+    #[test]
+    fn test_parse() {
+        let map = Map::parse(&small_map()).unwrap();
+        assert_eq!((map.width, map.height), (16, 16));
+        assert!(map.is_wall(3, 2) && map.is_wall(6, 2) && !map.is_wall(7, 2));
+        assert!(map.is_wall(0, 5) && map.is_wall(15, 5) && map.is_wall(5, 15));
+        assert_eq!(map.wall_runs(), vec![(3, 2, 4)]);
+        assert_eq!(map.capacity(), 2);
+        assert_eq!(map.spawns(2), vec![
+            Spawn { x: 1, y: 1, direction: Move::Right },
+            Spawn { x: 14, y: 14, direction: Move::Left },
+        ]);
+    }
+
+    #[test]
+    fn test_food_tile() {
+        let map = Map::parse(&small_map()).unwrap();
+        let mut rng = rand::thread_rng();
+        for _ in 0..20 {
+            let tile = map.food_tile(&mut rng);
+            assert!(tile == (8, 3) || tile == (9, 3));
+        }
+
+        assert_eq!(map.food_tiles(), vec![(8, 3), (9, 3)]);
+
+        let map = Map::empty(16, 16);
+        for _ in 0..20 {
+            let (x, y) = map.food_tile(&mut rng);
+            assert!(!map.is_wall(x, y));
+        }
+        assert_eq!(map.food_tiles().len(), 12 * 12);
+    }
+
+    #[test]
+    fn test_parse_invalid() {
+        assert!(Map::parse("#####\n#...#\n#####").is_err());
+        assert!(Map::parse(&small_map().replace("#..####", "#..##?#")).is_err());
+        assert!(Map::parse(&small_map().replace("#>", ">.")).is_err());
+    }
+
+    #[test]
+    fn test_empty() {
+        let map = Map::empty(64, 40);
+        assert!(map.wall_runs().is_empty());
+        assert!(map.is_wall(63, 10) && map.is_wall(10, 39) && !map.is_wall(62, 38));
+        assert_eq!(map.capacity(), spawn::capacity(64, 40));
+    }
+
+    #[test]
+    fn test_wrap() {
+        let mut map = Map::parse(&small_map()).unwrap();
+        map.wrap = true;
+        assert!(!map.is_wall(0, 5) && !map.is_wall(15, 5) && !map.is_wall(5, 15));
+        assert!(map.is_wall(3, 2) && map.is_wall(19, 2) && map.is_wall(3, -14));
+        assert_eq!(map.torus(), Some((16, 16)));
+        assert_eq!(Map::empty(16, 16).torus(), None);
+    }
+
+    #[test]
+    fn test_wrap_food_tiles() {
+        // Sans pourtour, les cases au bord de la map peuvent recevoir de la nourriture
+        let mut map = Map::empty(16, 16);
+        map.wrap = true;
+        let tiles = map.food_tiles();
+        assert_eq!(tiles.len(), 16 * 16);
+        assert!(tiles.contains(&(0, 0)) && tiles.contains(&(15, 7)) && tiles.contains(&(1, 15)));
+
+        let mut rng = rand::thread_rng();
+        let edge = (0..1000).map(|_| map.food_tile(&mut rng)).any(|(x, y)| x < 2 || y < 2 || x >= 14 || y >= 14);
+        assert!(edge);
+    }
+}
@@ -1,340 +1,545 @@
 use std::net::TcpStream;
-use std::io::{Write, Read};
-use std::ops::Shr;
+use std::io::{self, Write};
+use std::time;
+
+use snecc_protocol::{self as wire, Message, Msg};
 
 use crate::Snake;
-use crate::SnakeNode;
 use crate::Food;
 use crate::Move;
+use crate::map::Map;
+use crate::leaderboard::Stats;
 
-/// Différents types de messages
-#[derive(PartialEq, Debug)]
-enum Msg {
-    PlayerId,
-    GameParams,
-    GameStart,
-    Frame,
-    Move,
-    UnknownId,
+pub use snecc_protocol::{PROTOCOL_VERSION, FEATURE_NICKNAMES, FEATURE_LEADERBOARD, FEATURE_QUEUE_STATUS};
+pub use snecc_protocol::{PlayerParams, ProtocolError};
+
+/// Fonctionnalités prises en charge par le serveur
+pub const SUPPORTED_FEATURES: u32 = FEATURE_NICKNAMES | FEATURE_LEADERBOARD | FEATURE_QUEUE_STATUS;
+
+/// Temps laissé au client pour finir d'envoyer un paquet dont l'en-tête est arrivé
+const PACKET_TIMEOUT: time::Duration = time::Duration::from_secs(1);
+
+/// Envoie un message au client.
+/// Renvoie une erreur si le client s'est déconnecté ou si le message est trop long.
+/// 
+/// # Arguments
+/// 
+/// `stream` - Référence mutable vers le flux
+/// `message` - Référence vers le message à envoyer
+fn send_message<W: Write, M: Message>(stream: &mut W, message: &M) -> io::Result<()> {
+    send_packet(stream, &message.packet()?)
 }
 
-/// Renvoie un objet message à partir de son identifiant
-fn id_to_msg(id: u8) -> Msg {
-    match id {
-        0 => Msg::PlayerId,
-        1 => Msg::GameParams,
-        2 => Msg::GameStart,
-        3 => Msg::Frame,
-        4 => Msg::Move,
-        _ => Msg::UnknownId,
-    }
+/// Envoie un paquet déjà construit (par exemple par `frame_packet`) au client.
+/// Renvoie une erreur si le client s'est déconnecté.
+/// 
+/// # Arguments
+/// 
+/// `stream` - Référence mutable vers le flux
+/// `packet` - Slice vers les octets du paquet
+pub fn send_packet<W: Write>(stream: &mut W, packet: &[u8]) -> io::Result<()> {
+    stream.write_all(packet)
 }
 
-/// Renvoie l'identifiant numérique associé à un message
-fn msg_to_id(id: Msg) -> u8 {
-    match id {
-        Msg::PlayerId => 0,
-        Msg::GameParams => 1,
-        Msg::GameStart => 2,
-        Msg::Frame => 3,
-        Msg::Move => 4,
-        Msg::UnknownId => {
-            panic!("Unknown message ID");
-        },
+/// Lit sans les consommer les octets disponibles (le flux doit avoir un timeout de lecture).
+/// Renvoie le nombre d'octets lus (0 si rien n'est arrivé), ou une erreur si le client a fermé la connexion.
+/// 
+/// # Arguments
+/// 
+/// `stream` - Référence vers le flux TCP
+/// `buf` - Tampon recevant les octets lus
+fn peek(stream: &TcpStream, buf: &mut [u8]) -> io::Result<usize> {
+    match stream.peek(buf) {
+        Ok(0) => Err(io::Error::new(io::ErrorKind::UnexpectedEof, "connexion fermée par le client")),
+        Ok(n) => Ok(n),
+        Err(ref e) if e.kind() == io::ErrorKind::WouldBlock || e.kind() == io::ErrorKind::TimedOut => Ok(0),
+        Err(e) => Err(e),
     }
 }
 
-/// Transforme un entier en un vecteur d'octets (little-endian)
+/// Indique si l'en-tête d'un paquet est entièrement disponible en lecture (le flux doit avoir un timeout de lecture).
+/// Un en-tête arrivé en partie n'est pas consommé et sera lu une fois complet.
+/// Renvoie une erreur si le client a fermé la connexion.
 /// 
 /// # Arguments
 /// 
-/// `n` - Entier 32 bits à transformer
-/// `sz` - Taille en octets du vecteur de sortie
-pub fn split_bytes(mut n: u32, sz: usize) -> Vec<u8> {
-    let mut out = vec![];
-    for _i in 0..sz {
-        out.push((n % 256) as u8);
-        n /= 256;
-    }
-    out
+/// `stream` - Référence vers le flux TCP
+fn has_data(stream: &TcpStream) -> io::Result<bool> {
+    Ok(peek(stream, &mut [0; wire::HEADER_LEN])? == wire::HEADER_LEN)
 }
 
-/// Transforme un entier en un vecteur d'octets (little-endian)
+/// Reçoit un message du client.
+/// Renvoie un couple (type du message, vecteur d'octets du contenu),
+/// ou une erreur si le client s'est déconnecté ou a envoyé un identifiant de message inconnu.
 /// 
 /// # Arguments
 /// 
-/// `data` - Entier 16 bits signé à transformer
-/// `n` - Taille en octets du vecteur de sortie
-fn vec_as_n_bytes(data: i16, n: usize) -> Vec<u8> {
-    let mut v: Vec<u8> = vec![0; n];
-    for i in 0..n {
-        let octet_i = data.shr(i * 8) & 0b1111_1111;
-        v[i] = octet_i as u8;
-    }
-    return v;
+/// `stream` - Référence mutable vers le flux
+fn get_data<R: io::Read>(stream: &mut R) -> io::Result<(Msg, Vec<u8>)> {
+    wire::read_packet(stream)
+}
+
+/// Différentes demandes de connexion d'un client
+#[derive(PartialEq, Debug)]
+pub enum Join {
+    /// [0] Nouveau joueur
+    New,
+    /// [1] Reconnexion d'un joueur avec son jeton de session
+    Reconnect(u64),
+    /// [2] Spectateur d'un salon (0 : la partie la plus récente)
+    Spectate(u32),
+    /// Demande des `n` premiers joueurs du classement (message Leaderboard), sans rejoindre de partie
+    Leaderboard(u8),
 }
 
-/// Lit et renvoie un entier 32 bits à partir d'un tampon d'octets
+/// Reçoit le premier message du client, qui doit être sa version du protocole et ses fonctionnalités.
+/// Renvoie le couple (version, fonctionnalités), ou une erreur si le client s'est déconnecté ou a envoyé autre chose.
 /// 
 /// # Arguments
 /// 
-/// `buf` - Slice vers le tampon d'octets
-/// `index` - Indice à partir duquel lire l'entier
-/// `n` - Nombre d'octets à lire
-pub fn read_int_from_n_bytes(buf: &[u8], index: u32, n: u32) -> u32 {
-    if (buf.len() as u32) < index + n {
-        panic!("read_int_from_n_bytes: Asked to read further than actual buffer length");
-    }
-    
-    let mut res: u32 = 0;
-    for i in 0..n {
-        res += (256 as u32).pow(i) * ((buf[(index + i) as usize]) as u32);
+/// `stream` - Référence mutable vers le flux
+pub fn get_version<R: io::Read>(stream: &mut R) -> io::Result<(u16, u32)> {
+    match get_data(stream)? {
+        (Msg::Version, content) => {
+            let version = wire::Version::decode(&content)?;
+            Ok((version.version, version.features))
+        }
+        (msg, _) => Err(ProtocolError::UnexpectedMessage(msg).into()),
     }
+}
 
-    res
+/// Accepte le client : envoie la version du protocole et les fonctionnalités retenues.
+/// 
+/// # Arguments
+/// 
+/// `stream` - Référence mutable vers le flux
+/// `features` - Fonctionnalités communes au client et au serveur
+pub fn send_version<W: Write>(stream: &mut W, features: u32) -> io::Result<()> {
+    send_message(stream, &wire::Version { version: PROTOCOL_VERSION, features })
 }
 
-/// Envoie un message au serveur.
+/// Refuse le client, avec une raison lisible (UTF-8) qu'il peut afficher.
 /// 
 /// # Arguments
 /// 
-/// `stream` - Référence mutable vers le flux TCP
-/// `msg` - Type de message à envoyer
-/// `msg_data` - Slice vers les octets constituant le contenu du message à envoyer
-fn send_data(stream: &mut TcpStream, msg: Msg, msg_data: &[u8]) -> () {
-    let n = msg_data.len();
-    // println!("Message len of {:?} = {}", msg, n);
-    match stream.write(&[&[msg_to_id(msg), (n % 256) as u8, (n / 256) as u8], msg_data].concat()) {
-        Err (e) => panic!("Erreur send_data: {}", e),
-        Ok (_) => (),
+/// `stream` - Référence mutable vers le flux
+/// `reason` - Raison du refus
+pub fn send_reject<W: Write>(stream: &mut W, reason: &str) -> io::Result<()> {
+    send_message(stream, &wire::Reject { reason: reason.to_string() })
+}
+
+/// Renvoie la fonctionnalité nécessaire pour envoyer un paquet au client (0 si aucune)
+/// 
+/// # Arguments
+/// 
+/// `packet` - Slice vers les octets du paquet
+pub fn required_feature(packet: &[u8]) -> u32 {
+    match packet.first().map(|&id| wire::id_to_msg(id)) {
+        Some(Ok(Msg::Queue)) => FEATURE_QUEUE_STATUS,
+        Some(Ok(Msg::Leaderboard)) => FEATURE_LEADERBOARD,
+        _ => 0,
     }
 }
 
-/// Reçoit un message du serveur. Toujours faire un peek avant.
-/// Renvoie un triplet (type du message, taille, vecteur d'octets du contenu)
+/// Reçoit le message de présentation du client (pseudo en UTF-8), s'il en envoie un avant sa demande de connexion.
+/// Renvoie une option du pseudo brut, ou une erreur si le client s'est déconnecté.
 /// 
 /// # Arguments
 /// 
 /// `stream` - Référence mutable vers le flux TCP
-fn get_data(stream: &mut TcpStream) ->  (Msg, u32, Vec<u8>) {
-    let header: &mut [u8] = &mut [0; 3];
-    stream.read(header).unwrap();
-
-    let msg_id: u32 = read_int_from_n_bytes(header, 0, 1);
-    let msg_len: u32 = read_int_from_n_bytes(header, 1, 2);
-
-    let mut buf = vec![0 as u8; msg_len as usize];
-    stream.read_exact(&mut buf).unwrap();
+pub fn get_hello(stream: &mut TcpStream) -> io::Result<Option<String>> {
+    let head: &mut [u8] = &mut [0; 1];
+    if peek(stream, head)? == 0 || head[0] != wire::msg_to_id(Msg::Hello) {
+        return Ok(None);
+    }
+    let (_msg, content) = get_data(stream)?;
+    Ok(Some(wire::Hello::decode(&content)?.nickname))
+}
 
-    (id_to_msg(msg_id as u8), msg_len, buf)
+/// Reçoit la demande de connexion du client.
+/// Renvoie une erreur si le client s'est déconnecté ou a envoyé un message invalide.
+/// 
+/// # Arguments
+/// 
+/// `stream` - Référence mutable vers le flux
+pub fn get_join<R: io::Read>(stream: &mut R) -> io::Result<Join> {
+    match get_data(stream)? {
+        (Msg::Join, content) => match wire::Join::decode(&content)? {
+            wire::Join::New => Ok(Join::New),
+            wire::Join::Reconnect(token) => Ok(Join::Reconnect(token)),
+            wire::Join::Spectate(room_id) => Ok(Join::Spectate(room_id)),
+        },
+        (Msg::Leaderboard, content) => Ok(Join::Leaderboard(wire::LeaderboardRequest::decode(&content)?.n)),
+        (msg, _) => Err(ProtocolError::UnexpectedMessage(msg).into()),
+    }
 }
 
-/// Envoie l'identifiant du joueur.
+/// Envoie l'identifiant du joueur et son jeton de session.
 /// 
 /// # Arguments
 /// 
 /// `stream` - Référence mutable vers le flux TCP
 /// `player_id` - Identifiant du joueur
-pub fn send_player_id(stream: &mut TcpStream, player_id: u8) -> () {
-    send_data(stream, Msg::PlayerId, &[player_id]);
+/// `token` - Jeton de session permettant de se reconnecter à la partie
+pub fn send_player_id(stream: &mut TcpStream, player_id: u8, token: u64) -> io::Result<()> {
+    send_message(stream, &wire::PlayerId { player_id, token })
 }
 
-/// Envoie les paramètres de la partie.
+/// Construit le paquet des paramètres de la partie.
+/// Renvoie une erreur si le paquet est trop long.
 /// 
 /// # Arguments
 /// 
-/// `stream` - Référence mutable vers le flux TCP
-/// `map_size` - Taille de la map en blocs
+/// `map` - Map de la partie (dimensions, mode torique et murs intérieurs)
 /// `block_size` - Taille d'un bloc en pixels
 /// `initial_speed` - Vitesse initiale
-/// `n_players` - Nombre de joueurs
-/// `players` - Vecteur des paramètres des joueurs
-pub fn send_game_params(stream: &mut TcpStream, map_size: u16, block_size: u16, initial_speed: u8, n_players: u8, players: Vec<(u8, (u8, u8, u8), i16, i16)>) {
-    let mut players_formatted: Vec<u8> = vec![];
-    for i in 0..(n_players as usize) {
-        players_formatted.push(players[i].0);
-        players_formatted.push((players[i].1).0);
-        players_formatted.push((players[i].1).1);
-        players_formatted.push((players[i].1).2);
-        let x0 = split_bytes(players[i].2 as u32, 2);
-        players_formatted.push(x0[0]);
-        players_formatted.push(x0[1]);
-        let y0 = split_bytes(players[i].3 as u32, 2);
-        players_formatted.push(y0[0]);
-        players_formatted.push(y0[1]);
-        println!("{} length of players_formatted", players_formatted.len());
-    }
+/// `players` - Vecteur des paramètres des joueurs (identifiant, couleur, coordonnées, direction initiale, pseudo)
+pub fn game_params_packet(map: &Map, block_size: u16, initial_speed: u8, players: Vec<PlayerParams>) -> Result<Vec<u8>, ProtocolError> {
+    wire::GameParams {
+        width: map.width,
+        height: map.height,
+        block_size,
+        initial_speed,
+        wrap: map.wrap,
+        players,
+        walls: map.wall_runs(),
+    }.packet()
+}
 
-    send_data(
-        stream,
-        Msg::GameParams,
-        &[
-            split_bytes(map_size as u32, 2),
-            split_bytes(block_size as u32, 2),
-            vec![initial_speed],
-            vec![n_players],
-            players_formatted,
-        ].concat()
-    );
+/// Construit le paquet d'état de la file d'attente, envoyé à un joueur en attente d'une partie.
+/// Renvoie une erreur si le paquet est trop long.
+/// 
+/// # Arguments
+/// 
+/// `position` - Position du joueur dans la file (à partir de 1)
+/// `n_waiting` - Nombre de joueurs en attente dans le salon
+/// `min_players`, `max_players` - Nombres minimal et maximal de joueurs d'une partie
+/// `time_left` - Option du temps restant en secondes avant le début de la partie
+pub fn queue_packet(position: u8, n_waiting: u8, min_players: u8, max_players: u8, time_left: Option<u16>) -> Result<Vec<u8>, ProtocolError> {
+    wire::QueueStatus { position, n_waiting, min_players, max_players, time_left }.packet()
 }
 
-/// Reçoit un mouvement. Renvoie une option du mouvement.
-/// Renvoie None en cas d'erreur de peek ou si `get_move` a renvoyé None.
+/// Construit le paquet du classement.
+/// Renvoie une erreur si le paquet est trop long.
+/// 
+/// # Arguments
+/// 
+/// `top` - Slice vers les meilleurs joueurs, dans l'ordre du classement (identité, statistiques)
+pub fn leaderboard_packet(top: &[(&str, &Stats)]) -> Result<Vec<u8>, ProtocolError> {
+    wire::Leaderboard {
+        entries: top.iter().map(|(identity, stats)| wire::LeaderboardEntry {
+            identity: identity.to_string(),
+            wins: stats.wins,
+            losses: stats.losses,
+            kills: stats.kills,
+            max_length: stats.max_length,
+        }).collect(),
+    }.packet()
+}
+
+/// Reçoit tous les mouvements en attente et renvoie une option du dernier.
+/// Renvoie None s'il n'y a rien à lire, ou une erreur si le client s'est déconnecté.
 /// 
 /// # Arguments
 /// 
 /// `stream` - Référence mutable vers le flux TCP
-pub fn get_move_empty_buff(stream: &mut TcpStream) -> Option<Move> {
+pub fn get_move_empty_buff(stream: &mut TcpStream) -> io::Result<Option<Move>> {
     let mut ret = None;
-    while {
-        match stream.peek(&mut[0; 3]) {
-            Err(_) => false,
-            Ok(n) => n > 0,
-        }
-    } {
-        ret = get_move(stream);
+    while has_data(stream)? {
+        ret = get_move(stream)?;
     }
-    return ret;
-    
+    Ok(ret)
 }
 
 /// Reçoit un mouvement. Renvoie une option du mouvement.
-/// Renvoie None s'il n'y a rien à lire.
+/// Renvoie None s'il n'y a rien à lire, ou une erreur si le client s'est déconnecté ou a envoyé un message invalide.
 /// 
 /// # Arguments
 /// 
 /// `stream` - Référence mutable vers le flux TCP
-pub fn get_move(stream: &mut TcpStream) -> Option<Move> {
-    match stream.peek(&mut [0; 3]) {
-        Err(_) => None,
-        Ok(n) => { 
-            if n == 0 {
-                // Rien à lire
-                return None;
-            }
-            
-            let (msg_id, msg_len, content): (Msg, u32, Vec<u8>) = get_data(stream);
-            
-            if msg_id != Msg::Move || msg_len != 1 {
-                panic!("get_move: malformed message; id:{}; len:{}", msg_to_id(msg_id), msg_len);
-            }
-            
-            match content[0] {
-                1 => Some(Move::Up),
-                2 => Some(Move::Down),
-                3 => Some(Move::Left),
-                4 => Some(Move::Right),
-                _ => {
-                    panic!("get_move: malformed move ({})", content[0]);
-                }
-            }
-        }
+pub fn get_move(stream: &mut TcpStream) -> io::Result<Option<Move>> {
+    if !has_data(stream)? {
+        // Rien à lire
+        return Ok(None);
     }
+
+    // L'en-tête est arrivé : le reste du paquet peut arriver dans un autre segment TCP,
+    // il est attendu plus longtemps que le timeout de scrutation, qui est ensuite rétabli
+    let poll_timeout = stream.read_timeout()?;
+    stream.set_read_timeout(Some(PACKET_TIMEOUT))?;
+    let player_move = read_move(stream);
+    stream.set_read_timeout(poll_timeout)?;
+    player_move.map(Some)
 }
 
-/// Envoie une frame au client.
+/// Lit un paquet qui doit être un mouvement.
+/// Renvoie une erreur si le client s'est déconnecté ou a envoyé un message invalide.
 /// 
 /// # Arguments
 /// 
-/// `stream` - Référence mutable vers le flux TCP
-/// `list_food` - Référence vers un vecteur de nourritures
-/// `list_snake` - Référence vers un vecteur de références vers les serpents
-pub fn send_frame(stream: &mut TcpStream, list_food: &Vec<Food>, list_snake: &Vec<&Snake>) -> () {
-    send_data(
-        stream,
-        Msg::Frame, 
-        &[
-            food_to_bytes(list_food),
-            snake_to_bytes(list_snake),
-        ].concat()
-    );
+/// `stream` - Référence mutable vers le flux
+fn read_move<R: io::Read>(stream: &mut R) -> io::Result<Move> {
+    match get_data(stream)? {
+        (Msg::Move, content) => Ok(Move::decode(&content)?),
+        (msg, _) => Err(ProtocolError::UnexpectedMessage(msg).into()),
+    }
 }
 
-/// Transforme une liste de nourriture en vecteur d'octets pour le message
+/// Construit le paquet d'une frame pour un destinataire : les serpents invisibles sont envoyés
+/// sans leurs noeuds aux autres joueurs, mais en entier à eux-mêmes, aux spectateurs et au replay.
+/// Renvoie une erreur si le paquet est trop long.
 /// 
 /// # Arguments
 /// 
 /// `list_food` - Référence vers un vecteur de nourritures
-fn food_to_bytes(list_food: &Vec<Food>) -> Vec<u8> {
-    let mut data: Vec<u8> = vec![];
-    data.push(list_food.len() as u8);
-    for food in list_food {
-        let (f_id, x, y): (u8, i16, i16) = food.get_info_for_data_trs();
-        data.push(f_id);
-        data.append(&mut vec_as_n_bytes(x, 2));
-        data.append(&mut vec_as_n_bytes(y, 2));
-    }
-    return data;
+/// `list_snake` - Référence vers un vecteur de références vers les serpents
+/// `recipient` - Identifiant du joueur destinataire, ou None pour un spectateur ou le replay
+pub fn frame_packet(list_food: &[Food], list_snake: &Vec<&Snake>, recipient: Option<u8>) -> Result<Vec<u8>, ProtocolError> {
+    wire::Frame {
+        food: list_food.iter().map(|food| {
+            let (food_type, x, y): (u8, i16, i16) = food.get_info_for_data_trs();
+            wire::FoodState { food_type, x, y }
+        }).collect(),
+        snakes: list_snake.iter().map(|snake| snake_state(snake, recipient)).collect(),
+    }.packet()
 }
 
-/// Transforme une liste de serpents en vecteur d'octets pour le message
+/// Renvoie l'état d'un serpent tel qu'envoyé dans une frame
 /// 
 /// # Arguments
 /// 
-/// `list_snake` - Référence vers un vecteur de références vers des serpents
-fn snake_to_bytes(list_snake: &Vec<&Snake>) -> Vec<u8> {
-    let mut data: Vec<u8> = vec![];
-    data.push(list_snake.len() as u8);
-    for snake in list_snake {
-        data.push(snake.id);
-        data.push(if snake.has_lost { 1 as u8 } else { 0 as u8 });
-        data.push(snake.stomach);
-        let len = snake.head.len();
-        data.push(len as u8);
-        
-        let mut node: &SnakeNode = &snake.head;
-        for i in 0..len {
-            if i > 0 {
-                node = node.get_next().unwrap();
-            }
-            data.append(&mut vec_as_n_bytes(node.x, 2));
-            data.append(&mut vec_as_n_bytes(node.y, 2));
-        }
-    }
-    return data;
+/// `snake` - Référence vers le serpent
+/// `recipient` - Identifiant du joueur destinataire, ou None pour un spectateur ou le replay
+fn snake_state(snake: &Snake, recipient: Option<u8>) -> wire::SnakeState {
+    let invisible = snake.invisible > 0;
+    let hidden = invisible && recipient.is_some_and(|id| id != snake.id);
+    let nodes = if hidden {
+        vec![]
+    } else {
+        snake.body.iter().map(|node| (node.x, node.y)).collect()
+    };
+    wire::SnakeState { id: snake.id, has_lost: snake.has_lost, invisible, stomach: snake.stomach, nodes }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::SnakeNode;
     use crate::FoodType;
-    
+    use proptest::collection::vec;
+    use proptest::prelude::*;
+    use std::io::Cursor;
+    use std::net::TcpListener;
+    use std::time::Duration;
+
+    /// Renvoie une paire de flux TCP connectés (côté client, côté serveur),
+    /// le côté serveur ayant un timeout de lecture court comme pendant une partie
+    fn stream_pair() -> (TcpStream, TcpStream) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let client = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        let (server, _) = listener.accept().unwrap();
+        server.set_read_timeout(Some(Duration::from_millis(1))).unwrap();
+        (client, server)
+    }
+
+    /// Attend (sans timeout) qu'au moins `n` octets soient disponibles sur le flux, ou qu'il soit fermé,
+    /// puis rétablit son timeout de lecture
+    fn wait_for(stream: &TcpStream, n: usize) {
+        let timeout = stream.read_timeout().unwrap();
+        stream.set_read_timeout(None).unwrap();
+        let mut buf = vec![0; n];
+        loop {
+            let available = stream.peek(&mut buf).unwrap();
+            if available == 0 || available >= n {
+                break;
+            }
+        }
+        stream.set_read_timeout(timeout).unwrap();
+    }
+
     #[test]
-    fn test_vec_as_n_bytes() {
-        let data = 2;
-        let v = vec_as_n_bytes(data, 2);
-        assert_eq!(v, [2, 0]);
-
-        let data = 9832;
-        let v = vec_as_n_bytes(data, 1);
-        assert_eq!(v, [0x68]);
-
-        let data = 9832;
-        let v = vec_as_n_bytes(data, 2);
-        assert_eq!(v, [0x68, 0x26]);
-
-        let data: i16 = -1;
-        let v = vec_as_n_bytes(data, 2);
-        assert_eq!(v, [0xff, 0xff]);
+    fn test_socket_moves() {
+        let (mut client, mut server) = stream_pair();
+        let poll_timeout = server.read_timeout().unwrap();
+        assert!(get_move(&mut server).unwrap().is_none());
+
+        // Un en-tête incomplet n'est pas consommé
+        client.write_all(&[4, 1]).unwrap();
+        wait_for(&server, 2);
+        assert!(get_move(&mut server).unwrap().is_none());
+        client.write_all(&[0, 2, 4, 1, 0, 3]).unwrap();
+        wait_for(&server, 8);
+        assert_eq!(get_move_empty_buff(&mut server).unwrap(), Some(Move::Left));
+
+        // Le contenu d'un paquet peut arriver après son en-tête
+        client.write_all(&[4, 1, 0]).unwrap();
+        wait_for(&server, 3);
+        let mut late = client.try_clone().unwrap();
+        let writer = std::thread::spawn(move || late.write_all(&[1]).unwrap());
+        assert_eq!(get_move(&mut server).unwrap(), Some(Move::Up));
+        writer.join().unwrap();
+        assert_eq!(server.read_timeout().unwrap(), poll_timeout);
+
+        // Une présentation est lue si elle est là, sinon la demande de connexion reste à lire
+        client.write_all(&[8, 3, 0, b'B', b'o', b'b', 6, 1, 0, 0]).unwrap();
+        wait_for(&server, 10);
+        assert_eq!(get_hello(&mut server).unwrap(), Some("Bob".to_string()));
+        assert_eq!(get_hello(&mut server).unwrap(), None);
+        assert_eq!(get_join(&mut server).unwrap(), Join::New);
+
+        drop(client);
+        wait_for(&server, 1);
+        assert!(get_move_empty_buff(&mut server).is_err());
     }
-    
+
     #[test]
-    fn test_snake_to_bytes() {
-        let sn = &Snake::new(1, (2, 3, 4), 10, 20, Move::Right, Move::Right);
-        let snake_lst = vec![sn];
-        assert_eq!(snake_to_bytes(&snake_lst), [1,1, 0, 10, 2, 10, 0 , 20, 0 ,10, 0, 20, 0]);
-    }  
+    fn test_get_join() {
+        let mut stream = Cursor::new(vec![
+            6, 1, 0, 0,
+            6, 9, 0, 1, 8, 7, 6, 5, 4, 3, 2, 1,
+            6, 5, 0, 2, 3, 0, 0, 0,
+            7, 1, 0, 10,
+            4, 1, 0, 1,
+        ]);
+        assert_eq!(get_join(&mut stream).unwrap(), Join::New);
+        assert_eq!(get_join(&mut stream).unwrap(), Join::Reconnect(0x0102030405060708));
+        assert_eq!(get_join(&mut stream).unwrap(), Join::Spectate(3));
+        assert_eq!(get_join(&mut stream).unwrap(), Join::Leaderboard(10));
+        assert!(get_join(&mut stream).is_err());
+    }
+
+    #[test]
+    fn test_version() {
+        let mut stream = Cursor::new(vec![9, 6, 0, 1, 0, 5, 0, 0, 0, 6, 1, 0, 0]);
+        assert_eq!(get_version(&mut stream).unwrap(), (1, FEATURE_NICKNAMES | FEATURE_QUEUE_STATUS));
+        assert!(get_version(&mut stream).is_err());
+
+        let mut sent = vec![];
+        send_version(&mut sent, FEATURE_LEADERBOARD).unwrap();
+        send_reject(&mut sent, "non").unwrap();
+        assert_eq!(sent, [9, 6, 0, 3, 0, 2, 0, 0, 0, 10, 3, 0, b'n', b'o', b'n']);
+    }
+
+    #[test]
+    fn test_required_feature() {
+        assert_eq!(required_feature(&queue_packet(1, 1, 2, 2, None).unwrap()), FEATURE_QUEUE_STATUS);
+        assert_eq!(required_feature(&leaderboard_packet(&[]).unwrap()), FEATURE_LEADERBOARD);
+        assert_eq!(required_feature(&frame_packet(&[], &vec![], None).unwrap()), 0);
+    }
+
+    #[test]
+    fn test_leaderboard_packet() {
+        let stats = Stats { wins: 3, losses: 1, kills: 258, max_length: 40, name: None };
+        assert_eq!(leaderboard_packet(&[("1.2.3.4", &stats)]).unwrap(), [
+            7, 25, 0,
+            1, 7, b'1', b'.', b'2', b'.', b'3', b'.', b'4',
+            3, 0, 0, 0, 1, 0, 0, 0, 2, 1, 0, 0, 40, 0, 0, 0,
+        ]);
+    }
+
+    #[test]
+    fn test_read_move_malformed() {
+        for packet in [&[4, 1, 0, 9][..], &[4, 2, 0, 1, 1], &[6, 1, 0, 0], &[42, 0, 0]].iter() {
+            assert_eq!(read_move(&mut Cursor::new(packet)).unwrap_err().kind(), io::ErrorKind::InvalidData);
+        }
+        assert_eq!(read_move(&mut Cursor::new(&[4, 1, 0])).unwrap_err().kind(), io::ErrorKind::UnexpectedEof);
+    }
+    
     #[test]
-    fn test_snake_to_bytes2() {
+    fn test_frame_packet_snakes() {
         let sn = &Snake::new(1, (2, 3, 4), 10, 20, Move::Right, Move::Right);
         let sn2 = &Snake::new(2, (2, 3, 4), 30, 40, Move::Right, Move::Right);
-        let snake_lst = vec![sn, sn2];
-        assert_eq!(snake_to_bytes(&snake_lst), [2,1, 0, 10, 2, 10, 0 , 20, 0 ,10, 0, 20, 0, 2, 0, 10, 2, 30, 0 ,40, 0, 30, 0, 40, 0]);
-    }  
-    
+        assert_eq!(frame_packet(&[], &vec![sn], Some(1)).unwrap(), [3, 15, 0, 0, 1,1, 0, 10, 2, 0, 10, 0 , 20, 0 ,10, 0, 20, 0]);
+        assert_eq!(frame_packet(&[], &vec![sn, sn2], None).unwrap()[4..], [2,1, 0, 10, 2, 0, 10, 0 , 20, 0 ,10, 0, 20, 0, 2, 0, 10, 2, 0, 30, 0 ,40, 0, 30, 0, 40, 0]);
+    }
+
     #[test]
-    fn test_food_to_bytes() {
+    fn test_queue_packet() {
+        assert_eq!(queue_packet(2, 3, 2, 4, Some(300)).unwrap(), [5, 6, 0, 2, 3, 2, 4, 0x2C, 0x01]);
+        assert_eq!(queue_packet(1, 1, 2, 4, None).unwrap(), [5, 6, 0, 1, 1, 2, 4, 0xFF, 0xFF]);
+    }
+
+    #[test]
+    fn test_game_params_packet() {
+        let mut lines = vec!["................"; 16];
+        lines[3] = "...###......##..";
+        let map = Map::parse(&lines.join("\n")).unwrap();
+        let packet = game_params_packet(&map, 10, 1, vec![
+            PlayerParams { id: 1, color: (1, 2, 3), x: 4, y: 5, direction: Move::Down, name: "Léa".to_string() },
+        ]).unwrap();
+        assert_eq!(packet, [
+            1, 37, 0,
+            16, 0, 16, 0, 10, 0, 1, 0, 1,
+            1, 1, 2, 3, 4, 0, 5, 0, 2,
+            2, 0, 3, 0, 3, 0, 3, 0, 12, 0, 3, 0, 2, 0,
+            4, b'L', 0xC3, 0xA9, b'a',
+        ]);
+    }
+
+    #[test]
+    fn test_frame_packet_invisible() {
+        let mut sn = Snake::new(1, (2, 3, 4), 10, 20, Move::Right, Move::Right);
+        sn.invisible = 5;
+        let sn2 = Snake::new(2, (2, 3, 4), 30, 40, Move::Right, Move::Right);
+        let snakes = vec![&sn, &sn2];
+
+        // Caché à l'adversaire, visible (et marqué invisible) pour lui-même et les spectateurs
+        assert_eq!(frame_packet(&[], &snakes, Some(2)).unwrap()[4..14], [2, 1, 2, 10, 0, 0, 2, 0, 10, 2]);
+        for recipient in [Some(1), None] {
+            let packet = frame_packet(&[], &snakes, recipient).unwrap();
+            let frame = wire::Frame::decode(&packet[wire::HEADER_LEN..]).unwrap();
+            assert!(frame.snakes[0].invisible);
+            assert_eq!(frame.snakes[0].nodes, vec![(10, 20), (10, 20)]);
+            assert!(!frame.snakes[1].invisible);
+        }
+    }
+
+    #[test]
+    fn test_frame_packet_food() {
         let food_lst = vec![Food::new(10, 20, FoodType::Apple), Food::new(30, 40, FoodType::Apple)];
-        assert_eq!(food_to_bytes(&food_lst), [2, 1, 10, 0, 20, 0, 1, 30, 0, 40, 0]);
+        assert_eq!(frame_packet(&food_lst, &vec![], Some(1)).unwrap(), [3, 12, 0, 2, 1, 10, 0, 20, 0, 1, 30, 0, 40, 0, 0]);
+    }
+
+    /// Renvoie un serpent dont le corps passe par les noeuds donnés (au moins un)
+    fn snake_with_nodes(id: u8, has_lost: bool, stomach: u8, nodes: &[(i16, i16)]) -> Snake {
+        let mut snake = Snake::new(id, (2, 3, 4), nodes[0].0, nodes[0].1, Move::Right, Move::Right);
+        snake.body = nodes.iter().map(|&(x, y)| SnakeNode::new(x, y)).collect();
+        snake.has_lost = has_lost;
+        snake.stomach = stomach;
+        snake
+    }
+
+    proptest! {
+        /// Les nourritures et serpents envoyés sont ceux que le client décode, y compris les longs serpents
+        #[test]
+        fn prop_frame_packet_round_trip(
+            food in vec((0..64i16, 0..64i16, 1..=3u8), 0..=20),
+            snakes in vec((any::<bool>(), any::<u8>(), vec((0..64i16, 0..64i16), 1..400)), 0..=16),
+        ) {
+            let food_type = |id: u8| match id {
+                1 => FoodType::Apple,
+                2 => FoodType::Mango,
+                _ => FoodType::Invisibility,
+            };
+            let list_food: Vec<Food> = food.iter().map(|&(x, y, id)| Food::new(x, y, food_type(id))).collect();
+            let list_snake: Vec<Snake> = snakes.iter().enumerate()
+                .map(|(i, (has_lost, stomach, nodes))| snake_with_nodes(i as u8 + 1, *has_lost, *stomach, nodes))
+                .collect();
+
+            let packet = frame_packet(&list_food, &list_snake.iter().collect(), None).unwrap();
+            let (msg, content) = wire::read_packet(&mut io::Cursor::new(packet)).unwrap();
+            prop_assert_eq!(msg, Msg::Frame);
+            let frame = wire::Frame::decode(&content).unwrap();
+
+            let expected_food: Vec<(u8, i16, i16)> = food.iter().map(|&(x, y, id)| (id, x, y)).collect();
+            prop_assert_eq!(frame.food.iter().map(|f| (f.food_type, f.x, f.y)).collect::<Vec<_>>(), expected_food);
+            prop_assert_eq!(frame.snakes.len(), snakes.len());
+            for (i, (state, (has_lost, stomach, nodes))) in frame.snakes.iter().zip(snakes.iter()).enumerate() {
+                prop_assert_eq!(state.id, i as u8 + 1);
+                prop_assert_eq!(state.has_lost, *has_lost);
+                prop_assert_eq!(state.stomach, *stomach);
+                prop_assert_eq!(&state.nodes, nodes);
+            }
+        }
     }
 }
@@ -0,0 +1,166 @@
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+use std::time;
+
+use snecc_protocol::replay::{self as format, Header, Record};
+use snecc_protocol::HEADER_LEN;
+
+use crate::game_serv::Move;
+
+/// Enregistrement du replay d'une partie, au format décrit dans `snecc_protocol::replay`.
+/// Une erreur d'écriture n'interrompt jamais la partie : elle est affichée et l'enregistrement s'arrête.
+pub struct Replay {
+    /// Identifiant du salon
+    room_id: u32,
+    /// Fichier de replay (None si l'enregistrement est désactivé ou a échoué)
+    out: Option<BufWriter<File>>,
+}
+
+/// Implémentation de l'enregistrement d'un replay
+impl Replay {
+    /// Renvoie un enregistrement désactivé, qui n'écrit rien
+    pub fn disabled() -> Self {
+        Replay { room_id: 0, out: None }
+    }
+
+    /// Crée le fichier de replay d'une partie dans un dossier (créé si besoin) et écrit son en-tête
+    ///
+    /// # Arguments
+    ///
+    /// * `dir` - Dossier des replays
+    /// * `room_id` - Identifiant du salon
+    /// * `seed` - Graine du générateur aléatoire de la partie
+    /// * `period_ms` - Durée d'un tick en millisecondes
+    pub fn create(dir: &str, room_id: u32, seed: u64, period_ms: u32) -> Self {
+        let timestamp = time::SystemTime::now()
+            .duration_since(time::UNIX_EPOCH)
+            .map(|duration| duration.as_secs())
+            .unwrap_or(0);
+        let path = Path::new(dir).join(format!("snecc-{}-salon-{}.replay", timestamp, room_id));
+
+        match Replay::open(&path, room_id, seed, period_ms) {
+            Ok(out) => {
+                println!("[salon {}] Enregistrement du replay dans {}", room_id, path.display());
+                Replay { room_id, out: Some(out) }
+            }
+            Err(e) => {
+                println!("[salon {}] Impossible d'enregistrer le replay ({}) : {}", room_id, path.display(), e);
+                Replay::disabled()
+            }
+        }
+    }
+
+    /// Ouvre le fichier de replay et écrit son en-tête
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - Chemin du fichier
+    /// * `room_id` - Identifiant du salon
+    /// * `seed` - Graine du générateur aléatoire de la partie
+    /// * `period_ms` - Durée d'un tick en millisecondes
+    fn open(path: &Path, room_id: u32, seed: u64, period_ms: u32) -> io::Result<BufWriter<File>> {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let mut out = BufWriter::new(File::create(path)?);
+        format::write_header(&mut out, &Header { room_id, seed, period_ms })?;
+        Ok(out)
+    }
+
+    /// Enregistre les paramètres de la partie
+    ///
+    /// # Arguments
+    ///
+    /// * `packet` - Paquet GameParams envoyé aux clients
+    pub fn params(&mut self, packet: &[u8]) {
+        self.write(Record::Params, &packet[HEADER_LEN..]);
+    }
+
+    /// Enregistre un mouvement reçu d'un joueur
+    ///
+    /// # Arguments
+    ///
+    /// * `player_id` - Identifiant du joueur
+    /// * `player_move` - Le mouvement reçu
+    pub fn input(&mut self, player_id: u8, player_move: &Move) {
+        self.write(Record::Input, &[player_id, player_move.to_id()]);
+    }
+
+    /// Enregistre l'élimination d'un joueur qui ne s'est pas reconnecté à temps
+    ///
+    /// # Arguments
+    ///
+    /// * `player_id` - Identifiant du joueur
+    pub fn quit(&mut self, player_id: u8) {
+        self.write(Record::Quit, &[player_id]);
+    }
+
+    /// Enregistre la frame d'un tick
+    ///
+    /// # Arguments
+    ///
+    /// * `packet` - Paquet Frame envoyé aux clients
+    pub fn frame(&mut self, packet: &[u8]) {
+        self.write(Record::Frame, &packet[HEADER_LEN..]);
+    }
+
+    /// Termine l'enregistrement (vide le tampon d'écriture)
+    pub fn finish(mut self) {
+        if let Some(mut out) = self.out.take() {
+            if let Err(e) = out.flush() {
+                println!("[salon {}] Erreur d'écriture du replay : {}", self.room_id, e);
+            }
+        }
+    }
+
+    /// Écrit un enregistrement ; en cas d'erreur, l'enregistrement du replay s'arrête
+    ///
+    /// # Arguments
+    ///
+    /// * `record` - Type de l'enregistrement
+    /// * `payload` - Contenu de l'enregistrement
+    fn write(&mut self, record: Record, payload: &[u8]) {
+        if let Some(out) = self.out.as_mut() {
+            if let Err(e) = format::write_record(out, record, payload) {
+                println!("[salon {}] Erreur d'écriture du replay, enregistrement arrêté : {}", self.room_id, e);
+                self.out = None;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_replay_file() {
+        let dir = std::env::temp_dir().join(format!("snecc-replay-{}", std::process::id()));
+        let mut replay = Replay::create(dir.to_str().unwrap(), 7, 0x0102, 50);
+        replay.params(&[1, 2, 0, 10, 11]);
+        replay.input(2, &Move::Left);
+        replay.quit(3);
+        replay.frame(&[3, 1, 0, 42]);
+        replay.finish();
+
+        let path = fs::read_dir(&dir).unwrap().next().unwrap().unwrap().path();
+        let content = fs::read(&path).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+        let file = format::parse(&content).unwrap();
+        assert_eq!(file.header, Header { room_id: 7, seed: 0x0102, period_ms: 50 });
+        assert_eq!(file.records, vec![
+            (Record::Params, &[10, 11][..]),
+            (Record::Input, &[2, 3][..]),
+            (Record::Quit, &[3][..]),
+            (Record::Frame, &[42][..]),
+        ]);
+    }
+
+    #[test]
+    fn test_disabled() {
+        let mut replay = Replay::disabled();
+        replay.frame(&[3, 1, 0, 42]);
+        replay.finish();
+    }
+}
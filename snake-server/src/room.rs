@@ -0,0 +1,797 @@
+use rand::seq::SliceRandom;
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::sync::mpsc::{channel, Receiver, RecvTimeoutError, Sender};
+use std::{thread, time};
+
+use crate::config::Config;
+use crate::game_serv::{Game, Move};
+use crate::leaderboard::{Leaderboard, PlayerResult};
+use crate::map::Map;
+use crate::protocol;
+use crate::replay::Replay;
+use crate::{QUEUE_PERIOD, RECONNECT_GRACE};
+
+/// Longueur maximale d'un pseudo en caractères
+pub const MAX_NICKNAME_LEN: usize = 16;
+
+/// Événements envoyés à la boucle de jeu (ou au gestionnaire pendant l'attente)
+pub enum ClientEvent {
+    /// Dernier mouvement reçu d'un joueur
+    Move(u8, Move),
+    /// Une connexion d'un joueur s'est fermée (ou une erreur d'entrée/sortie est survenue) ;
+    /// contient l'identifiant du joueur et le numéro de la connexion
+    Disconnected(u8, u32),
+    /// Un joueur déconnecté s'est reconnecté avec son jeton de session
+    Reconnected(Client),
+    /// Un spectateur rejoint la partie
+    Spectate(Client),
+}
+
+/// Identité d'un joueur, transmise par son thread de connexion
+pub struct Identity {
+    /// Adresse IP du joueur
+    pub address: String,
+    /// Pseudo choisi par le joueur (déjà nettoyé), s'il en a envoyé un
+    pub nickname: Option<String>,
+}
+
+/// Demandes envoyées au gestionnaire de salons par les threads de connexion
+pub enum LobbyRequest {
+    /// Nouveau joueur, avec son identité ; la place attribuée est renvoyée dans le canal
+    Join(Identity, Sender<Seat>),
+    /// Joueur qui présente un jeton de session, avec son identité ; la place attribuée est renvoyée dans le canal
+    Reconnect(u64, Identity, Sender<Seat>),
+    /// Spectateur d'un salon (0 : la partie en cours la plus récente) ; la place attribuée, ou la raison
+    /// du refus si le salon n'existe pas, est renvoyée dans le canal
+    Spectate(u32, Sender<Result<Seat, String>>),
+    /// Demande des `n` premiers joueurs du classement ; le paquet du classement est renvoyé dans le canal
+    Leaderboard(u8, Sender<Result<Vec<u8>, protocol::ProtocolError>>),
+}
+
+/// Place attribuée à une connexion par le gestionnaire de salons
+pub struct Seat {
+    /// Identifiant du joueur (0 pour un spectateur)
+    pub player_id: u8,
+    /// Jeton de session permettant de se reconnecter à la partie
+    pub token: u64,
+    /// Numéro de la connexion, distinct pour chaque connexion d'un même joueur
+    pub connection: u32,
+    /// Paquets à envoyer au client
+    pub outbox: Receiver<Vec<u8>>,
+    /// Canal vers le salon du joueur
+    pub events: Sender<ClientEvent>,
+}
+
+/// Client d'un salon, du point de vue du salon
+pub struct Client {
+    /// Identifiant du joueur (0 pour un spectateur)
+    player_id: u8,
+    /// Numéro de la connexion
+    connection: u32,
+    /// Canal vers le thread de connexion
+    outbox: Sender<Vec<u8>>,
+}
+
+/// Session d'un joueur, retrouvée à partir de son jeton
+struct Session {
+    /// Identifiant du salon
+    room_id: u32,
+    /// Identifiant du joueur dans le salon
+    player_id: u8,
+    /// Identité du joueur dans le classement (adresse IP)
+    identity: String,
+    /// Pseudo du joueur, unique dans le salon, affiché dans le classement s'il en a choisi un
+    name: Option<String>,
+    /// Canal vers le salon
+    events: Sender<ClientEvent>,
+}
+
+/// Salon : une file de joueurs en attente, puis une partie en cours dans son propre thread
+pub struct Room {
+    /// Identifiant du salon
+    id: u32,
+    /// Joueurs en attente, par ordre d'arrivée
+    clients: Vec<Client>,
+    /// Spectateurs en attente du début de la partie
+    spectators: Vec<Client>,
+    /// Pseudos des joueurs en attente, indexés par identifiant
+    names: HashMap<u8, String>,
+    /// Canal des événements des joueurs, cloné pour chaque thread de connexion
+    events_tx: Sender<ClientEvent>,
+    /// Événements des joueurs, lus par le gestionnaire pendant l'attente puis par la boucle de jeu
+    events_rx: Receiver<ClientEvent>,
+    /// Couleurs des serpents (permutation aléatoire de la palette)
+    snake_colors: Vec<(u8, u8, u8)>,
+    /// Configuration du serveur
+    config: Arc<Config>,
+    /// Map des parties
+    map: Arc<Map>,
+}
+
+/// Gestionnaire des salons (matchmaking) : place les nouveaux clients dans le salon en attente
+/// et lance sa partie dès qu'il est plein, ou lorsque le nombre minimal de joueurs est atteint
+/// et que le compte à rebours a expiré.
+pub struct RoomManager {
+    /// Configuration du serveur (nombres de joueurs, compte à rebours...)
+    config: Arc<Config>,
+    /// Map des parties
+    map: Arc<Map>,
+    /// Début du compte à rebours du salon en attente
+    countdown_start: Option<time::Instant>,
+    /// Identifiant du prochain salon
+    next_id: u32,
+    /// Numéro de la prochaine connexion
+    next_connection: u32,
+    /// Salon en attente de joueurs
+    waiting: Room,
+    /// Sessions des joueurs, indexées par jeton
+    sessions: HashMap<u64, Session>,
+    /// Canaux vers les salons dont la partie est en cours, indexés par identifiant
+    running: HashMap<u32, Sender<ClientEvent>>,
+    /// Canal par lequel les boucles de jeu signalent la fin de leur partie et ses résultats
+    finished_tx: Sender<(u32, Vec<PlayerResult>)>,
+    /// Identifiants des salons dont la partie est terminée, avec les résultats des joueurs
+    finished_rx: Receiver<(u32, Vec<PlayerResult>)>,
+    /// Classement des joueurs, mis à jour à la fin de chaque partie
+    leaderboard: Leaderboard,
+}
+
+/// Implémentation d'un salon
+impl Room {
+    /// Renvoie un nouveau salon vide
+    ///
+    /// # Arguments
+    ///
+    /// * `id` - Identifiant du salon
+    /// * `config` - Configuration du serveur
+    /// * `map` - Map de la partie
+    pub fn new(id: u32, config: Arc<Config>, map: Arc<Map>) -> Self {
+        // Permutation aléatoire des couleurs
+        let mut snake_colors: Vec<(u8, u8, u8)> = config.palette.clone();
+        snake_colors.shuffle(&mut rand::thread_rng());
+
+        let (events_tx, events_rx) = channel::<ClientEvent>();
+        Room {
+            id,
+            clients: vec![],
+            spectators: vec![],
+            names: HashMap::new(),
+            events_tx,
+            events_rx,
+            snake_colors,
+            config,
+            map,
+        }
+    }
+
+    /// Ajoute un client à la file du salon et renvoie sa place.
+    /// Le joueur reçoit le plus petit identifiant libre, et un pseudo unique dans le salon :
+    /// son pseudo (suivi d'un numéro s'il est déjà pris), ou à défaut « Joueur <identifiant> ».
+    ///
+    /// # Arguments
+    ///
+    /// * `token` - Jeton de session du joueur
+    /// * `connection` - Numéro de la connexion
+    /// * `nickname` - Option du pseudo choisi par le joueur
+    pub fn add_client(&mut self, token: u64, connection: u32, nickname: Option<String>) -> Seat {
+        let player_id = (1..).find(|id| self.clients.iter().all(|client| client.player_id != *id)).unwrap();
+        let name = self.unique_name(nickname.unwrap_or_else(|| format!("Joueur {}", player_id)));
+        println!("[salon {}] [{}] Joined queue as {}", self.id, player_id, name);
+        self.names.insert(player_id, name);
+
+        let (outbox_tx, outbox_rx) = channel::<Vec<u8>>();
+        self.clients.push(Client { player_id, connection, outbox: outbox_tx });
+        Seat {
+            player_id,
+            token,
+            connection,
+            outbox: outbox_rx,
+            events: self.events_tx.clone(),
+        }
+    }
+
+    /// Ajoute un spectateur au salon et renvoie sa place.
+    /// Il recevra les paramètres et les frames de la partie dès son lancement.
+    ///
+    /// # Arguments
+    ///
+    /// * `connection` - Numéro de la connexion
+    fn add_spectator(&mut self, connection: u32) -> Seat {
+        println!("[salon {}] New spectator", self.id);
+        let (outbox_tx, outbox_rx) = channel::<Vec<u8>>();
+        self.spectators.push(Client { player_id: 0, connection, outbox: outbox_tx });
+        Seat {
+            player_id: 0,
+            token: 0,
+            connection,
+            outbox: outbox_rx,
+            events: self.events_tx.clone(),
+        }
+    }
+
+    /// Renvoie un pseudo qui n'est pas encore pris dans le salon, en ajoutant si besoin un numéro au pseudo demandé
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - Pseudo demandé
+    fn unique_name(&self, name: String) -> String {
+        let taken = |candidate: &String| self.names.values().any(|other| other == candidate);
+        if !taken(&name) {
+            return name;
+        }
+        (2..).map(|n| format!("{} {}", name, n)).find(|candidate| !taken(candidate)).unwrap()
+    }
+
+    /// Retire de la file les joueurs qui se sont déconnectés pendant l'attente.
+    /// Renvoie les identifiants des joueurs retirés.
+    fn remove_disconnected(&mut self) -> Vec<u8> {
+        let mut removed = vec![];
+        while let Ok(event) = self.events_rx.try_recv() {
+            match event {
+                ClientEvent::Disconnected(0, connection) => {
+                    self.spectators.retain(|spectator| spectator.connection != connection);
+                }
+                ClientEvent::Disconnected(player_id, connection) => {
+                    let n_waiting = self.len();
+                    self.clients.retain(|client| client.player_id != player_id || client.connection != connection);
+                    if self.len() < n_waiting {
+                        println!("[salon {}] [{}] Left queue", self.id, player_id);
+                        self.names.remove(&player_id);
+                        removed.push(player_id);
+                    }
+                }
+                _ => (),
+            }
+        }
+        removed
+    }
+
+    /// Renvoie le nombre de joueurs en attente dans le salon
+    pub fn len(&self) -> usize {
+        self.clients.len()
+    }
+
+    /// Envoie à chaque joueur en attente sa position dans la file
+    ///
+    /// # Arguments
+    ///
+    /// * `min_players`, `max_players` - Nombres minimal et maximal de joueurs d'une partie
+    /// * `time_left` - Option du temps restant avant le début de la partie
+    fn send_queue_status(&self, min_players: u8, max_players: u8, time_left: Option<time::Duration>) {
+        // Arrondi à la seconde supérieure
+        let time_left = time_left.map(|t| t.as_millis().div_ceil(1000) as u16);
+        for (position, client) in self.clients.iter().enumerate() {
+            if let Ok(packet) = protocol::queue_packet(position as u8 + 1, self.len() as u8, min_players, max_players, time_left) {
+                let _ = client.outbox.send(packet);
+            }
+        }
+    }
+
+    /// Crée la partie avec les joueurs en attente et la lance dans son propre thread.
+    /// Renvoie le canal vers la boucle de jeu.
+    ///
+    /// # Arguments
+    ///
+    /// * `finished` - Canal par lequel la boucle de jeu signale la fin de la partie et ses résultats
+    pub fn start(self, finished: Sender<(u32, Vec<PlayerResult>)>) -> Sender<ClientEvent> {
+        let seed = self.config.seed.unwrap_or_else(rand::random);
+        println!("[salon {}] Début de la partie ({} joueurs, graine {})", self.id, self.len(), seed);
+        let mut game = Game::new(self.config.clone(), self.map.clone(), seed);
+        let replay = match &self.config.replay_dir {
+            Some(dir) => Replay::create(dir, self.id, seed, self.config.initial_period_ms as u32),
+            None => Replay::disabled(),
+        };
+        let spawns = self.map.spawns(self.clients.len());
+        for (client, spawn) in self.clients.iter().zip(&spawns) {
+            let name = self.names.get(&client.player_id).cloned().unwrap_or_default();
+            game.add_player(client.player_id, self.snake_colors[client.player_id as usize - 1], name, spawn);
+        }
+
+        let Room { id, clients, spectators, events_tx, events_rx, config, .. } = self;
+        thread::spawn(move || {
+            let results = game_loop(id, game, &config, clients, spectators, events_rx, replay);
+            let _ = finished.send((id, results));
+        });
+        events_tx
+    }
+}
+
+/// Implémentation du gestionnaire de salons
+impl RoomManager {
+    /// Renvoie un nouveau gestionnaire avec un salon en attente
+    ///
+    /// # Arguments
+    ///
+    /// * `config` - Configuration du serveur
+    /// * `map` - Map des parties
+    /// * `leaderboard` - Classement des joueurs
+    pub fn new(config: Arc<Config>, map: Arc<Map>, leaderboard: Leaderboard) -> Self {
+        let (finished_tx, finished_rx) = channel::<(u32, Vec<PlayerResult>)>();
+        RoomManager {
+            waiting: Room::new(1, config.clone(), map.clone()),
+            config,
+            map,
+            countdown_start: None,
+            next_id: 2,
+            next_connection: 1,
+            sessions: HashMap::new(),
+            running: HashMap::new(),
+            finished_tx,
+            finished_rx,
+            leaderboard,
+        }
+    }
+
+    /// Place un nouveau joueur dans le salon en attente et renvoie sa place
+    ///
+    /// # Arguments
+    ///
+    /// * `identity` - Identité du joueur ; ses statistiques sont enregistrées sous son adresse IP
+    ///   et affichées sous son pseudo s'il en a choisi un
+    fn join(&mut self, identity: Identity) -> Seat {
+        // Le jeton 0 est réservé
+        let token = loop {
+            let token = rand::random::<u64>();
+            if token != 0 && !self.sessions.contains_key(&token) {
+                break token;
+            }
+        };
+        let Identity { address, nickname } = identity;
+        let has_nickname = nickname.is_some();
+        let seat = self.waiting.add_client(token, self.next_connection, nickname);
+        self.next_connection += 1;
+        // Le pseudo affiché est celui rendu unique dans le salon
+        let name = self.waiting.names.get(&seat.player_id).filter(|_| has_nickname).cloned();
+        self.sessions.insert(token, Session {
+            room_id: self.waiting.id,
+            player_id: seat.player_id,
+            identity: address,
+            name,
+            events: seat.events.clone(),
+        });
+        seat
+    }
+
+    /// Rattache un joueur à sa partie en cours à partir de son jeton de session.
+    /// Renvoie *None* si le jeton est inconnu, si la partie est terminée ou si elle n'a pas encore commencé.
+    ///
+    /// # Arguments
+    ///
+    /// * `token` - Jeton de session présenté par le client
+    fn reconnect(&mut self, token: u64) -> Option<Seat> {
+        let (room_id, player_id, events) = {
+            let session = self.sessions.get(&token)?;
+            (session.room_id, session.player_id, session.events.clone())
+        };
+        if room_id == self.waiting.id {
+            // Le joueur est encore dans la file : il la rejoint à nouveau
+            self.waiting.clients.retain(|client| client.player_id != player_id);
+            self.waiting.names.remove(&player_id);
+            self.sessions.remove(&token);
+            return None;
+        }
+
+        let (outbox_tx, outbox_rx) = channel::<Vec<u8>>();
+        let connection = self.next_connection;
+        self.next_connection += 1;
+        let client = Client { player_id, connection, outbox: outbox_tx };
+        if events.send(ClientEvent::Reconnected(client)).is_err() {
+            // La partie est terminée
+            self.sessions.remove(&token);
+            return None;
+        }
+
+        println!("[salon {}] [{}] Reconnected", room_id, player_id);
+        Some(Seat {
+            player_id,
+            token,
+            connection,
+            outbox: outbox_rx,
+            events,
+        })
+    }
+
+    /// Ajoute un spectateur à une partie et renvoie sa place.
+    /// Sans salon demandé, le spectateur regarde la partie en cours la plus récente, ou à défaut
+    /// la prochaine partie, celle du salon en attente. Un salon inconnu ou terminé est refusé.
+    ///
+    /// # Arguments
+    ///
+    /// * `room_id` - Identifiant du salon (0 : la partie en cours la plus récente)
+    fn spectate(&mut self, room_id: u32) -> Result<Seat, String> {
+        let connection = self.next_connection;
+        self.next_connection += 1;
+
+        let room_id = match room_id {
+            0 => self.running.keys().max().cloned().unwrap_or(self.waiting.id),
+            room_id => room_id,
+        };
+        if room_id == self.waiting.id {
+            return Ok(self.waiting.add_spectator(connection));
+        }
+        if let Some(events) = self.running.get(&room_id) {
+            let (outbox_tx, outbox_rx) = channel::<Vec<u8>>();
+            let spectator = Client { player_id: 0, connection, outbox: outbox_tx };
+            if events.send(ClientEvent::Spectate(spectator)).is_ok() {
+                println!("[salon {}] New spectator", room_id);
+                return Ok(Seat {
+                    player_id: 0,
+                    token: 0,
+                    connection,
+                    outbox: outbox_rx,
+                    events: events.clone(),
+                });
+            }
+        }
+
+        Err(format!("salon inconnu : {}", room_id))
+    }
+
+    /// Ajoute les résultats d'une partie terminée au classement et l'enregistre
+    ///
+    /// # Arguments
+    ///
+    /// * `room_id` - Identifiant du salon
+    /// * `results` - Résultats des joueurs de la partie
+    fn record_results(&mut self, room_id: u32, results: &[PlayerResult]) {
+        for result in results {
+            let session = self.sessions.values().find(|session| session.room_id == room_id && session.player_id == result.player_id);
+            if let Some(session) = session {
+                self.leaderboard.record(&session.identity, session.name.as_deref(), result);
+            }
+        }
+        if let Err(e) = self.leaderboard.save() {
+            println!("Impossible d'enregistrer le classement : {}", e);
+        }
+    }
+
+    /// Renvoie le temps d'attente maximal d'une demande : la période de la file,
+    /// raccourcie pour que la partie démarre dès la fin du compte à rebours
+    fn queue_timeout(&self) -> time::Duration {
+        match self.countdown_start {
+            Some(start) => self.config.countdown().saturating_sub(start.elapsed()).min(QUEUE_PERIOD),
+            None => QUEUE_PERIOD,
+        }
+    }
+
+    /// Boucle du matchmaking : reçoit les demandes des threads de connexion, tient les joueurs
+    /// en attente informés de leur position et lance les parties.
+    ///
+    /// # Arguments
+    ///
+    /// * `requests` - Canal des demandes des threads de connexion
+    pub fn run(mut self, requests: Receiver<LobbyRequest>) {
+        let mut last_status = time::Instant::now();
+        loop {
+            let mut changed = false;
+            match requests.recv_timeout(self.queue_timeout()) {
+                Ok(LobbyRequest::Join(identity, reply)) => {
+                    let _ = reply.send(self.join(identity));
+                    changed = true;
+                }
+                Ok(LobbyRequest::Reconnect(token, identity, reply)) => {
+                    let seat = match self.reconnect(token) {
+                        Some(seat) => seat,
+                        // Jeton invalide : le client rejoint la file comme un nouveau joueur
+                        None => {
+                            changed = true;
+                            self.join(identity)
+                        }
+                    };
+                    let _ = reply.send(seat);
+                }
+                Ok(LobbyRequest::Spectate(room_id, reply)) => {
+                    let _ = reply.send(self.spectate(room_id));
+                }
+                Ok(LobbyRequest::Leaderboard(n, reply)) => {
+                    let _ = reply.send(protocol::leaderboard_packet(&self.leaderboard.top(n as usize)));
+                }
+                Err(RecvTimeoutError::Timeout) => (),
+                Err(RecvTimeoutError::Disconnected) => return,
+            }
+
+            // Les résultats des parties terminées sont ajoutés au classement,
+            // puis leurs sessions ne sont plus utiles
+            while let Ok((room_id, results)) = self.finished_rx.try_recv() {
+                self.record_results(room_id, &results);
+                self.sessions.retain(|_, session| session.room_id != room_id);
+                self.running.remove(&room_id);
+            }
+
+            let waiting_id = self.waiting.id;
+            for player_id in self.waiting.remove_disconnected() {
+                self.sessions.retain(|_, session| session.room_id != waiting_id || session.player_id != player_id);
+                changed = true;
+            }
+
+            // Le compte à rebours ne tourne que si le nombre minimal de joueurs est atteint
+            if self.waiting.len() < self.config.min_players as usize {
+                self.countdown_start = None;
+            } else if self.countdown_start.is_none() {
+                self.countdown_start = Some(time::Instant::now());
+            }
+
+            let time_left = self.countdown_start.map(|start| self.config.countdown().checked_sub(start.elapsed()).unwrap_or_default());
+            if self.waiting.len() >= self.config.max_players as usize || time_left == Some(time::Duration::from_secs(0)) {
+                let room = std::mem::replace(&mut self.waiting, Room::new(self.next_id, self.config.clone(), self.map.clone()));
+                self.next_id += 1;
+                self.countdown_start = None;
+                let room_id = room.id;
+                self.running.insert(room_id, room.start(self.finished_tx.clone()));
+            } else if changed || last_status.elapsed() >= QUEUE_PERIOD {
+                self.waiting.send_queue_status(self.config.min_players, self.config.max_players, time_left);
+                last_status = time::Instant::now();
+            }
+        }
+    }
+}
+
+/// Boucle de jeu d'une partie : c'est le seul thread qui fait avancer la partie.
+/// À chaque tick, les derniers mouvements des joueurs sont appliqués, tous les serpents
+/// avancent en une seule étape et une frame est envoyée à chaque client : chaque joueur reçoit
+/// la sienne (sans les serpents invisibles de ses adversaires), les spectateurs et le replay voient tout.
+/// Renvoie les résultats des joueurs à la fin de la partie.
+///
+/// # Arguments
+///
+/// * `room_id` - Identifiant du salon
+/// * `game` - La partie, dont tous les joueurs ont déjà été ajoutés
+/// * `config` - Configuration du serveur
+/// * `clients` - Clients des joueurs de la partie
+/// * `spectators` - Clients des spectateurs de la partie
+/// * `events` - Canal des événements envoyés par les threads de connexion
+/// * `replay` - Enregistrement du replay de la partie (paramètres, mouvements et frames)
+fn game_loop(room_id: u32, mut game: Game, config: &Config, mut clients: Vec<Client>, mut spectators: Vec<Client>, events: Receiver<ClientEvent>, mut replay: Replay) -> Vec<PlayerResult> {
+    let n_players = game.players.len();
+
+    // Tous les joueurs sont là ; on peut envoyer les paramètres du jeu
+    let players: Vec<protocol::PlayerParams> = game.players.iter()
+        .map(|player| protocol::PlayerParams {
+            id: player.id,
+            color: player.color,
+            x: player.head().x,
+            y: player.head().y,
+            direction: player.direction,
+            name: player.name.clone(),
+        })
+        .collect();
+    let params = match protocol::game_params_packet(game.map(), config.block_size, config.initial_speed, players) {
+        Ok(params) => params,
+        Err(e) => {
+            // Les clients sont déconnectés quand leurs canaux se ferment
+            println!("[salon {}] Impossible d'envoyer les paramètres de la partie : {}", room_id, e);
+            return vec![];
+        }
+    };
+    broadcast(&clients, &params);
+    broadcast(&spectators, &params);
+    replay.params(&params);
+    println!("[salon {}] Sent game params", room_id);
+
+    // Joueurs déconnectés, avec l'instant de leur déconnexion
+    let mut disconnected: Vec<(u8, time::Instant)> = vec![];
+
+    let mut last_frame = time::Instant::now();
+    let alive: Vec<u8> = loop {
+        thread::sleep(config.initial_period().checked_sub(last_frame.elapsed()).unwrap_or_default());
+        last_frame = time::Instant::now();
+
+        // Application des derniers mouvements reçus
+        while let Ok(event) = events.try_recv() {
+            match event {
+                ClientEvent::Move(player_id, mv) => {
+                    if let Some(index) = game.get_player(player_id) {
+                        replay.input(player_id, &mv);
+                        game.players[index].change_intent(mv);
+                    }
+                }
+                ClientEvent::Disconnected(0, connection) => {
+                    spectators.retain(|spectator| spectator.connection != connection);
+                }
+                ClientEvent::Disconnected(player_id, connection) => {
+                    // La fermeture d'une ancienne connexion, déjà remplacée par une reconnexion, est ignorée
+                    if clients.iter().any(|client| client.player_id == player_id && client.connection == connection) {
+                        println!("[salon {}] {} s'est déconnecté", room_id, game.player_name(player_id));
+                        disconnected.push((player_id, time::Instant::now()));
+                    }
+                }
+                ClientEvent::Reconnected(client) => {
+                    disconnected.retain(|(player_id, _)| *player_id != client.player_id);
+                    // Le client reconstruit la partie à partir des paramètres, puis des frames suivantes
+                    let _ = client.outbox.send(params.clone());
+                    clients.retain(|other| other.player_id != client.player_id);
+                    clients.push(client);
+                }
+                ClientEvent::Spectate(spectator) => {
+                    let _ = spectator.outbox.send(params.clone());
+                    spectators.push(spectator);
+                }
+            }
+        }
+
+        // Le serpent d'un joueur qui ne s'est pas reconnecté à temps a perdu ; les autres terminent la partie
+        for (player_id, _) in disconnected.iter().filter(|(_, instant)| instant.elapsed() > RECONNECT_GRACE) {
+            println!("[salon {}] {} a quitté la partie", room_id, game.player_name(*player_id));
+            replay.quit(*player_id);
+            game.set_lost(*player_id);
+        }
+        disconnected.retain(|(_, instant)| instant.elapsed() <= RECONNECT_GRACE);
+
+        game.step();
+        let snakes = game.players_alive();
+        // Une frame trop longue n'est pas envoyée ; la partie continue
+        for client in &clients {
+            match protocol::frame_packet(game.food(), &snakes, Some(client.player_id)) {
+                // Un client déconnecté ne doit pas interrompre la partie
+                Ok(frame) => { let _ = client.outbox.send(frame); }
+                Err(e) => println!("[salon {}] Frame non envoyée : {}", room_id, e),
+            }
+        }
+        match protocol::frame_packet(game.food(), &snakes, None) {
+            Ok(frame) => {
+                broadcast(&spectators, &frame);
+                replay.frame(&frame);
+            }
+            Err(e) => println!("[salon {}] Frame non envoyée : {}", room_id, e),
+        }
+
+        // Vérification du nombre de joueurs encore vivants
+        let alive = game.get_alive();
+        if alive.len() < std::cmp::min(2, n_players) {
+            break alive;
+        }
+    };
+
+    if alive.is_empty() {
+        // Tout le monde est mort
+        println!("[salon {}] Tout le monde est mort !", room_id);
+    } else if alive.len() == 1 {
+        // On a un gagnant
+        println!("[salon {}] {} a gagné", room_id, game.player_name(alive[0]));
+    }
+    replay.finish();
+
+    game.players.iter()
+        .map(|player| PlayerResult {
+            player_id: player.id,
+            won: alive.len() == 1 && alive[0] == player.id,
+            kills: player.kills,
+            max_length: player.max_length,
+        })
+        .collect()
+}
+
+/// Envoie un même paquet à plusieurs clients d'une partie
+///
+/// # Arguments
+///
+/// * `clients` - Clients destinataires
+/// * `packet` - Le paquet à envoyer
+fn broadcast(clients: &[Client], packet: &[u8]) {
+    for client in clients {
+        // Un client déconnecté ne doit pas interrompre la partie
+        let _ = client.outbox.send(packet.to_vec());
+    }
+}
+
+/// Nettoie un pseudo reçu d'un client : seuls les lettres, chiffres, espaces et `-_.'` sont gardés,
+/// les espaces consécutifs sont fusionnés et le pseudo est tronqué à `MAX_NICKNAME_LEN` caractères.
+/// Renvoie *None* si rien n'en reste.
+///
+/// # Arguments
+///
+/// * `raw` - Pseudo tel que reçu
+pub fn sanitize_nickname(raw: &str) -> Option<String> {
+    let kept: String = raw.chars()
+        .map(|c| if c.is_whitespace() { ' ' } else { c })
+        .filter(|c| c.is_alphanumeric() || " -_.'".contains(*c))
+        .collect();
+    let nickname: String = kept.split_whitespace().collect::<Vec<_>>().join(" ").chars().take(MAX_NICKNAME_LEN).collect();
+    let nickname = nickname.trim_end().to_string();
+    if nickname.is_empty() { None } else { Some(nickname) }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sanitize_nickname() {
+        assert_eq!(sanitize_nickname("  Léa\t  la  Terrible "), Some("Léa la Terrible".to_string()));
+        assert_eq!(sanitize_nickname("<b>bob</b>\n"), Some("bbobb".to_string()));
+        assert_eq!(sanitize_nickname("un pseudo vraiment très long"), Some("un pseudo vraime".to_string()));
+        assert_eq!(sanitize_nickname("abcdefghijklmno pq"), Some("abcdefghijklmno".to_string()));
+        assert_eq!(sanitize_nickname(" \u{0}<> "), None);
+    }
+
+    #[test]
+    fn test_unique_names() {
+        let mut room = Room::new(1, Arc::new(Config::default()), Arc::new(Map::empty(64, 64)));
+        room.add_client(1, 1, Some("Bob".to_string()));
+        room.add_client(2, 2, Some("Bob".to_string()));
+        room.add_client(3, 3, None);
+        room.add_client(4, 4, Some("Bob".to_string()));
+        let mut names: Vec<&str> = room.names.values().map(|name| name.as_str()).collect();
+        names.sort();
+        assert_eq!(names, ["Bob", "Bob 2", "Bob 3", "Joueur 3"]);
+    }
+
+    #[test]
+    fn test_start_after_player_left() {
+        // Ticks courts sur une petite map : les serpents atteignent vite un mur et la partie se termine
+        let config = Config { initial_period_ms: 5, ..Config::default() };
+        let mut room = Room::new(1, Arc::new(config), Arc::new(Map::empty(32, 32)));
+        let first = room.add_client(1, 1, None);
+        let second = room.add_client(2, 2, None);
+        let third = room.add_client(3, 3, None);
+        second.events.send(ClientEvent::Disconnected(second.player_id, second.connection)).unwrap();
+        assert_eq!(room.remove_disconnected(), vec![2]);
+
+        // Les joueurs 1 et 3 jouent : la boucle de jeu doit envoyer les paramètres puis des frames
+        let (finished_tx, finished_rx) = channel();
+        room.start(finished_tx);
+        let timeout = time::Duration::from_secs(5);
+        for seat in [&first, &third] {
+            assert_eq!(seat.outbox.recv_timeout(timeout).unwrap()[0], 1);
+            for _ in 0..3 {
+                assert_eq!(seat.outbox.recv_timeout(timeout).unwrap()[0], 3);
+            }
+        }
+
+        // La boucle de jeu doit être terminée avant la fin du test
+        let (room_id, results) = finished_rx.recv_timeout(timeout).unwrap();
+        assert_eq!(room_id, 1);
+        assert_eq!(results.iter().map(|result| result.player_id).collect::<Vec<_>>(), [1, 3]);
+    }
+
+    #[test]
+    fn test_leaderboard_identity() {
+        let path = std::env::temp_dir().join(format!("snecc-room-leaderboard-{}.toml", std::process::id()));
+        let leaderboard = Leaderboard::load(path.to_str().unwrap()).unwrap();
+        let mut manager = RoomManager::new(Arc::new(Config::default()), Arc::new(Map::empty(64, 64)), leaderboard);
+        let identity = |address: &str, nickname: &str| Identity { address: address.to_string(), nickname: Some(nickname.to_string()) };
+        let bob = manager.join(identity("10.0.0.1", "Bob"));
+        let impostor = manager.join(identity("10.0.0.2", "Bob"));
+
+        // Chaque joueur est enregistré sous son adresse IP, et affiché sous son pseudo unique dans la partie
+        let results: Vec<PlayerResult> = [(&bob, true), (&impostor, false)].iter()
+            .map(|(seat, won)| PlayerResult { player_id: seat.player_id, won: *won, kills: 0, max_length: 5 })
+            .collect();
+        manager.record_results(1, &results);
+        let _ = std::fs::remove_file(&path);
+        let top = manager.leaderboard.top(10);
+        assert_eq!(top.iter().map(|(name, stats)| (*name, stats.wins, stats.losses)).collect::<Vec<_>>(), [("Bob", 1, 0), ("Bob 2", 0, 1)]);
+    }
+
+    #[test]
+    fn test_spectate_unknown_room() {
+        let path = std::env::temp_dir().join(format!("snecc-room-spectate-{}.toml", std::process::id()));
+        let leaderboard = Leaderboard::load(path.to_str().unwrap()).unwrap();
+        let mut manager = RoomManager::new(Arc::new(Config::default()), Arc::new(Map::empty(64, 64)), leaderboard);
+
+        // Sans partie en cours, le spectateur regarde le salon en attente, qu'il l'ait demandé ou non
+        assert!(manager.spectate(0).is_ok());
+        assert!(manager.spectate(1).is_ok());
+        assert_eq!(manager.waiting.spectators.len(), 2);
+
+        // Un salon inconnu est refusé au lieu de renvoyer vers une autre partie
+        assert_eq!(manager.spectate(42).err(), Some("salon inconnu : 42".to_string()));
+        assert_eq!(manager.waiting.spectators.len(), 2);
+    }
+
+    #[test]
+    fn test_queue_timeout() {
+        let path = std::env::temp_dir().join(format!("snecc-room-timeout-{}.toml", std::process::id()));
+        let leaderboard = Leaderboard::load(path.to_str().unwrap()).unwrap();
+        let mut manager = RoomManager::new(Arc::new(Config::default()), Arc::new(Map::empty(64, 64)), leaderboard);
+        assert_eq!(manager.queue_timeout(), QUEUE_PERIOD);
+
+        // Le compte à rebours se termine avant la prochaine période : l'attente est raccourcie
+        let countdown = manager.config.countdown();
+        manager.countdown_start = time::Instant::now().checked_sub(countdown - QUEUE_PERIOD / 5);
+        assert!(manager.queue_timeout() <= QUEUE_PERIOD / 5);
+        manager.countdown_start = time::Instant::now().checked_sub(countdown * 2);
+        assert_eq!(manager.queue_timeout(), time::Duration::ZERO);
+    }
+}
@@ -0,0 +1,254 @@
+use crate::game_serv::Move;
+
+/// Nombre maximal de joueurs d'une partie
+pub const MAX_PLAYERS: u8 = 16;
+
+/// Distance minimale (en blocs) entre deux points d'apparition
+const MIN_SPACING: i32 = 3;
+
+/// Point d'apparition d'un serpent
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Spawn {
+    /// Coordonnées initiales
+    pub x: u16,
+    pub y: u16,
+    /// Direction initiale, vers l'intérieur de la map
+    pub direction: Move,
+}
+
+/// Anneau rectangulaire sur lequel les serpents apparaissent
+#[derive(Clone, Copy)]
+struct Ring {
+    /// Bords gauche et droit
+    left: i32,
+    right: i32,
+    /// Bords haut et bas
+    top: i32,
+    bottom: i32,
+}
+
+/// Implémentation de l'anneau d'apparition
+impl Ring {
+    /// Renvoie l'anneau d'apparition d'une map, à distance des murs
+    ///
+    /// # Arguments
+    ///
+    /// * `width`, `height` - Dimensions de la map en blocs
+    fn new(width: u16, height: u16) -> Self {
+        let margin = std::cmp::max(3, std::cmp::min(width, height) as i32 / 16);
+        Ring {
+            left: margin,
+            right: width as i32 - 1 - margin,
+            top: margin,
+            bottom: height as i32 - 1 - margin,
+        }
+    }
+
+    /// Renvoie la longueur de l'anneau en blocs
+    fn perimeter(&self) -> i32 {
+        2 * (self.right - self.left) + 2 * (self.bottom - self.top)
+    }
+
+    /// Renvoie si un point est un coin de l'anneau
+    ///
+    /// # Arguments
+    ///
+    /// * `x`, `y` - Coordonnées du point
+    fn is_corner(&self, x: i32, y: i32) -> bool {
+        (x == self.left || x == self.right) && (y == self.top || y == self.bottom)
+    }
+
+    /// Renvoie le point d'apparition situé à une position donnée de l'anneau
+    ///
+    /// # Arguments
+    ///
+    /// * `t` - Abscisse curviligne sur l'anneau, dans le sens horaire depuis le coin en haut à gauche
+    fn point(&self, t: i32) -> Spawn {
+        let (w, h) = (self.right - self.left, self.bottom - self.top);
+        let (x, y, direction) = if t < w {
+            (self.left + t, self.top, Move::Down)
+        } else if t < w + h {
+            (self.right, self.top + t - w, Move::Left)
+        } else if t < 2 * w + h {
+            (self.right - (t - w - h), self.bottom, Move::Up)
+        } else {
+            (self.left, self.bottom - (t - 2 * w - h), Move::Right)
+        };
+        Spawn { x: x as u16, y: y as u16, direction }
+    }
+}
+
+/// Renvoie le nombre maximal de joueurs qui peuvent apparaître sur une map de cette taille :
+/// pour tout nombre de joueurs jusqu'à celui-ci, le placement est sans conflit.
+///
+/// # Arguments
+///
+/// * `width`, `height` - Dimensions de la map en blocs
+pub fn capacity(width: u16, height: u16) -> u8 {
+    let ring = Ring::new(width, height);
+    (1..=MAX_PLAYERS)
+        .take_while(|&n| conflicts(&plan(n as usize, width, height), &ring) == 0)
+        .last()
+        .unwrap_or(0)
+}
+
+/// Place `n` serpents à intervalles réguliers sur un anneau rectangulaire à distance des murs,
+/// chacun tourné vers l'intérieur de la map.
+/// Le décalage de départ sur l'anneau est choisi, puis chaque point est éventuellement décalé
+/// de quelques blocs, de sorte qu'aucun point ne soit dans un coin, que deux serpents ne soient
+/// pas trop proches, ni face à face sur la même ligne, ni sur le point d'atteindre le même bloc au même moment.
+///
+/// # Arguments
+///
+/// * `n` - Nombre de serpents
+/// * `width`, `height` - Dimensions de la map en blocs
+pub fn plan(n: usize, width: u16, height: u16) -> Vec<Spawn> {
+    let ring = Ring::new(width, height);
+    let perimeter = ring.perimeter();
+    let place = |positions: &[i32]| -> Vec<Spawn> {
+        positions.iter().map(|&t| ring.point(t)).collect()
+    };
+
+    // Positions régulières, avec le décalage de départ qui donne le moins de conflits
+    let mut phases: Vec<i32> = (0..64).collect();
+    phases.sort_by_key(|k| (k - 32).abs());
+    let mut positions: Vec<i32> = phases.into_iter()
+        .map(|k| (0..n)
+            .map(|i| (((i as f64 + k as f64 / 64.0) * perimeter as f64 / n as f64).round() as i32) % perimeter)
+            .collect::<Vec<i32>>())
+        .min_by_key(|positions| conflicts(&place(positions), &ring))
+        .unwrap();
+
+    // Décalage des points restant en conflit, tant que cela réduit le nombre de conflits
+    let mut best = conflicts(&place(&positions), &ring);
+    while best > 0 {
+        let mut improved = false;
+        for i in 0..n {
+            for shift in &[1, -1, 2, -2] {
+                let mut candidate = positions.clone();
+                candidate[i] = (candidate[i] + shift + perimeter) % perimeter;
+                let count = conflicts(&place(&candidate), &ring);
+                if count < best {
+                    positions = candidate;
+                    best = count;
+                    improved = true;
+                }
+            }
+        }
+        if !improved {
+            break;
+        }
+    }
+
+    place(&positions)
+}
+
+/// Renvoie le nombre de points placés dans un coin de l'anneau (ils regarderaient le long de leur côté)
+/// et de paires de points d'apparition en conflit
+///
+/// # Arguments
+///
+/// * `spawns` - Points d'apparition
+/// * `ring` - Anneau d'apparition
+fn conflicts(spawns: &[Spawn], ring: &Ring) -> usize {
+    let corners = spawns.iter()
+        .filter(|spawn| ring.is_corner(spawn.x as i32, spawn.y as i32))
+        .count();
+
+    let mut pairs = 0;
+    for (i, a) in spawns.iter().enumerate() {
+        for b in &spawns[i + 1..] {
+            if in_conflict(a, b) {
+                pairs += 1;
+            }
+        }
+    }
+    corners + pairs
+}
+
+/// Renvoie si un mouvement est vertical
+fn is_vertical(direction: Move) -> bool {
+    direction == Move::Up || direction == Move::Down
+}
+
+/// Renvoie le déplacement unitaire correspondant à une direction
+fn delta(direction: Move) -> (i32, i32) {
+    match direction {
+        Move::Up => (0, -1),
+        Move::Down => (0, 1),
+        Move::Left => (-1, 0),
+        Move::Right => (1, 0),
+    }
+}
+
+/// Renvoie si deux points d'apparition sont en conflit : trop proches, face à face sur la même ligne,
+/// ou dont les trajectoires se croisent au même moment
+///
+/// # Arguments
+///
+/// * `a`, `b` - Points d'apparition
+fn in_conflict(a: &Spawn, b: &Spawn) -> bool {
+    let (ax, ay, bx, by) = (a.x as i32, a.y as i32, b.x as i32, b.y as i32);
+    if std::cmp::max((ax - bx).abs(), (ay - by).abs()) < MIN_SPACING {
+        return true;
+    }
+
+    let (adx, ady) = delta(a.direction);
+    let (bdx, bdy) = delta(b.direction);
+    match (is_vertical(a.direction), is_vertical(b.direction)) {
+        // Face à face sur la même colonne ou la même ligne
+        (true, true) => ax == bx && ady != bdy && (by - ay) * ady > 0,
+        (false, false) => ay == by && adx != bdx && (bx - ax) * adx > 0,
+        // Trajectoires perpendiculaires : conflit si les deux têtes atteignent le croisement ensemble
+        (true, false) => {
+            let (ta, tb) = ((by - ay) * ady, (ax - bx) * bdx);
+            ta >= 0 && tb >= 0 && (ta - tb).abs() <= 1
+        }
+        (false, true) => {
+            let (ta, tb) = ((bx - ax) * adx, (ay - by) * bdy);
+            ta >= 0 && tb >= 0 && (ta - tb).abs() <= 1
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_plan_no_conflict() {
+        for (width, height) in [(16, 16), (32, 32), (64, 64), (100, 100), (96, 32), (20, 60)] {
+            let ring = Ring::new(width, height);
+            for n in 1..=capacity(width, height) as usize {
+                let spawns = plan(n, width, height);
+                assert_eq!(spawns.len(), n);
+                assert_eq!(conflicts(&spawns, &ring), 0, "{} joueurs sur une map de {}x{}", n, width, height);
+            }
+        }
+    }
+
+    #[test]
+    fn test_plan_inward() {
+        for (width, height) in [(64, 64), (96, 48)] {
+            let ring = Ring::new(width, height);
+            for spawn in plan(16, width, height) {
+                let (x, y) = (spawn.x as i32, spawn.y as i32);
+                assert!(x >= ring.left && x <= ring.right && y >= ring.top && y <= ring.bottom);
+                let (dx, dy) = delta(spawn.direction);
+                // Le bloc suivant se rapproche du centre
+                let (cx, cy) = ((width - 1) as f64 / 2.0, (height - 1) as f64 / 2.0);
+                let before = (x as f64 - cx).abs() + (y as f64 - cy).abs();
+                let after = ((x + dx) as f64 - cx).abs() + ((y + dy) as f64 - cy).abs();
+                assert!(after < before);
+            }
+        }
+    }
+
+    #[test]
+    fn test_capacity() {
+        assert_eq!(capacity(64, 64), MAX_PLAYERS);
+        assert_eq!(capacity(96, 48), MAX_PLAYERS);
+        assert!(capacity(16, 16) >= 4);
+        assert!(capacity(32, 32) >= 8);
+    }
+}
@@ -0,0 +1,24 @@
+//! Contenu quelconque décodé comme les deux plus gros messages, Frame et GameParams :
+//! le décodage ne doit jamais paniquer, et une frame décodée doit se réencoder à l'identique.
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use snecc_protocol::{Frame, GameParams, Message};
+
+fuzz_target!(|data: &[u8]| {
+    if let Ok(frame) = Frame::decode(data) {
+        let mut encoded = vec![];
+        frame.encode(&mut encoded);
+        assert_eq!(encoded, data);
+    }
+    if let Ok(params) = GameParams::decode(data) {
+        // Les pseudos invalides en UTF-8 sont remplacés et peuvent être tronqués au réencodage :
+        // on vérifie seulement que le réencodage reste décodable, avec la même structure
+        let mut encoded = vec![];
+        params.encode(&mut encoded);
+        let reencoded = GameParams::decode(&encoded).expect("réencodage invalide");
+        assert_eq!(reencoded.players.len(), params.players.len());
+        assert_eq!(reencoded.walls, params.walls);
+    }
+});
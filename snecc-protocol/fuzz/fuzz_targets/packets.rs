@@ -0,0 +1,50 @@
+//! Flux d'octets quelconque lu paquet par paquet, comme le font le serveur et le client :
+//! le décodage ne doit jamais paniquer, et un message à format fixe décodé sans erreur
+//! doit se réencoder en exactement les mêmes octets.
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use snecc_protocol::*;
+use std::io::Cursor;
+
+fuzz_target!(|data: &[u8]| {
+    let mut stream = Cursor::new(data);
+    while let Ok((msg, content)) = read_packet(&mut stream) {
+        match msg {
+            Msg::PlayerId => check_exact::<PlayerId>(&content),
+            Msg::GameParams => {
+                let _ = GameParams::decode(&content);
+            }
+            Msg::GameStart => {}
+            Msg::Frame => check_exact::<Frame>(&content),
+            Msg::Move => check_exact::<Move>(&content),
+            Msg::Queue => check_exact::<QueueStatus>(&content),
+            Msg::Join => check_exact::<Join>(&content),
+            Msg::Leaderboard => {
+                check_exact::<LeaderboardRequest>(&content);
+                let _ = Leaderboard::decode(&content);
+            }
+            Msg::Hello => {
+                let _ = Hello::decode(&content);
+            }
+            Msg::Version => check_exact::<Version>(&content),
+            Msg::Reject => {
+                let _ = Reject::decode(&content);
+            }
+        }
+    }
+});
+
+/// Vérifie qu'un message décodé sans erreur se réencode à l'identique
+///
+/// # Arguments
+///
+/// `content` - Contenu du message
+fn check_exact<M: Message>(content: &[u8]) {
+    if let Ok(message) = M::decode(content) {
+        let mut encoded = vec![];
+        message.encode(&mut encoded);
+        assert_eq!(encoded, content);
+    }
+}
@@ -0,0 +1,385 @@
+//! Protocole réseau de Snecc, partagé par le serveur et le client.
+//!
+//! Chaque message est un paquet `[type, longueur u16, contenu]` ; les entiers sont en little-endian.
+//! Les messages typés (voir `messages`) implémentent le trait `Message`, qui les encode en paquets
+//! et les décode à partir de leur contenu : le serveur et le client utilisent le même code,
+//! ils ne peuvent donc pas diverger.
+
+use std::fmt;
+use std::io;
+
+mod messages;
+pub use messages::*;
+
+pub mod replay;
+
+/// Version du protocole : le client et le serveur doivent avoir la même
+pub const PROTOCOL_VERSION: u16 = 3;
+
+/// Fonctionnalité facultative : pseudos des joueurs (message Hello)
+pub const FEATURE_NICKNAMES: u32 = 1;
+/// Fonctionnalité facultative : demande du classement (message Leaderboard)
+pub const FEATURE_LEADERBOARD: u32 = 1 << 1;
+/// Fonctionnalité facultative : état de la file d'attente (message Queue)
+pub const FEATURE_QUEUE_STATUS: u32 = 1 << 2;
+
+/// Taille de l'en-tête d'un paquet : type, longueur (u16)
+pub const HEADER_LEN: usize = 3;
+
+/// Longueur maximale du contenu d'un paquet, codée sur un u16
+pub const MAX_CONTENT_LEN: usize = u16::MAX as usize;
+
+/// Différents types de messages
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Msg {
+    PlayerId,
+    GameParams,
+    GameStart,
+    Frame,
+    Move,
+    Queue,
+    Join,
+    Leaderboard,
+    Hello,
+    Version,
+    Reject,
+}
+
+/// Erreur de décodage d'un message reçu : le message est ignoré et la connexion qui l'a envoyé doit être fermée.
+/// Sert aussi à signaler un message trop long pour être encodé.
+#[derive(Clone, PartialEq, Debug)]
+pub enum ProtocolError {
+    /// Identifiant de message inconnu
+    UnknownMessage(u8),
+    /// Message valide, mais inattendu à cette étape des échanges
+    UnexpectedMessage(Msg),
+    /// Contenu plus court que ce qu'annoncent ses champs
+    Truncated(Msg),
+    /// Octets en trop à la fin du contenu
+    TrailingBytes(Msg),
+    /// Valeur invalide d'un champ du message (type du message, nom du champ, valeur reçue)
+    InvalidValue(Msg, &'static str, u8),
+    /// Identifiant de joueur qui ne fait pas partie de la partie
+    UnknownPlayer(u8),
+    /// Contenu trop long pour que sa longueur tienne sur un u16 (type du message, longueur du contenu)
+    TooLong(Msg, usize),
+}
+
+impl fmt::Display for ProtocolError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ProtocolError::UnknownMessage(id) => write!(f, "identifiant de message inconnu ({})", id),
+            ProtocolError::UnexpectedMessage(msg) => write!(f, "message {:?} inattendu", msg),
+            ProtocolError::Truncated(msg) => write!(f, "message {:?} tronqué", msg),
+            ProtocolError::TrailingBytes(msg) => write!(f, "octets en trop à la fin du message {:?}", msg),
+            ProtocolError::InvalidValue(msg, field, value) => write!(f, "{} invalide ({}) dans le message {:?}", field, value, msg),
+            ProtocolError::UnknownPlayer(id) => write!(f, "joueur {} inconnu", id),
+            ProtocolError::TooLong(msg, len) => write!(f, "message {:?} trop long ({} octets)", msg, len),
+        }
+    }
+}
+
+impl std::error::Error for ProtocolError {}
+
+/// Une erreur de protocole lors d'une lecture sur un flux devient une erreur d'entrée/sortie `InvalidData`
+impl From<ProtocolError> for io::Error {
+    fn from(error: ProtocolError) -> Self {
+        io::Error::new(io::ErrorKind::InvalidData, error)
+    }
+}
+
+/// Renvoie un objet message à partir de son identifiant
+///
+/// # Arguments
+///
+/// `id` - Identifiant du message
+pub fn id_to_msg(id: u8) -> Result<Msg, ProtocolError> {
+    match id {
+        0 => Ok(Msg::PlayerId),
+        1 => Ok(Msg::GameParams),
+        2 => Ok(Msg::GameStart),
+        3 => Ok(Msg::Frame),
+        4 => Ok(Msg::Move),
+        5 => Ok(Msg::Queue),
+        6 => Ok(Msg::Join),
+        7 => Ok(Msg::Leaderboard),
+        8 => Ok(Msg::Hello),
+        9 => Ok(Msg::Version),
+        10 => Ok(Msg::Reject),
+        _ => Err(ProtocolError::UnknownMessage(id)),
+    }
+}
+
+/// Renvoie l'identifiant numérique associé à un message
+///
+/// # Arguments
+///
+/// `msg` - Type du message
+pub fn msg_to_id(msg: Msg) -> u8 {
+    match msg {
+        Msg::PlayerId => 0,
+        Msg::GameParams => 1,
+        Msg::GameStart => 2,
+        Msg::Frame => 3,
+        Msg::Move => 4,
+        Msg::Queue => 5,
+        Msg::Join => 6,
+        Msg::Leaderboard => 7,
+        Msg::Hello => 8,
+        Msg::Version => 9,
+        Msg::Reject => 10,
+    }
+}
+
+/// Construit un paquet (en-tête et contenu) prêt à être envoyé.
+/// Renvoie une erreur si le contenu dépasse `MAX_CONTENT_LEN` octets.
+///
+/// # Arguments
+///
+/// `msg` - Type du message
+/// `content` - Slice vers le contenu du message
+pub fn pack(msg: Msg, content: &[u8]) -> Result<Vec<u8>, ProtocolError> {
+    let n = content.len();
+    if n > MAX_CONTENT_LEN {
+        return Err(ProtocolError::TooLong(msg, n));
+    }
+    Ok([&[msg_to_id(msg), (n % 256) as u8, (n / 256) as u8], content].concat())
+}
+
+/// Lit l'en-tête d'un paquet et renvoie le couple (type du message, longueur du contenu)
+///
+/// # Arguments
+///
+/// `header` - Référence vers les octets de l'en-tête
+pub fn parse_header(header: &[u8; HEADER_LEN]) -> Result<(Msg, usize), ProtocolError> {
+    Ok((id_to_msg(header[0])?, u16::from_le_bytes([header[1], header[2]]) as usize))
+}
+
+/// Lit un paquet complet (en-tête puis contenu) et renvoie le couple (type du message, contenu).
+/// Renvoie une erreur si la lecture échoue (connexion perdue, flux tronqué)
+/// ou si l'identifiant du message est inconnu.
+///
+/// # Arguments
+///
+/// `reader` - Référence mutable vers le flux à lire
+pub fn read_packet<R: io::Read>(reader: &mut R) -> io::Result<(Msg, Vec<u8>)> {
+    let mut header = [0; HEADER_LEN];
+    reader.read_exact(&mut header)?;
+    let (msg, len) = parse_header(&header)?;
+
+    let mut content = vec![0; len];
+    reader.read_exact(&mut content)?;
+    Ok((msg, content))
+}
+
+/// Message typé du protocole
+pub trait Message: Sized {
+    /// Type du message
+    const MSG: Msg;
+
+    /// Écrit le contenu du message (sans en-tête)
+    ///
+    /// # Arguments
+    ///
+    /// `out` - Référence mutable vers le tampon de sortie
+    fn encode(&self, out: &mut Vec<u8>);
+
+    /// Lit un message à partir de son contenu (sans en-tête).
+    /// Renvoie une erreur si le contenu est mal formé.
+    ///
+    /// # Arguments
+    ///
+    /// `content` - Slice vers le contenu du message
+    fn decode(content: &[u8]) -> Result<Self, ProtocolError>;
+
+    /// Construit le paquet du message (en-tête et contenu).
+    /// Renvoie une erreur si le contenu est trop long.
+    fn packet(&self) -> Result<Vec<u8>, ProtocolError> {
+        let mut content = vec![];
+        self.encode(&mut content);
+        pack(Self::MSG, &content)
+    }
+}
+
+/// Lecteur du contenu d'un message : lit les champs les uns après les autres,
+/// en vérifiant qu'il ne dépasse pas la fin du contenu
+pub(crate) struct Reader<'a> {
+    /// Type du message lu, pour les erreurs
+    msg: Msg,
+    /// Contenu du message
+    content: &'a [u8],
+    /// Indice du prochain octet à lire
+    index: usize,
+}
+
+/// Implémentation du lecteur
+impl<'a> Reader<'a> {
+    /// Renvoie un lecteur au début d'un contenu
+    ///
+    /// # Arguments
+    ///
+    /// `msg` - Type du message
+    /// `content` - Slice vers le contenu du message
+    pub fn new(msg: Msg, content: &'a [u8]) -> Self {
+        Reader { msg, content, index: 0 }
+    }
+
+    /// Renvoie une erreur de valeur invalide pour un champ du message lu
+    ///
+    /// # Arguments
+    ///
+    /// `field` - Nom du champ
+    /// `value` - Valeur reçue
+    pub fn invalid(&self, field: &'static str, value: u8) -> ProtocolError {
+        ProtocolError::InvalidValue(self.msg, field, value)
+    }
+
+    /// Lit `n` octets
+    ///
+    /// # Arguments
+    ///
+    /// `n` - Nombre d'octets à lire
+    pub fn bytes(&mut self, n: usize) -> Result<&'a [u8], ProtocolError> {
+        let end = self.index.checked_add(n).ok_or(ProtocolError::Truncated(self.msg))?;
+        let bytes = self.content.get(self.index..end).ok_or(ProtocolError::Truncated(self.msg))?;
+        self.index = end;
+        Ok(bytes)
+    }
+
+    /// Lit un octet
+    pub fn u8(&mut self) -> Result<u8, ProtocolError> {
+        Ok(self.bytes(1)?[0])
+    }
+
+    /// Lit un booléen (0 ou 1)
+    ///
+    /// # Arguments
+    ///
+    /// `field` - Nom du champ, pour l'erreur si la valeur est invalide
+    pub fn bool(&mut self, field: &'static str) -> Result<bool, ProtocolError> {
+        match self.u8()? {
+            0 => Ok(false),
+            1 => Ok(true),
+            value => Err(self.invalid(field, value)),
+        }
+    }
+
+    /// Lit un entier 16 bits non signé
+    pub fn u16(&mut self) -> Result<u16, ProtocolError> {
+        let bytes = self.bytes(2)?;
+        Ok(u16::from_le_bytes([bytes[0], bytes[1]]))
+    }
+
+    /// Lit un entier 16 bits signé
+    pub fn i16(&mut self) -> Result<i16, ProtocolError> {
+        Ok(self.u16()? as i16)
+    }
+
+    /// Lit un entier 32 bits non signé
+    pub fn u32(&mut self) -> Result<u32, ProtocolError> {
+        let bytes = self.bytes(4)?;
+        Ok(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+    }
+
+    /// Lit un entier 64 bits non signé
+    pub fn u64(&mut self) -> Result<u64, ProtocolError> {
+        let bytes = self.bytes(8)?;
+        let mut array = [0; 8];
+        array.copy_from_slice(bytes);
+        Ok(u64::from_le_bytes(array))
+    }
+
+    /// Lit une chaîne précédée de sa longueur (u8) ; les octets invalides en UTF-8 sont remplacés
+    pub fn string(&mut self) -> Result<String, ProtocolError> {
+        let len = self.u8()? as usize;
+        Ok(String::from_utf8_lossy(self.bytes(len)?).to_string())
+    }
+
+    /// Indique si tout le contenu a été lu
+    pub fn is_empty(&self) -> bool {
+        self.index >= self.content.len()
+    }
+
+    /// Termine la lecture d'un message : renvoie une erreur s'il reste des octets
+    pub fn finish(self) -> Result<(), ProtocolError> {
+        if self.is_empty() { Ok(()) } else { Err(ProtocolError::TrailingBytes(self.msg)) }
+    }
+}
+
+/// Écrit une chaîne précédée de sa longueur (u8) ; une chaîne trop longue est tronquée
+/// (sans couper de caractère)
+///
+/// # Arguments
+///
+/// `out` - Référence mutable vers le tampon de sortie
+/// `s` - Chaîne à écrire
+pub(crate) fn put_string(out: &mut Vec<u8>, s: &str) {
+    let mut len = std::cmp::min(s.len(), u8::MAX as usize);
+    while !s.is_char_boundary(len) {
+        len -= 1;
+    }
+    out.push(len as u8);
+    out.extend_from_slice(&s.as_bytes()[..len]);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_msg_ids() {
+        for id in 0..=10 {
+            assert_eq!(msg_to_id(id_to_msg(id).unwrap()), id);
+        }
+        assert_eq!(id_to_msg(11), Err(ProtocolError::UnknownMessage(11)));
+    }
+
+    #[test]
+    fn test_pack_and_parse_header() {
+        let packet = pack(Msg::Frame, &[0; 300]).unwrap();
+        assert_eq!(&packet[..HEADER_LEN], [3, 0x2C, 0x01]);
+        assert_eq!(&pack(Msg::Frame, &[0; MAX_CONTENT_LEN]).unwrap()[..HEADER_LEN], [3, 0xFF, 0xFF]);
+        assert_eq!(pack(Msg::Frame, &[0; MAX_CONTENT_LEN + 1]), Err(ProtocolError::TooLong(Msg::Frame, 65536)));
+        assert_eq!(parse_header(&[3, 0x2C, 0x01]), Ok((Msg::Frame, 300)));
+        assert_eq!(parse_header(&[0xFF, 0, 0]), Err(ProtocolError::UnknownMessage(0xFF)));
+    }
+
+    #[test]
+    fn test_reader() {
+        let mut out = vec![7, 0xff, 0xff, 1, 2, 3, 4, 1];
+        put_string(&mut out, "Léa");
+        let mut reader = Reader::new(Msg::Frame, &out);
+        assert_eq!(reader.u8(), Ok(7));
+        assert_eq!(reader.i16(), Ok(-1));
+        assert_eq!(reader.u32(), Ok(0x04030201));
+        assert_eq!(reader.bool("perdu"), Ok(true));
+        assert_eq!(reader.string(), Ok("Léa".to_string()));
+        assert!(reader.is_empty());
+        assert_eq!(reader.u8(), Err(ProtocolError::Truncated(Msg::Frame)));
+        assert_eq!(Reader::new(Msg::Frame, &[1, 2]).u32(), Err(ProtocolError::Truncated(Msg::Frame)));
+        assert_eq!(Reader::new(Msg::Frame, &[2]).bool("perdu"), Err(ProtocolError::InvalidValue(Msg::Frame, "perdu", 2)));
+        assert_eq!(Reader::new(Msg::Move, &[1, 2]).finish(), Err(ProtocolError::TrailingBytes(Msg::Move)));
+        assert_eq!(Reader::new(Msg::Frame, &[5]).string(), Err(ProtocolError::Truncated(Msg::Frame)));
+
+        // Une chaîne trop longue est tronquée entre deux caractères
+        let mut out = vec![];
+        put_string(&mut out, &"é".repeat(200));
+        assert_eq!(out[0], 254);
+        assert_eq!(Reader::new(Msg::Hello, &out).string(), Ok("é".repeat(127)));
+    }
+
+    #[test]
+    fn test_read_packet() {
+        let mut stream = io::Cursor::new(vec![4, 1, 0, 3, 9, 6]);
+        assert_eq!(read_packet(&mut stream).unwrap(), (Msg::Move, vec![3]));
+        assert_eq!(read_packet(&mut stream).unwrap_err().kind(), io::ErrorKind::UnexpectedEof);
+        assert_eq!(read_packet(&mut io::Cursor::new(vec![42, 0, 0])).unwrap_err().kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn test_io_error() {
+        let error: io::Error = ProtocolError::UnknownPlayer(3).into();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+        assert_eq!(error.to_string(), "joueur 3 inconnu");
+    }
+}
@@ -0,0 +1,728 @@
+use crate::{put_string, Message, Msg, ProtocolError, Reader};
+
+/// Différents mouvements
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Move {
+    Up,
+    Down,
+    Left,
+    Right,
+}
+
+/// Implémentation d'un mouvement
+impl Move {
+    /// Renvoie l'octet codant le mouvement dans le protocole
+    pub fn to_id(self) -> u8 {
+        match self {
+            Move::Up => 1,
+            Move::Down => 2,
+            Move::Left => 3,
+            Move::Right => 4,
+        }
+    }
+
+    /// Renvoie le mouvement codé par un octet du protocole, ou *None* s'il est invalide
+    ///
+    /// # Arguments
+    ///
+    /// `id` - Octet codant le mouvement
+    pub fn from_id(id: u8) -> Option<Move> {
+        match id {
+            1 => Some(Move::Up),
+            2 => Some(Move::Down),
+            3 => Some(Move::Left),
+            4 => Some(Move::Right),
+            _ => None,
+        }
+    }
+}
+
+/// [4] Mouvement envoyé par un joueur : `[mouvement]`
+impl Message for Move {
+    const MSG: Msg = Msg::Move;
+
+    fn encode(&self, out: &mut Vec<u8>) {
+        out.push(self.to_id());
+    }
+
+    fn decode(content: &[u8]) -> Result<Self, ProtocolError> {
+        let mut reader = Reader::new(Self::MSG, content);
+        let id = reader.u8()?;
+        let player_move = Move::from_id(id).ok_or_else(|| reader.invalid("mouvement", id))?;
+        reader.finish()?;
+        Ok(player_move)
+    }
+}
+
+/// [9] Négociation de version, premier message du client puis réponse du serveur :
+/// `[version u16, fonctionnalités u32]`
+#[derive(Clone, PartialEq, Debug)]
+pub struct Version {
+    /// Version du protocole
+    pub version: u16,
+    /// Fonctionnalités facultatives (proposées par le client, retenues par le serveur)
+    pub features: u32,
+}
+
+impl Message for Version {
+    const MSG: Msg = Msg::Version;
+
+    fn encode(&self, out: &mut Vec<u8>) {
+        out.extend_from_slice(&self.version.to_le_bytes());
+        out.extend_from_slice(&self.features.to_le_bytes());
+    }
+
+    fn decode(content: &[u8]) -> Result<Self, ProtocolError> {
+        let mut reader = Reader::new(Self::MSG, content);
+        let version = Version { version: reader.u16()?, features: reader.u32()? };
+        reader.finish()?;
+        Ok(version)
+    }
+}
+
+/// [10] Refus du client par le serveur, avec une raison lisible (UTF-8)
+#[derive(Clone, PartialEq, Debug)]
+pub struct Reject {
+    /// Raison du refus
+    pub reason: String,
+}
+
+impl Message for Reject {
+    const MSG: Msg = Msg::Reject;
+
+    fn encode(&self, out: &mut Vec<u8>) {
+        out.extend_from_slice(self.reason.as_bytes());
+    }
+
+    fn decode(content: &[u8]) -> Result<Self, ProtocolError> {
+        Ok(Reject { reason: String::from_utf8_lossy(content).to_string() })
+    }
+}
+
+/// [8] Présentation du client avant sa demande de connexion : pseudo (UTF-8)
+#[derive(Clone, PartialEq, Debug)]
+pub struct Hello {
+    /// Pseudo choisi par le joueur, tel qu'envoyé
+    pub nickname: String,
+}
+
+impl Message for Hello {
+    const MSG: Msg = Msg::Hello;
+
+    fn encode(&self, out: &mut Vec<u8>) {
+        out.extend_from_slice(self.nickname.as_bytes());
+    }
+
+    fn decode(content: &[u8]) -> Result<Self, ProtocolError> {
+        Ok(Hello { nickname: String::from_utf8_lossy(content).to_string() })
+    }
+}
+
+/// [6] Demande de connexion d'un client
+#[derive(Clone, PartialEq, Debug)]
+pub enum Join {
+    /// `[0]` Nouveau joueur
+    New,
+    /// `[1, jeton u64]` Reconnexion d'un joueur avec son jeton de session
+    Reconnect(u64),
+    /// `[2, salon u32]` Spectateur d'un salon (0 : la partie la plus récente)
+    Spectate(u32),
+}
+
+impl Message for Join {
+    const MSG: Msg = Msg::Join;
+
+    fn encode(&self, out: &mut Vec<u8>) {
+        match self {
+            Join::New => out.push(0),
+            Join::Reconnect(token) => {
+                out.push(1);
+                out.extend_from_slice(&token.to_le_bytes());
+            }
+            Join::Spectate(room_id) => {
+                out.push(2);
+                out.extend_from_slice(&room_id.to_le_bytes());
+            }
+        }
+    }
+
+    fn decode(content: &[u8]) -> Result<Self, ProtocolError> {
+        let mut reader = Reader::new(Self::MSG, content);
+        let join = match reader.u8()? {
+            0 => Join::New,
+            1 => Join::Reconnect(reader.u64()?),
+            2 => Join::Spectate(reader.u32()?),
+            kind => return Err(reader.invalid("type de demande", kind)),
+        };
+        reader.finish()?;
+        Ok(join)
+    }
+}
+
+/// [7] Demande des premiers joueurs du classement, à la place d'une demande de connexion : `[n]`
+#[derive(Clone, PartialEq, Debug)]
+pub struct LeaderboardRequest {
+    /// Nombre de joueurs demandés
+    pub n: u8,
+}
+
+impl Message for LeaderboardRequest {
+    const MSG: Msg = Msg::Leaderboard;
+
+    fn encode(&self, out: &mut Vec<u8>) {
+        out.push(self.n);
+    }
+
+    fn decode(content: &[u8]) -> Result<Self, ProtocolError> {
+        let mut reader = Reader::new(Self::MSG, content);
+        let request = LeaderboardRequest { n: reader.u8()? };
+        reader.finish()?;
+        Ok(request)
+    }
+}
+
+/// Statistiques d'un joueur du classement
+#[derive(Clone, PartialEq, Debug)]
+pub struct LeaderboardEntry {
+    /// Identité du joueur (pseudo ou adresse IP)
+    pub identity: String,
+    /// Nombre de parties gagnées
+    pub wins: u32,
+    /// Nombre de parties perdues
+    pub losses: u32,
+    /// Nombre de serpents tués
+    pub kills: u32,
+    /// Longueur maximale atteinte en blocs
+    pub max_length: u32,
+}
+
+/// [7] Réponse du serveur à une demande du classement : nombre de joueurs, puis pour chacun la longueur
+/// de son identité, son identité (UTF-8), ses victoires, défaites, serpents tués et longueur maximale (u32)
+#[derive(Clone, PartialEq, Debug)]
+pub struct Leaderboard {
+    /// Premiers joueurs, dans l'ordre du classement
+    pub entries: Vec<LeaderboardEntry>,
+}
+
+impl Message for Leaderboard {
+    const MSG: Msg = Msg::Leaderboard;
+
+    fn encode(&self, out: &mut Vec<u8>) {
+        out.push(self.entries.len() as u8);
+        for entry in &self.entries {
+            put_string(out, &entry.identity);
+            for value in [entry.wins, entry.losses, entry.kills, entry.max_length].iter() {
+                out.extend_from_slice(&value.to_le_bytes());
+            }
+        }
+    }
+
+    fn decode(content: &[u8]) -> Result<Self, ProtocolError> {
+        let mut reader = Reader::new(Self::MSG, content);
+        let n = reader.u8()?;
+        let mut entries = vec![];
+        for _i in 0..n {
+            entries.push(LeaderboardEntry {
+                identity: reader.string()?,
+                wins: reader.u32()?,
+                losses: reader.u32()?,
+                kills: reader.u32()?,
+                max_length: reader.u32()?,
+            });
+        }
+        reader.finish()?;
+        Ok(Leaderboard { entries })
+    }
+}
+
+/// [0] Identifiant attribué au joueur (0 pour un spectateur) et son jeton de session : `[identifiant, jeton u64]`
+#[derive(Clone, PartialEq, Debug)]
+pub struct PlayerId {
+    /// Identifiant du joueur
+    pub player_id: u8,
+    /// Jeton de session permettant de se reconnecter à la partie
+    pub token: u64,
+}
+
+impl Message for PlayerId {
+    const MSG: Msg = Msg::PlayerId;
+
+    fn encode(&self, out: &mut Vec<u8>) {
+        out.push(self.player_id);
+        out.extend_from_slice(&self.token.to_le_bytes());
+    }
+
+    fn decode(content: &[u8]) -> Result<Self, ProtocolError> {
+        let mut reader = Reader::new(Self::MSG, content);
+        let player_id = PlayerId { player_id: reader.u8()?, token: reader.u64()? };
+        reader.finish()?;
+        Ok(player_id)
+    }
+}
+
+/// [5] État de la file d'attente d'un joueur en attente d'une partie :
+/// `[position, en attente, min, max, temps restant u16 (0xFFFF : pas de compte à rebours)]`
+#[derive(Clone, PartialEq, Debug)]
+pub struct QueueStatus {
+    /// Position du joueur dans la file (à partir de 1)
+    pub position: u8,
+    /// Nombre de joueurs en attente dans le salon
+    pub n_waiting: u8,
+    /// Nombre minimal de joueurs d'une partie
+    pub min_players: u8,
+    /// Nombre maximal de joueurs d'une partie
+    pub max_players: u8,
+    /// Option du temps restant en secondes avant le début de la partie
+    pub time_left: Option<u16>,
+}
+
+impl Message for QueueStatus {
+    const MSG: Msg = Msg::Queue;
+
+    fn encode(&self, out: &mut Vec<u8>) {
+        out.extend_from_slice(&[self.position, self.n_waiting, self.min_players, self.max_players]);
+        out.extend_from_slice(&self.time_left.unwrap_or(u16::MAX).to_le_bytes());
+    }
+
+    fn decode(content: &[u8]) -> Result<Self, ProtocolError> {
+        let mut reader = Reader::new(Self::MSG, content);
+        let mut status = QueueStatus {
+            position: reader.u8()?,
+            n_waiting: reader.u8()?,
+            min_players: reader.u8()?,
+            max_players: reader.u8()?,
+            time_left: None,
+        };
+        let time_left = reader.u16()?;
+        if time_left != u16::MAX {
+            status.time_left = Some(time_left);
+        }
+        reader.finish()?;
+        Ok(status)
+    }
+}
+
+/// Paramètres d'un joueur au début d'une partie
+#[derive(Clone, PartialEq, Debug)]
+pub struct PlayerParams {
+    /// Identifiant du joueur
+    pub id: u8,
+    /// Couleur du serpent (RGB)
+    pub color: (u8, u8, u8),
+    /// Coordonnées initiales du serpent
+    pub x: i16,
+    pub y: i16,
+    /// Direction initiale du serpent
+    pub direction: Move,
+    /// Pseudo du joueur
+    pub name: String,
+}
+
+/// [1] Paramètres de la partie : largeur, hauteur, taille des blocs (u16), vitesse initiale, arène torique,
+/// nombre de joueurs, puis pour chacun `[identifiant, r, g, b, x u16, y u16, direction]`,
+/// puis les murs intérieurs (nombre u16, puis segments horizontaux x, y, longueur en u16)
+/// et enfin les pseudos des joueurs (longueur, puis UTF-8), dans l'ordre des joueurs
+#[derive(Clone, PartialEq, Debug)]
+pub struct GameParams {
+    /// Dimensions de la map en blocs
+    pub width: u16,
+    pub height: u16,
+    /// Taille d'un bloc en pixels
+    pub block_size: u16,
+    /// Vitesse initiale
+    pub initial_speed: u8,
+    /// Booléen codant si l'arène est torique
+    pub wrap: bool,
+    /// Paramètres des joueurs
+    pub players: Vec<PlayerParams>,
+    /// Murs intérieurs en segments horizontaux (x, y, longueur)
+    pub walls: Vec<(u16, u16, u16)>,
+}
+
+impl Message for GameParams {
+    const MSG: Msg = Msg::GameParams;
+
+    fn encode(&self, out: &mut Vec<u8>) {
+        out.extend_from_slice(&self.width.to_le_bytes());
+        out.extend_from_slice(&self.height.to_le_bytes());
+        out.extend_from_slice(&self.block_size.to_le_bytes());
+        out.push(self.initial_speed);
+        out.push(self.wrap as u8);
+        out.push(self.players.len() as u8);
+        for player in &self.players {
+            out.extend_from_slice(&[player.id, player.color.0, player.color.1, player.color.2]);
+            out.extend_from_slice(&player.x.to_le_bytes());
+            out.extend_from_slice(&player.y.to_le_bytes());
+            out.push(player.direction.to_id());
+        }
+
+        out.extend_from_slice(&(self.walls.len() as u16).to_le_bytes());
+        for (x, y, len) in &self.walls {
+            out.extend_from_slice(&x.to_le_bytes());
+            out.extend_from_slice(&y.to_le_bytes());
+            out.extend_from_slice(&len.to_le_bytes());
+        }
+
+        for player in &self.players {
+            put_string(out, &player.name);
+        }
+    }
+
+    fn decode(content: &[u8]) -> Result<Self, ProtocolError> {
+        let mut reader = Reader::new(Self::MSG, content);
+        let mut params = GameParams {
+            width: reader.u16()?,
+            height: reader.u16()?,
+            block_size: reader.u16()?,
+            initial_speed: reader.u8()?,
+            wrap: reader.bool("arène torique")?,
+            players: vec![],
+            walls: vec![],
+        };
+
+        let n_players = reader.u8()?;
+        for _i in 0..n_players {
+            let id = reader.u8()?;
+            let color = (reader.u8()?, reader.u8()?, reader.u8()?);
+            let (x, y) = (reader.i16()?, reader.i16()?);
+            let direction = reader.u8()?;
+            params.players.push(PlayerParams {
+                id,
+                color,
+                x,
+                y,
+                direction: Move::from_id(direction).ok_or_else(|| reader.invalid("direction", direction))?,
+                name: String::new(),
+            });
+        }
+
+        let n_walls = reader.u16()?;
+        for _i in 0..n_walls {
+            params.walls.push((reader.u16()?, reader.u16()?, reader.u16()?));
+        }
+
+        for player in params.players.iter_mut() {
+            player.name = reader.string()?;
+        }
+        reader.finish()?;
+        Ok(params)
+    }
+}
+
+/// Nombre de types de nourriture, identifiés de 1 à `N_FOOD_TYPES`
+pub const N_FOOD_TYPES: u8 = 3;
+
+/// Nourriture présente sur la map
+#[derive(Clone, PartialEq, Debug)]
+pub struct FoodState {
+    /// Type de nourriture (1 : pomme, 2 : mangue, 3 : invisibilité)
+    pub food_type: u8,
+    /// Coordonnées de la nourriture
+    pub x: i16,
+    pub y: i16,
+}
+
+/// Serpent encore en jeu
+#[derive(Clone, PartialEq, Debug)]
+pub struct SnakeState {
+    /// Identifiant du joueur
+    pub id: u8,
+    /// Booléen codant si le joueur a perdu
+    pub has_lost: bool,
+    /// Booléen codant si le serpent est invisible pour ses adversaires
+    pub invisible: bool,
+    /// Nombre d'unités dans l'estomac du serpent
+    pub stomach: u8,
+    /// Noeuds du serpent, de la tête à la queue ; vide si le serpent est caché au destinataire
+    pub nodes: Vec<(i16, i16)>,
+}
+
+/// Bit de l'état d'un serpent : le joueur a perdu
+const SNAKE_LOST: u8 = 1;
+/// Bit de l'état d'un serpent : le serpent est invisible
+const SNAKE_INVISIBLE: u8 = 1 << 1;
+
+/// [3] Frame d'un tick : nombre de nourritures, puis pour chacune `[type, x u16, y u16]`,
+/// puis nombre de serpents, puis pour chacun `[identifiant, état, estomac, nombre de noeuds u16]`
+/// suivi des noeuds `[x u16, y u16]`. L'état combine les bits `SNAKE_LOST` et `SNAKE_INVISIBLE`.
+#[derive(Clone, PartialEq, Debug)]
+pub struct Frame {
+    /// Nourritures présentes sur la map
+    pub food: Vec<FoodState>,
+    /// Serpents encore en jeu
+    pub snakes: Vec<SnakeState>,
+}
+
+impl Message for Frame {
+    const MSG: Msg = Msg::Frame;
+
+    fn encode(&self, out: &mut Vec<u8>) {
+        out.push(self.food.len() as u8);
+        for food in &self.food {
+            out.push(food.food_type);
+            out.extend_from_slice(&food.x.to_le_bytes());
+            out.extend_from_slice(&food.y.to_le_bytes());
+        }
+
+        out.push(self.snakes.len() as u8);
+        for snake in &self.snakes {
+            let state = if snake.has_lost { SNAKE_LOST } else { 0 } | if snake.invisible { SNAKE_INVISIBLE } else { 0 };
+            out.extend_from_slice(&[snake.id, state, snake.stomach]);
+            // Un long serpent peut avoir plus de 255 noeuds
+            out.extend_from_slice(&(snake.nodes.len() as u16).to_le_bytes());
+            for (x, y) in &snake.nodes {
+                out.extend_from_slice(&x.to_le_bytes());
+                out.extend_from_slice(&y.to_le_bytes());
+            }
+        }
+    }
+
+    fn decode(content: &[u8]) -> Result<Self, ProtocolError> {
+        let mut reader = Reader::new(Self::MSG, content);
+        let mut frame = Frame { food: vec![], snakes: vec![] };
+
+        let n_food = reader.u8()?;
+        for _i in 0..n_food {
+            let food_type = reader.u8()?;
+            if food_type == 0 || food_type > N_FOOD_TYPES {
+                return Err(reader.invalid("type de nourriture", food_type));
+            }
+            frame.food.push(FoodState { food_type, x: reader.i16()?, y: reader.i16()? });
+        }
+
+        let n_snakes = reader.u8()?;
+        for _i in 0..n_snakes {
+            let id = reader.u8()?;
+            let state = reader.u8()?;
+            if state & !(SNAKE_LOST | SNAKE_INVISIBLE) != 0 {
+                return Err(reader.invalid("état", state));
+            }
+            let mut snake = SnakeState {
+                id,
+                has_lost: state & SNAKE_LOST != 0,
+                invisible: state & SNAKE_INVISIBLE != 0,
+                stomach: reader.u8()?,
+                nodes: vec![],
+            };
+            let n_nodes = reader.u16()?;
+            for _j in 0..n_nodes {
+                snake.nodes.push((reader.i16()?, reader.i16()?));
+            }
+            frame.snakes.push(snake);
+        }
+        reader.finish()?;
+        Ok(frame)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::collection::vec;
+    use proptest::prelude::*;
+
+    /// Vérifie qu'un message décodé à partir de son paquet est identique au message d'origine
+    fn round_trip<M: Message + PartialEq + std::fmt::Debug>(message: M) {
+        let packet = message.packet().unwrap();
+        assert_eq!(crate::parse_header(&[packet[0], packet[1], packet[2]]), Ok((M::MSG, packet.len() - crate::HEADER_LEN)));
+        assert_eq!(M::decode(&packet[crate::HEADER_LEN..]), Ok(message));
+    }
+
+    fn game_params() -> GameParams {
+        GameParams {
+            width: 16,
+            height: 16,
+            block_size: 10,
+            initial_speed: 1,
+            wrap: false,
+            players: vec![PlayerParams { id: 1, color: (1, 2, 3), x: 4, y: 5, direction: Move::Down, name: "Léa".to_string() }],
+            walls: vec![(3, 3, 3), (12, 3, 2)],
+        }
+    }
+
+    #[test]
+    fn test_round_trips() {
+        for &player_move in [Move::Up, Move::Down, Move::Left, Move::Right].iter() {
+            round_trip(player_move);
+        }
+        round_trip(Version { version: 1, features: 0b101 });
+        round_trip(Reject { reason: "version différente".to_string() });
+        round_trip(Hello { nickname: "Zoé".to_string() });
+        round_trip(Join::New);
+        round_trip(Join::Reconnect(0x0102030405060708));
+        round_trip(Join::Spectate(3));
+        round_trip(LeaderboardRequest { n: 10 });
+        round_trip(Leaderboard { entries: vec![] });
+        round_trip(Leaderboard {
+            entries: vec![LeaderboardEntry { identity: "1.2.3.4".to_string(), wins: 3, losses: 1, kills: 258, max_length: 40 }],
+        });
+        round_trip(PlayerId { player_id: 2, token: u64::MAX - 1 });
+        round_trip(QueueStatus { position: 2, n_waiting: 3, min_players: 2, max_players: 4, time_left: Some(300) });
+        round_trip(QueueStatus { position: 1, n_waiting: 1, min_players: 2, max_players: 2, time_left: None });
+        round_trip(game_params());
+        round_trip(Frame {
+            food: vec![FoodState { food_type: 1, x: 10, y: 20 }, FoodState { food_type: 2, x: 30, y: 40 }],
+            snakes: vec![
+                SnakeState { id: 1, has_lost: false, invisible: false, stomach: 10, nodes: vec![(10, 20), (10, 25)] },
+                SnakeState { id: 2, has_lost: true, invisible: false, stomach: 0, nodes: vec![(-1, 63), (0, 63)] },
+                SnakeState { id: 3, has_lost: false, invisible: true, stomach: 2, nodes: vec![] },
+            ],
+        });
+    }
+
+    #[test]
+    fn test_game_params_bytes() {
+        assert_eq!(game_params().packet().unwrap(), [
+            1, 37, 0,
+            16, 0, 16, 0, 10, 0, 1, 0, 1,
+            1, 1, 2, 3, 4, 0, 5, 0, 2,
+            2, 0, 3, 0, 3, 0, 3, 0, 12, 0, 3, 0, 2, 0,
+            4, b'L', 0xC3, 0xA9, b'a',
+        ]);
+    }
+
+    #[test]
+    fn test_game_params_missing_names() {
+        // Chaque joueur doit avoir un pseudo
+        let packet = game_params().packet().unwrap();
+        assert_eq!(GameParams::decode(&packet[crate::HEADER_LEN..packet.len() - 5]), Err(ProtocolError::Truncated(Msg::GameParams)));
+    }
+
+    #[test]
+    fn test_frame_bytes() {
+        let frame = Frame {
+            food: vec![FoodState { food_type: 1, x: 10, y: 20 }],
+            snakes: vec![SnakeState { id: 1, has_lost: false, invisible: false, stomach: 10, nodes: vec![(10, 20), (10, 20)] }],
+        };
+        assert_eq!(frame.packet().unwrap(), [3, 20, 0, 1, 1, 10, 0, 20, 0, 1, 1, 0, 10, 2, 0, 10, 0, 20, 0, 10, 0, 20, 0]);
+
+        // Serpent invisible caché au destinataire
+        let frame = Frame {
+            food: vec![],
+            snakes: vec![SnakeState { id: 2, has_lost: true, invisible: true, stomach: 0, nodes: vec![] }],
+        };
+        assert_eq!(frame.packet().unwrap(), [3, 7, 0, 0, 1, 2, 3, 0, 0, 0]);
+    }
+
+    #[test]
+    fn test_frame_too_long() {
+        // 16 384 coins de 4 octets : le contenu dépasse 65 535 octets
+        let frame = Frame {
+            food: vec![],
+            snakes: vec![SnakeState { id: 1, has_lost: false, invisible: false, stomach: 0, nodes: vec![(1, 2); 16384] }],
+        };
+        assert_eq!(frame.packet(), Err(ProtocolError::TooLong(Msg::Frame, 2 + 5 + 4 * 16384)));
+    }
+
+    #[test]
+    fn test_malformed() {
+        assert_eq!(Move::decode(&[5]), Err(ProtocolError::InvalidValue(Msg::Move, "mouvement", 5)));
+        assert_eq!(Move::decode(&[1, 1]), Err(ProtocolError::TrailingBytes(Msg::Move)));
+        assert_eq!(Move::decode(&[]), Err(ProtocolError::Truncated(Msg::Move)));
+        assert_eq!(Join::decode(&[3]), Err(ProtocolError::InvalidValue(Msg::Join, "type de demande", 3)));
+        assert_eq!(Join::decode(&[1, 8, 7]), Err(ProtocolError::Truncated(Msg::Join)));
+        assert_eq!(Version::decode(&[1, 0, 0]), Err(ProtocolError::Truncated(Msg::Version)));
+        assert_eq!(Leaderboard::decode(&[1, 7, b'1']), Err(ProtocolError::Truncated(Msg::Leaderboard)));
+        assert_eq!(Frame::decode(&[0, 1, 1, 0, 10, 2, 0, 10, 0]), Err(ProtocolError::Truncated(Msg::Frame)));
+        assert_eq!(Frame::decode(&[0, 1, 1, 4, 10, 0, 0]), Err(ProtocolError::InvalidValue(Msg::Frame, "état", 4)));
+        assert_eq!(Frame::decode(&[0, 0, 0]), Err(ProtocolError::TrailingBytes(Msg::Frame)));
+        assert_eq!(Frame::decode(&[1, 4, 10, 0, 20, 0, 0]), Err(ProtocolError::InvalidValue(Msg::Frame, "type de nourriture", 4)));
+        assert_eq!(Frame::decode(&[1, 0, 10, 0, 20, 0, 0]), Err(ProtocolError::InvalidValue(Msg::Frame, "type de nourriture", 0)));
+        assert_eq!(GameParams::decode(&[16, 0, 16]), Err(ProtocolError::Truncated(Msg::GameParams)));
+        assert_eq!(Leaderboard::decode(&[0, 0]), Err(ProtocolError::TrailingBytes(Msg::Leaderboard)));
+    }
+
+    fn move_strategy() -> impl Strategy<Value = Move> {
+        prop_oneof![Just(Move::Up), Just(Move::Down), Just(Move::Left), Just(Move::Right)]
+    }
+
+    /// Frames aléatoires, avec de longs serpents (plus de 255 noeuds)
+    fn frame_strategy() -> impl Strategy<Value = Frame> {
+        let food = (1..=N_FOOD_TYPES, any::<i16>(), any::<i16>()).prop_map(|(food_type, x, y)| FoodState { food_type, x, y });
+        let snake = (any::<u8>(), any::<(bool, bool)>(), any::<u8>(), vec(any::<(i16, i16)>(), 0..300))
+            .prop_map(|(id, (has_lost, invisible), stomach, nodes)| SnakeState { id, has_lost, invisible, stomach, nodes });
+        (vec(food, 0..=255), vec(snake, 0..=8)).prop_map(|(food, snakes)| Frame { food, snakes })
+    }
+
+    fn game_params_strategy() -> impl Strategy<Value = GameParams> {
+        let player = (any::<u8>(), any::<(u8, u8, u8)>(), any::<i16>(), any::<i16>(), move_strategy(), "\\PC{0,16}")
+            .prop_map(|(id, color, x, y, direction, name)| PlayerParams { id, color, x, y, direction, name });
+        (any::<(u16, u16, u16, u8, bool)>(), vec(player, 0..=16), vec(any::<(u16, u16, u16)>(), 0..200))
+            .prop_map(|((width, height, block_size, initial_speed, wrap), players, walls)| GameParams {
+                width, height, block_size, initial_speed, wrap, players, walls,
+            })
+    }
+
+    fn leaderboard_strategy() -> impl Strategy<Value = Leaderboard> {
+        let entry = ("\\PC{0,40}", any::<(u32, u32, u32, u32)>())
+            .prop_map(|(identity, (wins, losses, kills, max_length))| LeaderboardEntry { identity, wins, losses, kills, max_length });
+        vec(entry, 0..=20).prop_map(|entries| Leaderboard { entries })
+    }
+
+    /// Décode un contenu quelconque avec le décodeur de son type de message
+    fn decode_any(msg: Msg, content: &[u8]) {
+        let _ = match msg {
+            Msg::PlayerId => PlayerId::decode(content).map(drop),
+            Msg::GameParams => GameParams::decode(content).map(drop),
+            Msg::GameStart => Ok(()),
+            Msg::Frame => Frame::decode(content).map(drop),
+            Msg::Move => Move::decode(content).map(drop),
+            Msg::Queue => QueueStatus::decode(content).map(drop),
+            Msg::Join => Join::decode(content).map(drop),
+            Msg::Leaderboard => Leaderboard::decode(content).map(drop).and(LeaderboardRequest::decode(content).map(drop)),
+            Msg::Hello => Hello::decode(content).map(drop),
+            Msg::Version => Version::decode(content).map(drop),
+            Msg::Reject => Reject::decode(content).map(drop),
+        };
+    }
+
+    proptest! {
+        #[test]
+        fn prop_frame_round_trip(frame in frame_strategy()) {
+            round_trip(frame);
+        }
+
+        #[test]
+        fn prop_game_params_round_trip(params in game_params_strategy()) {
+            round_trip(params);
+        }
+
+        #[test]
+        fn prop_leaderboard_round_trip(leaderboard in leaderboard_strategy()) {
+            round_trip(leaderboard);
+        }
+
+        #[test]
+        fn prop_small_messages_round_trip(
+            player_move in move_strategy(),
+            (version, features, token, room_id) in any::<(u16, u32, u64, u32)>(),
+            (position, n_waiting, min_players, max_players, time_left) in any::<(u8, u8, u8, u8, Option<u16>)>(),
+            nickname in "\\PC*",
+        ) {
+            round_trip(player_move);
+            round_trip(Version { version, features });
+            round_trip(PlayerId { player_id: position, token });
+            round_trip(Join::Reconnect(token));
+            round_trip(Join::Spectate(room_id));
+            // 0xFFFF code l'absence de compte à rebours
+            round_trip(QueueStatus { position, n_waiting, min_players, max_players, time_left: time_left.filter(|&t| t != u16::MAX) });
+            round_trip(Hello { nickname: nickname.clone() });
+            round_trip(Reject { reason: nickname });
+        }
+
+        /// Une frame tronquée est toujours refusée, sans paniquer
+        #[test]
+        fn prop_truncated_frame(frame in frame_strategy(), cut in any::<prop::sample::Index>()) {
+            let packet = frame.packet().unwrap();
+            let content = &packet[crate::HEADER_LEN..];
+            let cut = cut.index(content.len());
+            prop_assert_eq!(Frame::decode(&content[..cut]), Err(ProtocolError::Truncated(Msg::Frame)));
+        }
+
+        /// Aucun contenu ne fait paniquer les décodeurs
+        #[test]
+        fn prop_decode_never_panics(id in 0..=10u8, content in vec(any::<u8>(), 0..512)) {
+            decode_any(crate::id_to_msg(id).unwrap(), &content);
+        }
+    }
+}
@@ -0,0 +1,244 @@
+//! Format des fichiers de replay, écrits par le serveur et lus par le client.
+//!
+//! Un fichier de replay commence par `MAGIC`, `VERSION` (1 octet), l'identifiant du salon (u32),
+//! la graine de la partie (u64) et la durée d'un tick en millisecondes (u32), puis contient une suite d'enregistrements
+//! `[type, longueur u16, contenu]`, comme les messages du protocole. Les entiers sont en little-endian.
+//! Les mouvements et départs d'un tick précèdent la frame de ce tick.
+
+use std::fmt;
+use std::io::{self, Write};
+
+use crate::MAX_CONTENT_LEN;
+
+/// Signature en tête d'un fichier de replay
+pub const MAGIC: &[u8; 8] = b"SNECCRPL";
+
+/// Version du format de replay
+pub const VERSION: u8 = 4;
+
+/// Taille de l'en-tête : signature, version, salon (u32), graine (u64), durée d'un tick (u32)
+pub const HEADER_LEN: usize = 8 + 1 + 4 + 8 + 4;
+
+/// Types d'enregistrements d'un replay
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Record {
+    /// Paramètres de la partie et liste des joueurs : contenu du message GameParams
+    Params = 1,
+    /// Mouvement reçu d'un joueur : `[identifiant du joueur, mouvement]`
+    Input = 2,
+    /// Joueur éliminé après une déconnexion sans reconnexion : `[identifiant du joueur]`
+    Quit = 3,
+    /// Frame d'un tick : contenu du message Frame
+    Frame = 4,
+}
+
+/// Implémentation d'un type d'enregistrement
+impl Record {
+    /// Renvoie le type d'enregistrement codé par un octet, ou *None* s'il est inconnu
+    ///
+    /// # Arguments
+    ///
+    /// `id` - Octet codant le type
+    pub fn from_id(id: u8) -> Option<Record> {
+        match id {
+            1 => Some(Record::Params),
+            2 => Some(Record::Input),
+            3 => Some(Record::Quit),
+            4 => Some(Record::Frame),
+            _ => None,
+        }
+    }
+}
+
+/// En-tête d'un fichier de replay (après la signature et la version)
+#[derive(Clone, PartialEq, Debug)]
+pub struct Header {
+    /// Identifiant du salon de la partie
+    pub room_id: u32,
+    /// Graine du générateur aléatoire de la partie
+    pub seed: u64,
+    /// Durée d'un tick en millisecondes
+    pub period_ms: u32,
+}
+
+/// Contenu d'un fichier de replay déconstruit
+pub struct ReplayFile<'a> {
+    /// En-tête du replay
+    pub header: Header,
+    /// Enregistrements, dans l'ordre du fichier (les types inconnus sont ignorés)
+    pub records: Vec<(Record, &'a [u8])>,
+}
+
+/// Erreur de lecture d'un fichier de replay
+#[derive(Clone, PartialEq, Debug)]
+pub enum ReplayError {
+    /// Le fichier ne commence pas par la signature d'un replay
+    NotAReplay,
+    /// Version du format non prise en charge
+    Version(u8),
+    /// Le fichier s'arrête au milieu d'un enregistrement
+    Truncated,
+}
+
+impl fmt::Display for ReplayError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ReplayError::NotAReplay => write!(f, "ce fichier n'est pas un replay"),
+            ReplayError::Version(version) => write!(f, "version de replay {} non prise en charge (attendue : {})", version, VERSION),
+            ReplayError::Truncated => write!(f, "replay tronqué"),
+        }
+    }
+}
+
+impl std::error::Error for ReplayError {}
+
+/// Une erreur de lecture d'un replay devient une erreur d'entrée/sortie `InvalidData`
+impl From<ReplayError> for io::Error {
+    fn from(error: ReplayError) -> Self {
+        io::Error::new(io::ErrorKind::InvalidData, error)
+    }
+}
+
+/// Écrit la signature, la version et l'en-tête d'un replay
+///
+/// # Arguments
+///
+/// `out` - Référence mutable vers le flux de sortie
+/// `header` - En-tête du replay
+pub fn write_header<W: Write>(out: &mut W, header: &Header) -> io::Result<()> {
+    out.write_all(MAGIC)?;
+    out.write_all(&[VERSION])?;
+    out.write_all(&header.room_id.to_le_bytes())?;
+    out.write_all(&header.seed.to_le_bytes())?;
+    out.write_all(&header.period_ms.to_le_bytes())
+}
+
+/// Écrit un enregistrement `[type, longueur u16, contenu]`.
+/// Renvoie une erreur `InvalidInput`, sans rien écrire, si le contenu dépasse `MAX_CONTENT_LEN` octets.
+///
+/// # Arguments
+///
+/// `out` - Référence mutable vers le flux de sortie
+/// `record` - Type de l'enregistrement
+/// `payload` - Contenu de l'enregistrement
+pub fn write_record<W: Write>(out: &mut W, record: Record, payload: &[u8]) -> io::Result<()> {
+    if payload.len() > MAX_CONTENT_LEN {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, format!("enregistrement {:?} trop long ({} octets)", record, payload.len())));
+    }
+    out.write_all(&[record as u8])?;
+    out.write_all(&(payload.len() as u16).to_le_bytes())?;
+    out.write_all(payload)
+}
+
+/// Déconstruit le contenu d'un fichier de replay : en-tête puis enregistrements.
+/// Renvoie une erreur si la signature ou la version ne correspondent pas, ou si le fichier est tronqué.
+///
+/// # Arguments
+///
+/// `content` - Slice vers le contenu du fichier
+pub fn parse(content: &[u8]) -> Result<ReplayFile<'_>, ReplayError> {
+    if content.len() < MAGIC.len() + 1 || &content[..MAGIC.len()] != MAGIC {
+        return Err(ReplayError::NotAReplay);
+    }
+    if content[MAGIC.len()] != VERSION {
+        return Err(ReplayError::Version(content[MAGIC.len()]));
+    }
+    if content.len() < HEADER_LEN {
+        return Err(ReplayError::Truncated);
+    }
+
+    let header = Header {
+        room_id: u32::from_le_bytes([content[9], content[10], content[11], content[12]]),
+        seed: u64::from_le_bytes([
+            content[13], content[14], content[15], content[16],
+            content[17], content[18], content[19], content[20],
+        ]),
+        period_ms: u32::from_le_bytes([content[21], content[22], content[23], content[24]]),
+    };
+
+    let mut records = vec![];
+    let mut index = HEADER_LEN;
+    while index < content.len() {
+        if index + 3 > content.len() {
+            return Err(ReplayError::Truncated);
+        }
+        let id = content[index];
+        let len = u16::from_le_bytes([content[index + 1], content[index + 2]]) as usize;
+        index += 3;
+        if index + len > content.len() {
+            return Err(ReplayError::Truncated);
+        }
+        if let Some(record) = Record::from_id(id) {
+            records.push((record, &content[index..index + len]));
+        }
+        index += len;
+    }
+    Ok(ReplayFile { header, records })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Renvoie un replay contenant un enregistrement de chaque type
+    fn sample() -> Vec<u8> {
+        let mut out = vec![];
+        write_header(&mut out, &Header { room_id: 7, seed: 0x0102, period_ms: 50 }).unwrap();
+        write_record(&mut out, Record::Params, &[10, 11]).unwrap();
+        write_record(&mut out, Record::Input, &[2, 3]).unwrap();
+        write_record(&mut out, Record::Quit, &[3]).unwrap();
+        write_record(&mut out, Record::Frame, &[42]).unwrap();
+        out
+    }
+
+    #[test]
+    fn test_write() {
+        assert_eq!(sample(), [
+            &MAGIC[..],
+            &[VERSION, 7, 0, 0, 0, 2, 1, 0, 0, 0, 0, 0, 0, 50, 0, 0, 0],
+            &[1, 2, 0, 10, 11],
+            &[2, 2, 0, 2, 3],
+            &[3, 1, 0, 3],
+            &[4, 1, 0, 42],
+        ].concat());
+    }
+
+    #[test]
+    fn test_write_too_long() {
+        let mut out = vec![];
+        assert!(write_record(&mut out, Record::Frame, &[0; MAX_CONTENT_LEN]).is_ok());
+        out.clear();
+        let error = write_record(&mut out, Record::Frame, &[0; MAX_CONTENT_LEN + 1]).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidInput);
+        assert!(out.is_empty());
+    }
+
+    #[test]
+    fn test_parse() {
+        let content = sample();
+        let replay = parse(&content).unwrap();
+        assert_eq!(replay.header, Header { room_id: 7, seed: 0x0102, period_ms: 50 });
+        assert_eq!(replay.records, vec![
+            (Record::Params, &[10, 11][..]),
+            (Record::Input, &[2, 3][..]),
+            (Record::Quit, &[3][..]),
+            (Record::Frame, &[42][..]),
+        ]);
+
+        // Un enregistrement de type inconnu est ignoré
+        let content = [&content[..], &[9, 1, 0, 0]].concat();
+        assert_eq!(parse(&content).unwrap().records.len(), 4);
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        let content = sample();
+        assert_eq!(parse(b"SNECC").err(), Some(ReplayError::NotAReplay));
+        assert_eq!(parse(&[b"NOTARPL!", &content[8..]].concat()).err(), Some(ReplayError::NotAReplay));
+        assert_eq!(parse(&[&content[..8], &[3], &content[9..]].concat()).err(), Some(ReplayError::Version(3)));
+        assert_eq!(parse(&content[..HEADER_LEN - 1]).err(), Some(ReplayError::Truncated));
+        assert_eq!(parse(&content[..content.len() - 1]).err(), Some(ReplayError::Truncated));
+        assert_eq!(parse(&content[..content.len() - 3]).err(), Some(ReplayError::Truncated));
+        assert_eq!(parse(&content[..HEADER_LEN]).unwrap().records.len(), 0);
+    }
+}